@@ -0,0 +1,193 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{cms, der, keys, resign::ResignOptions, resources::{self, AsResources}, tree_util::{self, children, tag}, State};
+
+const EXAMPLE_CUSTOMER: u32 = 64496;
+const EXAMPLE_PROVIDERS: [u32; 2] = [64497, 64498];
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AspaContent{
+    pub customer_asid: u32,
+    pub providers: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl State{
+    /// Returns customer and provider ASes of an ASPA as JSON: `{customerAsid, providers}`.
+    /// Providers of older drafts (SEQUENCE with afiLimit) are read as their AS.
+    #[wasm_bindgen]
+    pub fn aspa_get(&self) -> Result<String, String>{
+        let econtent = self.signed_payload(cms::OID_CT_ASPA, "ASPA")?;
+        let p = tree_util::payload(&self.tree, econtent, &self.overrides).ok_or("eContent is not valid ASN.1")?;
+        let t = &p.tree;
+
+        let c = children(t, p.root);
+        let c = if c.first().map(|v| tag(t, *v) == 0xA0).unwrap_or(false) { &c[1..] } else { c };
+        if c.len() != 2{
+            return Err("ASProviderAttestation must contain customerASID and providers".to_string());
+        }
+
+        let asid = |id: usize| der::parse_uint(&t.tokens[&id].data).filter(|a| *a <= u32::MAX as u128).map(|a| a as u32);
+        let customer_asid = asid(c[0]).ok_or("Invalid customerASID")?;
+
+        let mut providers = vec![];
+        for provider in children(t, c[1]){
+            let id = match tag(t, *provider){
+                0x30 => children(t, *provider).first().cloned(),
+                _ => Some(*provider),
+            };
+            providers.push(id.and_then(asid).ok_or("Invalid provider AS")?);
+        }
+
+        let content = AspaContent{
+            customer_asid,
+            providers,
+        };
+        Ok(serde_json::to_string(&content).unwrap())
+    }
+
+    /// Rebuilds the ASPA payload from JSON in the format of `aspa_get`. With `sort` providers are
+    /// sorted and duplicates removed, otherwise they are written as given.
+    /// With `update_ee` the EE certificate gets the customer AS as its only resource.
+    /// Digest and signatures are not updated, see `resign`.
+    #[wasm_bindgen]
+    pub fn aspa_set(&mut self, content: String, sort: bool, update_ee: bool) -> Result<(), String>{
+        let mut content: AspaContent = serde_json::from_str(&content).map_err(|e| format!("Invalid ASPA: {}", e))?;
+        let econtent = self.signed_payload(cms::OID_CT_ASPA, "ASPA")?;
+
+        if sort{
+            content.providers.sort();
+            content.providers.dedup();
+        }
+
+        let providers = content.providers.iter().flat_map(|p| der::tlv(0x02, &der::encode_uint(*p as u128))).collect::<Vec<u8>>();
+
+        // An explicit version is kept as it is
+        let mut aspa = vec![];
+        if let Some(version) = self.payload_version(econtent){
+            aspa.extend(version);
+        }
+        aspa.extend(der::tlv(0x02, &der::encode_uint(content.customer_asid as u128)));
+        aspa.extend(der::tlv(0x30, &providers));
+        tree_util::set_string_payload(&mut self.tree, econtent, &der::tlv(0x30, &aspa))?;
+
+        if update_ee{
+            let customer = content.customer_asid as u128;
+            let asn = AsResources{
+                inherit: false,
+                ranges: vec![(customer, customer)],
+            };
+            self.set_cert_extension(resources::OID_AS_RESOURCES, true, Some(&resources::encode_as_resources(&asn)))?;
+            self.set_cert_extension(resources::OID_IP_RESOURCES, true, None)?;
+        }
+
+        self.overrides.retain_existing(&self.tree);
+        self.label_aspa();
+        Ok(())
+    }
+}
+
+impl State{
+    /// Example ASPA with version 1 built on the ROA example and signed with random keys, so it does
+    /// not depend on the ASPA support of the object generator.
+    pub(crate) fn example_aspa() -> Result<State, String>{
+        let mut state = State::example_signed_object(cms::OID_CT_ASPA)?;
+        let econtent = state.signed_payload(cms::OID_CT_ASPA, "ASPA")?;
+
+        // aspa_set keeps the version of the payload
+        let mut aspa = der::tlv(0xA0, &der::tlv(0x02, &[1]));
+        aspa.extend(der::tlv(0x02, &der::encode_uint(EXAMPLE_CUSTOMER as u128)));
+        aspa.extend(der::tlv(0x30, &[]));
+        tree_util::set_string_payload(&mut state.tree, econtent, &der::tlv(0x30, &aspa))?;
+
+        let content = AspaContent{
+            customer_asid: EXAMPLE_CUSTOMER,
+            providers: EXAMPLE_PROVIDERS.to_vec(),
+        };
+        state.aspa_set(serde_json::to_string(&content).unwrap(), true, true)?;
+
+        let ee_key = keys::load_key(None)?;
+        state.resign_with(&ResignOptions::default(), &ee_key, Some(&keys::load_key(None)?))?;
+        Ok(state)
+    }
+
+    /// Labels the fields of an ASPA payload, the parser does not know this type. The eContent is
+    /// expanded first if needed, its encoding stays the same.
+    pub(crate) fn label_aspa(&mut self){
+        let root = match self.expanded_payload(cms::OID_CT_ASPA, "ASPA"){
+            Some(r) => r,
+            None => return,
+        };
+        let mut labels = vec![(root, "ASProviderAttestation")];
+        let c = children(&self.tree, root).to_vec();
+        let fields = if c.first().map(|v| tag(&self.tree, *v) == 0xA0).unwrap_or(false){
+            labels.push((c[0], "version"));
+            &c[1..]
+        }
+        else{
+            &c[..]
+        };
+
+        for (id, name) in fields.iter().zip(["customerASID", "providers"]){
+            labels.push((*id, name));
+        }
+        for provider in fields.get(1).map(|p| children(&self.tree, *p).to_vec()).unwrap_or_default(){
+            labels.push((provider, "providerASID"));
+        }
+        self.set_labels(labels);
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn content(state: &State) -> AspaContent{
+        serde_json::from_str(&state.aspa_get().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn example(){
+        let state = State::load_example("aspa").unwrap();
+        let aspa = content(&state);
+        assert_eq!((aspa.customer_asid, aspa.providers), (EXAMPLE_CUSTOMER, EXAMPLE_PROVIDERS.to_vec()));
+        assert!(state.payload_version(state.signed_payload(cms::OID_CT_ASPA, "ASPA").unwrap()).is_some());
+    }
+
+    #[test]
+    fn set_get(){
+        let mut state = State::load_example("aspa").unwrap();
+        let providers = r#"{"customerAsid": 64500, "providers": [65002, 65001, 65002]}"#;
+
+        state.aspa_set(providers.to_string(), false, false).unwrap();
+        assert_eq!(content(&state).providers, [65002, 65001, 65002]);
+
+        state.aspa_set(providers.to_string(), true, false).unwrap();
+        let aspa = content(&state);
+        assert_eq!((aspa.customer_asid, aspa.providers), (64500, vec![65001, 65002]));
+
+        let encoded = state.encode();
+        state.aspa_set(state.aspa_get().unwrap(), false, false).unwrap();
+        assert_eq!(state.encode(), encoded);
+    }
+
+    #[test]
+    fn labels(){
+        let state = State::load_example("aspa").unwrap();
+        let labelled = |label: &str| state.tree.tokens.values().filter(|t| t.info == label).count();
+        assert_eq!(labelled("providerASID"), EXAMPLE_PROVIDERS.len());
+
+        // Each provider is labelled, only the single fields are in the label map
+        assert!(state.tree.labels.contains_key("customerASID"));
+        assert!(!state.tree.labels.contains_key("providerASID"));
+    }
+
+    #[test]
+    fn invalid_content(){
+        let mut state = State::load_example("aspa").unwrap();
+        assert!(state.aspa_set(r#"{"customerAsid": 4294967296, "providers": []}"#.to_string(), true, false).is_err());
+        assert!(state.aspa_set(r#"{"providers": [1]}"#.to_string(), true, false).is_err());
+        assert!(State::load_example("roa").unwrap().aspa_get().is_err());
+    }
+}
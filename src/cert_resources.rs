@@ -0,0 +1,269 @@
+use chrono::{DateTime, Utc};
+use wasm_bindgen::prelude::*;
+
+use crate::{cms, der, encode_oid_from_string, resources, tree_util::{self, children, tag, Payload}, validate::Violation, State};
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ResourceSet{
+    pub inherit: bool,
+    pub resources: Vec<String>, // Prefixes, address ranges or AS ranges
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct CertResources{
+    pub ipv4: Option<ResourceSet>,
+    pub ipv6: Option<ResourceSet>,
+    pub asn: Option<ResourceSet>,
+    pub text: String, // All resources in the format of `set_cert_resources`
+    pub issues: Vec<Violation>, // Non-canonical encodings and overlaps
+}
+
+#[wasm_bindgen]
+impl State{
+    /// Returns the RFC 3779 resources of the certificate (or EE certificate) as JSON, together
+    /// with overlaps and encodings that are not in the canonical form.
+    #[wasm_bindgen]
+    pub fn cert_resources(&self) -> Result<String, String>{
+        let cert = cms::certificate(&self.tree, cms::object_certificate(&self.tree)?)?;
+        let ext = |oid: &str| cert.extensions.and_then(|e| cms::extension_value(&self.tree, e, oid));
+        let mut ret = CertResources::default();
+
+        if let Some(value) = ext(resources::OID_IP_RESOURCES){
+            let p = tree_util::payload(&self.tree, value, &self.overrides).ok_or("IP resources are not valid ASN.1")?;
+            ip_issues(&p, &mut ret.issues);
+
+            for family in resources::ip_resources(&p.tree, p.root).map_err(|e| format!("Invalid IP resources: {}", e))?{
+                let set = ResourceSet{
+                    inherit: family.inherit,
+                    resources: family.ranges.iter().map(|(min, max)| resources::format_ip_range(family.afi, *min, *max)).collect(),
+                };
+                match family.afi{
+                    1 => ret.ipv4 = Some(set),
+                    _ => ret.ipv6 = Some(set),
+                }
+            }
+        }
+
+        if let Some(value) = ext(resources::OID_AS_RESOURCES){
+            let p = tree_util::payload(&self.tree, value, &self.overrides).ok_or("AS resources are not valid ASN.1")?;
+            as_issues(&p, &mut ret.issues);
+
+            let asn = resources::as_resources(&p.tree, p.root).map_err(|e| format!("Invalid AS resources: {}", e))?;
+            ret.asn = Some(ResourceSet{
+                inherit: asn.inherit,
+                resources: asn.ranges.iter().map(|(min, max)| resources::format_as_range(*min, *max)).collect(),
+            });
+        }
+
+        let mut text = vec![];
+        for (name, set) in [("ipv4", &ret.ipv4), ("ipv6", &ret.ipv6), ("as", &ret.asn)]{
+            match set{
+                Some(s) if s.inherit => text.push(format!("{} inherit", name)),
+                Some(s) => text.extend(s.resources.iter().cloned()),
+                None => (),
+            }
+        }
+        ret.text = text.join(", ");
+        Ok(serde_json::to_string(&ret).unwrap())
+    }
+
+    /// Sets the resources from a list like `10.0.0.0/8, 192.168.0.0-192.168.3.255, AS64496-AS64511, inherit`.
+    /// The extensions are written in canonical form, resource types that are not listed are removed.
+    #[wasm_bindgen]
+    pub fn set_cert_resources(&mut self, text: String) -> Result<(), String>{
+        let (families, asn) = resources::parse_resource_list(&text)?;
+        if families.is_empty() && asn.is_none(){
+            return Err("No resources given".to_string());
+        }
+
+        let ip = (!families.is_empty()).then(|| resources::encode_ip_resources(&families));
+        self.set_cert_extension(resources::OID_IP_RESOURCES, true, ip.as_deref())?;
+        let asn = asn.map(|a| resources::encode_as_resources(&a));
+        self.set_cert_extension(resources::OID_AS_RESOURCES, true, asn.as_deref())?;
+
+        self.overrides.retain_existing(&self.tree);
+        Ok(())
+    }
+
+    /// Sets notBefore and notAfter of the certificate (or EE certificate) as ISO 8601, empty values
+    /// are left unchanged. The time types are kept, the certificate is not signed again.
+    #[wasm_bindgen]
+    pub fn set_cert_validity(&mut self, not_before: String, not_after: String) -> Result<(), String>{
+        let parse = |value: &str| match value.trim().is_empty(){
+            true => Ok(None),
+            false => DateTime::parse_from_rfc3339(value.trim())
+                .map(|t| Some(t.with_timezone(&Utc)))
+                .map_err(|_| format!("Invalid ISO 8601 date {}. Example: 2025-02-12T14:30:00Z", value)),
+        };
+        self.set_cert_window(parse(&not_before)?, parse(&not_after)?)
+    }
+}
+
+impl State{
+    /// `set_cert_validity` with parsed times.
+    pub(crate) fn set_cert_window(&mut self, not_before: Option<DateTime<Utc>>, not_after: Option<DateTime<Utc>>) -> Result<(), String>{
+        let cert = cms::certificate(&self.tree, cms::object_certificate(&self.tree)?)?;
+        let times = children(&self.tree, cert.validity).to_vec();
+        if times.len() != 2{
+            return Err("Validity must contain notBefore and notAfter".to_string());
+        }
+
+        for (id, time) in times.into_iter().zip([not_before, not_after]){
+            if let Some(time) = time{
+                let data = der::encode_time(tag(&self.tree, id), &time);
+                tree_util::set_content(&mut self.tree, id, data);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the extnValue of an extension of the certificate (or EE certificate). The extension
+    /// is added if it is missing and removed if `value` is None.
+    pub(crate) fn set_cert_extension(&mut self, oid: &str, critical: bool, value: Option<&[u8]>) -> Result<(), String>{
+        let cert = cms::certificate(&self.tree, cms::object_certificate(&self.tree)?)?;
+        let exts = cert.extensions.ok_or("Certificate has no extensions")?;
+
+        match (cms::extension_value(&self.tree, exts, oid), value){
+            (Some(ext), Some(value)) => return tree_util::set_string_payload(&mut self.tree, ext, value),
+            (Some(ext), None) => {
+                let ext = self.tree.tokens[&ext].parent;
+                self.tree.deep_delete(ext);
+                self.tree.tokens.get_mut(&exts).unwrap().children.retain(|c| *c != ext);
+            }
+            (None, Some(value)) => {
+                let mut ext = der::tlv(0x06, &encode_oid_from_string(oid));
+                if critical{
+                    ext.extend(der::tlv(0x01, &[0xFF]));
+                }
+                ext.extend(der::tlv(0x04, value));
+                let sub = tree_util::parse_encapsulated(&der::tlv(0x30, &ext))?;
+                tree_util::graft(&mut self.tree, &sub, exts, None);
+            }
+            (None, None) => return Ok(()),
+        }
+
+        self.tree.taint_parents(exts);
+        self.tree.fix_sizes(true);
+        Ok(())
+    }
+}
+
+fn issue(issues: &mut Vec<Violation>, node: usize, section: &str, message: &str){
+    issues.push(Violation{
+        node,
+        section: section.to_string(),
+        message: message.to_string(),
+    });
+}
+
+/// Checks the order of ranges, they must be sorted, must not overlap and adjacent ones must be joined.
+fn order_issues(issues: &mut Vec<Violation>, node: usize, prev: Option<(u128, u128)>, range: (u128, u128), section: &str){
+    let prev = match prev{
+        Some(p) => p,
+        None => return,
+    };
+    if range.0 < prev.0{
+        issue(issues, node, section, "Resources must be sorted ascending");
+    }
+    else if range.0 <= prev.1{
+        issue(issues, node, section, "Resource overlaps the previous one");
+    }
+    else if range.0 == prev.1 + 1{
+        issue(issues, node, section, "Resource is adjacent to the previous one, both must be joined");
+    }
+}
+
+fn ip_issues(p: &Payload, issues: &mut Vec<Violation>){
+    let t = &p.tree;
+    let mut last_afi = None;
+
+    for family in children(t, p.root){
+        let c = children(t, *family);
+        let afi = c.first().and_then(|a| resources::parse_afi(&t.tokens[a].data));
+        let bits = match afi.and_then(resources::address_bits){
+            Some(b) if c.len() == 2 => b,
+            _ => {
+                issue(issues, p.node(*family), "RFC 3779 2.2.3.3", "Invalid IPAddressFamily");
+                continue;
+            }
+        };
+        if last_afi.map(|l| l >= afi).unwrap_or(false){
+            issue(issues, p.node(*family), "RFC 3779 2.2.3.3", "Address families must be sorted and appear only once");
+        }
+        last_afi = Some(afi);
+
+        let mut prev = None;
+        for a in children(t, c[1]){
+            let range = match resources::address_or_range(t, *a, bits){
+                Some(r) => r,
+                None => {
+                    issue(issues, p.node(*a), "RFC 3779 2.2.3.7", "Invalid IPAddressOrRange");
+                    continue;
+                }
+            };
+
+            if tag(t, *a) == 0x03{
+                let (addr, len) = resources::parse_prefix(&t.tokens[a].data, bits).unwrap();
+                if t.tokens[a].data != resources::encode_bits(addr, len, bits){
+                    issue(issues, p.node(*a), "RFC 3779 2.2.3.8", "Unused bits of the prefix must be zero");
+                }
+            }
+            else{
+                let (min, max) = range;
+                let m = children(t, *a);
+                if min > max{
+                    issue(issues, p.node(*a), "RFC 3779 2.2.3.9", "Minimum of the range is greater than its maximum");
+                }
+                if resources::range_as_prefix(min, max, bits).is_some(){
+                    issue(issues, p.node(*a), "RFC 3779 2.2.3.7", "Range must be encoded as prefix");
+                }
+                if t.tokens[&m[0]].data != resources::encode_bits(min, bits - min.trailing_zeros().min(bits), bits){
+                    issue(issues, p.node(m[0]), "RFC 3779 2.2.3.9", "Trailing zero bits of the minimum must be left out");
+                }
+                if t.tokens[&m[1]].data != resources::encode_bits(max, bits - max.trailing_ones().min(bits), bits){
+                    issue(issues, p.node(m[1]), "RFC 3779 2.2.3.9", "Trailing one bits of the maximum must be left out");
+                }
+            }
+
+            order_issues(issues, p.node(*a), prev, range, "RFC 3779 2.2.3.6");
+            prev = Some(range);
+        }
+    }
+}
+
+fn as_issues(p: &Payload, issues: &mut Vec<Violation>){
+    let t = &p.tree;
+    if let Some(rdi) = tree_util::child_by_tag(t, p.root, 0xA1){
+        issue(issues, p.node(rdi), "RFC 6487 4.8.11", "Routing domain identifiers must not be used");
+    }
+
+    let ids = match tree_util::child_by_tag(t, p.root, 0xA0).and_then(|a| children(t, a).first().cloned()){
+        Some(c) if tag(t, c) == 0x30 => children(t, c),
+        _ => return,
+    };
+
+    let mut prev = None;
+    for id in ids{
+        let range = match tag(t, *id){
+            0x02 => der::parse_uint(&t.tokens[id].data).map(|v| (v, v)),
+            _ => {
+                let c = children(t, *id);
+                c.first().zip(c.get(1)).and_then(|(min, max)| der::parse_uint(&t.tokens[min].data).zip(der::parse_uint(&t.tokens[max].data)))
+            }
+        };
+        let range = match range{
+            Some(r) => r,
+            None => {
+                issue(issues, p.node(*id), "RFC 3779 3.2.3.6", "Invalid ASIdOrRange");
+                continue;
+            }
+        };
+
+        if tag(t, *id) == 0x30 && range.0 >= range.1{
+            let msg = if range.0 == range.1 { "Range of a single AS must be encoded as id" } else { "Minimum of the range is greater than its maximum" };
+            issue(issues, p.node(*id), "RFC 3779 3.2.3.7", msg);
+        }
+        order_issues(issues, p.node(*id), prev, range, "RFC 3779 3.2.3.5");
+        prev = Some(range);
+    }
+}
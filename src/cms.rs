@@ -0,0 +1,223 @@
+use cure_asn1::tree_parser::Tree;
+
+use crate::tree_util::{child, child_by_tag, children, is_oid, tag};
+
+// Locates the parts of RPKI signed objects (RFC 6488) and certificates in the tree.
+// All functions work on the structure only, labels are not required.
+
+pub const OID_SIGNED_DATA: &str = "1.2.840.113549.1.7.2";
+pub const OID_MESSAGE_DIGEST: &str = "1.2.840.113549.1.9.4";
+pub const OID_SHA256: &str = "2.16.840.1.101.3.4.2.1";
+pub const OID_SKI: &str = "2.5.29.14";
+pub const OID_AKI: &str = "2.5.29.35";
+pub const OID_CONTENT_TYPE: &str = "1.2.840.113549.1.9.3";
+pub const OID_SIGNING_TIME: &str = "1.2.840.113549.1.9.5";
+pub const OID_BINARY_SIGNING_TIME: &str = "1.2.840.113549.1.9.16.2.46";
+pub const OID_CRL_NUMBER: &str = "2.5.29.20";
+pub const OID_SHA256_WITH_RSA: &str = "1.2.840.113549.1.1.11";
+
+// eContentType of the RPKI signed objects
+pub const OID_CT_ROA: &str = "1.2.840.113549.1.9.16.1.24";
+pub const OID_CT_MANIFEST: &str = "1.2.840.113549.1.9.16.1.26";
+pub const OID_CT_GBR: &str = "1.2.840.113549.1.9.16.1.35";
+pub const OID_CT_RSC: &str = "1.2.840.113549.1.9.16.1.48";
+pub const OID_CT_ASPA: &str = "1.2.840.113549.1.9.16.1.49";
+pub const OID_CT_TAK: &str = "1.2.840.113549.1.9.16.1.50";
+
+// File extension used for each eContentType
+const CONTENT_TYPE_NAMES: &[(&str, &str)] = &[
+    (OID_CT_ROA, "roa"),
+    (OID_CT_MANIFEST, "mft"),
+    (OID_CT_GBR, "gbr"),
+    (OID_CT_ASPA, "asa"),
+    (OID_CT_RSC, "sig"),
+    (OID_CT_TAK, "tak"),
+];
+
+#[derive(Debug, Clone)]
+pub struct SignedObject{
+    pub signed_data: usize,
+    pub content_type: usize, // eContentType OID
+    pub econtent: usize, // OCTET STRING
+    pub certificate: Option<usize>, // EE certificate
+    pub signer_info: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct SignerInfo{
+    pub sid: usize,
+    pub digest_algorithm: usize, // OID
+    pub signed_attrs: Option<usize>,
+    pub signature: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Certificate{
+    pub tbs: usize,
+    pub signature: usize,
+    pub serial: usize,
+    pub issuer: usize,
+    pub validity: usize,
+    pub subject: usize,
+    pub spki: usize,
+    pub extensions: Option<usize>, // SEQUENCE of Extension
+}
+
+#[derive(Debug, Clone)]
+pub struct Crl{
+    pub tbs: usize,
+    pub this_update: usize,
+    pub next_update: Option<usize>,
+    pub revoked: Option<usize>, // SEQUENCE of revoked certificates
+    pub extensions: Option<usize>, // SEQUENCE of Extension
+}
+
+pub fn signed_object(tree: &Tree) -> Result<SignedObject, String>{
+    let root = tree.root_id;
+    if !is_oid(tree, child(tree, root, 0, "content type")?, OID_SIGNED_DATA){
+        return Err("Object is not a CMS signed object".to_string());
+    }
+
+    let signed_data = child(tree, child(tree, root, 1, "content")?, 0, "SignedData")?;
+    let encap = child(tree, signed_data, 2, "EncapsulatedContentInfo")?;
+    let content_type = child(tree, encap, 0, "eContentType")?;
+    let econtent = child(tree, child(tree, encap, 1, "eContent")?, 0, "eContent")?;
+
+    let certificate = match child_by_tag(tree, signed_data, 0xA0){
+        Some(certs) => children(tree, certs).first().cloned(),
+        None => None,
+    };
+
+    let signer_infos = *children(tree, signed_data).last().ok_or("SignedData has no signerInfos")?;
+    if tag(tree, signer_infos) != 0x31{
+        return Err("Missing SignerInfos".to_string());
+    }
+    let signer_info = child(tree, signer_infos, 0, "SignerInfo")?;
+
+    Ok(SignedObject{
+        signed_data,
+        content_type,
+        econtent,
+        certificate,
+        signer_info,
+    })
+}
+
+pub fn signer_info(tree: &Tree, id: usize) -> Result<SignerInfo, String>{
+    let sid = child(tree, id, 1, "SignerIdentifier")?;
+    let digest_algorithm = child(tree, child(tree, id, 2, "digestAlgorithm")?, 0, "digestAlgorithm")?;
+    let signed_attrs = child_by_tag(tree, id, 0xA0);
+
+    // The signature is the first OCTET STRING after the signature algorithm
+    let signature = children(tree, id).iter().skip(3).find(|c| tag(tree, **c) == 0x04).cloned()
+        .ok_or("Missing signature".to_string())?;
+
+    Ok(SignerInfo{
+        sid,
+        digest_algorithm,
+        signed_attrs,
+        signature,
+    })
+}
+
+/// Value of a signed attribute, i.e. the first element of its SET of values.
+pub fn signed_attribute(tree: &Tree, signed_attrs: usize, oid: &str) -> Option<usize>{
+    for attr in children(tree, signed_attrs){
+        let c = children(tree, *attr);
+        if c.len() == 2 && is_oid(tree, c[0], oid){
+            return children(tree, c[1]).first().cloned();
+        }
+    }
+    None
+}
+
+/// The EE certificate of a signed object, or the root if the object is a certificate itself.
+pub fn object_certificate(tree: &Tree) -> Result<usize, String>{
+    let root = tree.root_id;
+    let first = child(tree, root, 0, "content")?;
+    if is_oid(tree, first, OID_SIGNED_DATA){
+        return signed_object(tree)?.certificate.ok_or("Object has no EE certificate".to_string());
+    }
+    if tag(tree, first) == 0x30 && children(tree, root).len() == 3{
+        return Ok(root);
+    }
+    Err("Object is neither a certificate nor a signed object".to_string())
+}
+
+pub fn certificate(tree: &Tree, id: usize) -> Result<Certificate, String>{
+    let tbs = child(tree, id, 0, "TBSCertificate")?;
+    let signature = child(tree, id, 2, "signature")?;
+
+    // The version is optional, all later fields are at fixed positions after it
+    let offset = if tag(tree, child(tree, tbs, 0, "serial number")?) == 0xA0 { 1 } else { 0 };
+    let serial = child(tree, tbs, offset, "serial number")?;
+    let issuer = child(tree, tbs, offset + 2, "issuer")?;
+    let validity = child(tree, tbs, offset + 3, "validity")?;
+    let subject = child(tree, tbs, offset + 4, "subject")?;
+    let spki = child(tree, tbs, offset + 5, "SubjectPublicKeyInfo")?;
+    let extensions = match child_by_tag(tree, tbs, 0xA3){
+        Some(e) => Some(child(tree, e, 0, "extensions")?),
+        None => None,
+    };
+
+    Ok(Certificate{
+        tbs,
+        signature,
+        serial,
+        issuer,
+        validity,
+        subject,
+        spki,
+        extensions,
+    })
+}
+
+/// The extnValue OCTET STRING of the extension with the given OID.
+pub fn extension_value(tree: &Tree, extensions: usize, oid: &str) -> Option<usize>{
+    for ext in children(tree, extensions){
+        let c = children(tree, *ext);
+        if !c.is_empty() && is_oid(tree, c[0], oid){
+            return c.last().cloned();
+        }
+    }
+    None
+}
+
+pub fn crl(tree: &Tree) -> Result<Crl, String>{
+    let tbs = child(tree, tree.root_id, 0, "TBSCertList")?;
+    let is_time = |id: usize| matches!(tag(tree, id), 0x17 | 0x18);
+
+    // The version is optional for v1 CRLs
+    let offset = if tag(tree, child(tree, tbs, 0, "version")?) == 0x02 { 1 } else { 0 };
+    let this_update = child(tree, tbs, offset + 2, "thisUpdate")?;
+    if !is_time(this_update){
+        return Err("Object is not a CRL".to_string());
+    }
+
+    let c = children(tree, tbs);
+    let next_update = c.get(offset + 3).cloned().filter(|n| is_time(*n));
+    let revoked = c.iter().skip(offset + 3).find(|n| tag(tree, **n) == 0x30).cloned();
+    let extensions = match child_by_tag(tree, tbs, 0xA0){
+        Some(e) => Some(child(tree, e, 0, "crlExtensions")?),
+        None => None,
+    };
+
+    Ok(Crl{
+        tbs,
+        this_update,
+        next_update,
+        revoked,
+        extensions,
+    })
+}
+
+/// Type name of an object ("roa", "cer", "crl", ...) derived from its structure.
+pub fn object_type_name(tree: &Tree) -> Option<&'static str>{
+    if let Ok(obj) = signed_object(tree){
+        return CONTENT_TYPE_NAMES.iter().find(|(oid, _)| is_oid(tree, obj.content_type, oid)).map(|(_, name)| *name);
+    }
+    if crl(tree).is_ok(){
+        return Some("crl");
+    }
+    object_certificate(tree).ok().map(|_| "cer")
+}
@@ -0,0 +1,303 @@
+use chrono::{DateTime, Datelike, Utc};
+use wasm_bindgen::prelude::*;
+
+use crate::{cms, der, tree_util::{self, children, tag}, State};
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct EntryExtension{
+    pub oid: String,
+    pub critical: bool,
+    pub value: String, // Hex
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevokedEntry{
+    pub node: usize,
+    pub serial: String, // Hex
+    pub revocation_date: String, // RFC 3339, or the raw value if it is not a valid time
+    pub extensions: Vec<EntryExtension>,
+}
+
+/// Serial number from hex, optionally with 0x prefix or colons, as INTEGER content.
+fn parse_serial(serial: &str) -> Result<Vec<u8>, String>{
+    let s = serial.trim().trim_start_matches("0x").replace(':', "");
+    let mut data = hex::decode(&s).map_err(|_| format!("Invalid serial number {}, must be hex", serial))?;
+    if data.is_empty(){
+        return Err("Serial number must not be empty".to_string());
+    }
+    if data[0] & 0x80 != 0{
+        data.insert(0, 0);
+    }
+    Ok(data)
+}
+
+/// Orders INTEGER contents by their value, for positive numbers.
+fn serial_key(data: &[u8]) -> (usize, Vec<u8>){
+    let start = data.iter().position(|b| *b != 0).unwrap_or(data.len());
+    (data.len() - start, data[start..].to_vec())
+}
+
+fn parse_date(date: &str) -> Result<DateTime<Utc>, String>{
+    if date.trim().is_empty(){
+        return Ok(Utc::now());
+    }
+    chrono::DateTime::parse_from_rfc3339(date.trim())
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| format!("Invalid ISO 8601 date {}. Example: 2025-02-12T14:30:00Z", date))
+}
+
+/// UTCTime up to 2049, GeneralizedTime after (RFC 5280 5.1.2.6).
+fn encode_date(date: &DateTime<Utc>) -> Vec<u8>{
+    let tag = if date.year() < 2050 { 0x17 } else { 0x18 };
+    der::tlv(tag, &der::encode_time(tag, date))
+}
+
+#[wasm_bindgen]
+impl State{
+    /// Returns the revoked certificates of a CRL as JSON list of serial, revocation date and entry extensions.
+    #[wasm_bindgen]
+    pub fn crl_revoked(&self) -> Result<String, String>{
+        let crl = cms::crl(&self.tree)?;
+        let mut ret = vec![];
+
+        for entry in crl.revoked.map(|r| children(&self.tree, r)).unwrap_or(&[]){
+            let c = children(&self.tree, *entry);
+            let token = |i: usize| c.get(i).map(|n| &self.tree.tokens[n]);
+
+            let date = match token(1){
+                Some(t) => der::parse_time(t.tag_u, &t.data).map(|d| d.to_rfc3339()).unwrap_or(String::from_utf8_lossy(&t.data).to_string()),
+                None => String::new(),
+            };
+
+            let mut extensions = vec![];
+            for ext in c.get(2).map(|e| children(&self.tree, *e)).unwrap_or(&[]){
+                let e = children(&self.tree, *ext);
+                if e.len() < 2{
+                    continue;
+                }
+                extensions.push(EntryExtension{
+                    oid: der::decode_oid(&self.tree.tokens[&e[0]].data),
+                    critical: e.len() == 3 && self.tree.tokens[&e[1]].data != [0],
+                    value: hex::encode(tree_util::string_payload(&self.tree, *e.last().unwrap(), &self.overrides)),
+                });
+            }
+
+            ret.push(RevokedEntry{
+                node: *entry,
+                serial: token(0).map(|t| hex::encode(&t.data)).unwrap_or_default(),
+                revocation_date: date,
+                extensions,
+            });
+        }
+        Ok(serde_json::to_string(&ret).unwrap())
+    }
+
+    /// Adds a revoked serial (hex) with an ISO 8601 revocation date, now if empty.
+    /// With `sorted` the entry is inserted in serial order, otherwise appended.
+    #[wasm_bindgen]
+    pub fn crl_add_revoked(&mut self, serial: String, date: String, sorted: bool) -> Result<(), String>{
+        let serial = parse_serial(&serial)?;
+        let date = parse_date(&date)?;
+        self.add_revoked(serial, &date, sorted)?;
+        self.overrides.retain_existing(&self.tree);
+        Ok(())
+    }
+
+    /// Adds one revoked serial per line, optionally followed by an ISO 8601 date separated
+    /// by whitespace or a comma. Returns the number of added entries.
+    #[wasm_bindgen]
+    pub fn crl_import_revoked(&mut self, list: String, sorted: bool) -> Result<usize, String>{
+        let mut entries = vec![];
+        for line in list.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')){
+            let mut parts = line.splitn(2, |c: char| c == ',' || c.is_whitespace());
+            let serial = parse_serial(parts.next().unwrap())?;
+            let date = parse_date(parts.next().unwrap_or(""))?;
+            entries.push((serial, date));
+        }
+
+        for (serial, date) in entries.iter(){
+            self.add_revoked(serial.clone(), date, sorted)?;
+        }
+        self.overrides.retain_existing(&self.tree);
+        Ok(entries.len())
+    }
+
+    /// Removes all entries with the given serial. The list is dropped when it becomes empty.
+    #[wasm_bindgen]
+    pub fn crl_remove_revoked(&mut self, serial: String) -> Result<(), String>{
+        let serial = serial_key(&parse_serial(&serial)?);
+        let crl = cms::crl(&self.tree)?;
+        let revoked = crl.revoked.ok_or("CRL has no revoked certificates")?;
+
+        let matching = children(&self.tree, revoked).iter()
+            .filter(|e| children(&self.tree, **e).first().map(|s| serial_key(&self.tree.tokens[s].data) == serial).unwrap_or(false))
+            .cloned()
+            .collect::<Vec<usize>>();
+        if matching.is_empty(){
+            return Err("Serial number is not revoked".to_string());
+        }
+
+        for entry in matching{
+            self.tree.deep_delete(entry);
+            self.tree.tokens.get_mut(&revoked).unwrap().children.retain(|c| *c != entry);
+        }
+
+        if children(&self.tree, revoked).is_empty(){
+            self.tree.deep_delete(revoked);
+            self.tree.tokens.get_mut(&crl.tbs).unwrap().children.retain(|c| *c != revoked);
+            self.tree.taint_parents(crl.tbs);
+        }
+        else{
+            self.tree.taint_parents(revoked);
+        }
+        self.tree.fix_sizes(true);
+        self.overrides.retain_existing(&self.tree);
+        Ok(())
+    }
+
+    /// Orders the revoked certificates by serial, ascending or descending.
+    #[wasm_bindgen]
+    pub fn crl_sort_revoked(&mut self, descending: bool) -> Result<(), String>{
+        let revoked = cms::crl(&self.tree)?.revoked.ok_or("CRL has no revoked certificates")?;
+
+        let mut entries = children(&self.tree, revoked).to_vec();
+        entries.sort_by_key(|e| children(&self.tree, *e).first().map(|s| serial_key(&self.tree.tokens[s].data)));
+        if descending{
+            entries.reverse();
+        }
+
+        self.tree.tokens.get_mut(&revoked).unwrap().children = entries;
+        self.tree.taint_parents(revoked);
+        Ok(())
+    }
+
+    /// Sets thisUpdate and nextUpdate (ISO 8601), empty values are left unchanged. The time types are kept.
+    #[wasm_bindgen]
+    pub fn crl_set_update(&mut self, this_update: String, next_update: String) -> Result<(), String>{
+        let crl = cms::crl(&self.tree)?;
+        let next = crl.next_update.filter(|_| !next_update.trim().is_empty());
+        if crl.next_update.is_none() && !next_update.trim().is_empty(){
+            return Err("CRL has no nextUpdate".to_string());
+        }
+
+        for (id, value) in [(Some(crl.this_update), this_update), (next, next_update)]{
+            if let Some(id) = id.filter(|_| !value.trim().is_empty()){
+                let time = parse_date(&value)?;
+                let data = der::encode_time(tag(&self.tree, id), &time);
+                tree_util::set_content(&mut self.tree, id, data);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the CRLNumber extension to a decimal value.
+    #[wasm_bindgen]
+    pub fn crl_set_number(&mut self, number: String) -> Result<(), String>{
+        let number = number.trim().parse::<u128>().map_err(|_| "Invalid CRL number".to_string())?;
+        let crl = cms::crl(&self.tree)?;
+        let ext = crl.extensions
+            .and_then(|e| cms::extension_value(&self.tree, e, cms::OID_CRL_NUMBER))
+            .ok_or("CRL has no CRLNumber extension")?;
+
+        tree_util::set_string_payload(&mut self.tree, ext, &der::tlv(0x02, &der::encode_uint(number)))?;
+        self.overrides.retain_existing(&self.tree);
+        Ok(())
+    }
+}
+
+impl State{
+    fn add_revoked(&mut self, serial: Vec<u8>, date: &DateTime<Utc>, sorted: bool) -> Result<(), String>{
+        let crl = cms::crl(&self.tree)?;
+
+        let mut entry = der::tlv(0x02, &serial);
+        entry.extend(encode_date(date));
+        let entry = tree_util::parse_encapsulated(&der::tlv(0x30, &entry))?;
+
+        // revokedCertificates follows nextUpdate, or thisUpdate if there is none
+        let revoked = match crl.revoked{
+            Some(r) => r,
+            None => {
+                let after = crl.next_update.unwrap_or(crl.this_update);
+                let position = children(&self.tree, crl.tbs).iter().position(|c| *c == after).map(|p| p + 1);
+                let list = tree_util::parse_encapsulated(&der::tlv(0x30, &[]))?;
+                tree_util::graft(&mut self.tree, &list, crl.tbs, position)
+            }
+        };
+
+        let position = if sorted{
+            let key = serial_key(&serial);
+            children(&self.tree, revoked).iter().position(|e| {
+                children(&self.tree, *e).first().map(|s| serial_key(&self.tree.tokens[s].data) > key).unwrap_or(false)
+            })
+        }
+        else{
+            None
+        };
+
+        let id = tree_util::graft(&mut self.tree, &entry, revoked, position);
+        self.tree.taint_parents(id);
+        self.tree.fix_sizes(true);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn revoked(state: &State) -> Vec<RevokedEntry>{
+        serde_json::from_str(&state.crl_revoked().unwrap()).unwrap()
+    }
+
+    fn serials(state: &State) -> Vec<String>{
+        revoked(state).into_iter().map(|e| e.serial).collect()
+    }
+
+    #[test]
+    fn revoked_entries(){
+        let mut state = State::load_example("crl").unwrap();
+        for serial in serials(&state){
+            state.crl_remove_revoked(serial).unwrap();
+        }
+        assert!(cms::crl(&state.tree).unwrap().revoked.is_none());
+
+        let added = state.crl_import_revoked("0x03\n# comment\n01, 2024-01-01T00:00:00Z\n\n80 2024-01-02T00:00:00Z".to_string(), true).unwrap();
+        assert_eq!(added, 3);
+        assert_eq!(serials(&state), ["01", "03", "0080"]);
+        assert_eq!(revoked(&state)[0].revocation_date, "2024-01-01T00:00:00+00:00");
+
+        state.crl_add_revoked("02".to_string(), String::new(), false).unwrap();
+        assert_eq!(serials(&state), ["01", "03", "0080", "02"]);
+        state.crl_sort_revoked(true).unwrap();
+        assert_eq!(serials(&state), ["0080", "03", "02", "01"]);
+
+        state.crl_remove_revoked("0x80".to_string()).unwrap();
+        assert_eq!(serials(&state), ["03", "02", "01"]);
+        assert!(state.crl_remove_revoked("80".to_string()).is_err());
+        assert!(state.crl_add_revoked("xyz".to_string(), String::new(), true).is_err());
+        assert!(state.crl_add_revoked("01".to_string(), "2024-13-01".to_string(), true).is_err());
+    }
+
+    #[test]
+    fn update_and_number(){
+        let mut state = State::load_example("crl").unwrap();
+        state.crl_set_update("2030-01-01T00:00:00Z".to_string(), String::new()).unwrap();
+        let crl = cms::crl(&state.tree).unwrap();
+        let this_update = &state.tree.tokens[&crl.this_update];
+        assert_eq!(der::parse_time(this_update.tag_u, &this_update.data).map(|t| t.to_rfc3339()).as_deref(), Some("2030-01-01T00:00:00+00:00"));
+
+        state.crl_set_number("4660".to_string()).unwrap();
+        let crl = cms::crl(&state.tree).unwrap();
+        let number = cms::extension_value(&state.tree, crl.extensions.unwrap(), cms::OID_CRL_NUMBER).unwrap();
+        assert_eq!(tree_util::string_payload(&state.tree, number, &state.overrides), [0x02, 0x02, 0x12, 0x34]);
+        assert!(state.crl_set_number("0x10".to_string()).is_err());
+    }
+
+    #[test]
+    fn not_a_crl(){
+        let mut state = State::load_example("roa").unwrap();
+        assert!(state.crl_revoked().is_err());
+        assert!(state.crl_add_revoked("01".to_string(), String::new(), true).is_err());
+    }
+}
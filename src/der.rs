@@ -0,0 +1,176 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use crate::encoder::encode_length;
+
+// Minimal DER helpers for reading headers and building elements that are then
+// parsed into the tree. Everything else goes through cure_asn1.
+
+#[derive(Debug, Clone)]
+pub struct Header{
+    pub tag: Vec<u8>,
+    pub constructed: bool,
+    pub header_len: usize,
+    pub length: Option<usize>, // None for the indefinite form
+}
+
+/// Reads tag and length of the element starting at `offset`.
+pub fn parse_header(data: &[u8], offset: usize) -> Result<Header, String>{
+    let mut pos = offset;
+    let first = *data.get(pos).ok_or("Missing tag".to_string())?;
+    let mut tag = vec![first];
+    pos += 1;
+
+    // High tag number form, continues while the top bit is set
+    if first & 0x1F == 0x1F{
+        loop{
+            let b = *data.get(pos).ok_or("Truncated tag".to_string())?;
+            tag.push(b);
+            pos += 1;
+            if b & 0x80 == 0{
+                break;
+            }
+        }
+    }
+
+    let len_byte = *data.get(pos).ok_or("Missing length".to_string())?;
+    pos += 1;
+
+    let length = if len_byte == 0x80{
+        None
+    }
+    else if len_byte & 0x80 == 0{
+        Some(len_byte as usize)
+    }
+    else{
+        let num = (len_byte & 0x7F) as usize;
+        if num > std::mem::size_of::<usize>(){
+            return Err(format!("Length field of {} bytes is not supported", num));
+        }
+        if pos + num > data.len(){
+            return Err("Truncated length".to_string());
+        }
+        let mut l = 0usize;
+        for b in data[pos..pos + num].iter(){
+            l = (l << 8) | *b as usize;
+        }
+        pos += num;
+        Some(l)
+    };
+
+    Ok(Header{
+        tag,
+        constructed: first & 0x20 != 0,
+        header_len: pos - offset,
+        length,
+    })
+}
+
+pub fn tlv(tag: u8, content: &[u8]) -> Vec<u8>{
+    let mut ret = vec![tag];
+    ret.extend(encode_length(content.len()));
+    ret.extend_from_slice(content);
+    ret
+}
+
+/// Dotted string of an encoded OBJECT IDENTIFIER.
+pub fn decode_oid(data: &[u8]) -> String{
+    let mut arcs: Vec<u128> = vec![];
+    let mut cur = 0u128;
+    for b in data{
+        cur = (cur << 7) | (*b & 0x7F) as u128;
+        if b & 0x80 == 0{
+            arcs.push(cur);
+            cur = 0;
+        }
+    }
+
+    let mut ret = vec![];
+    if let Some(first) = arcs.first(){
+        let x = (*first / 40).min(2);
+        ret.push(x.to_string());
+        ret.push((first - x * 40).to_string());
+    }
+    ret.extend(arcs.iter().skip(1).map(|a| a.to_string()));
+    ret.join(".")
+}
+
+/// Value of a non-negative INTEGER, None if it is negative or does not fit.
+pub fn parse_uint(data: &[u8]) -> Option<u128>{
+    if data.is_empty() || data[0] & 0x80 != 0{
+        return None;
+    }
+    let data = if data[0] == 0 { &data[1..] } else { data };
+    if data.len() > 16{
+        return None;
+    }
+    Some(data.iter().fold(0u128, |acc, b| (acc << 8) | *b as u128))
+}
+
+/// Parses a UTCTime (0x17) or GeneralizedTime (0x18) in the DER form with seconds and Z.
+pub fn parse_time(tag: u8, data: &[u8]) -> Option<DateTime<Utc>>{
+    let s = std::str::from_utf8(data).ok()?;
+    let full = match tag{
+        // Two digit years from 50 on are in the 20th century (RFC 5280 4.1.2.5.1)
+        0x17 => format!("{}{}", if s.get(0..2)? >= "50" { "19" } else { "20" }, s),
+        0x18 => s.to_string(),
+        _ => return None,
+    };
+    NaiveDateTime::parse_from_str(&full, "%Y%m%d%H%M%SZ").ok().map(|t| t.and_utc())
+}
+
+/// Minimal encoding of a non-negative INTEGER value.
+pub fn encode_uint(value: u128) -> Vec<u8>{
+    let bytes = value.to_be_bytes();
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len() - 1);
+    let mut ret = vec![];
+    if bytes[start] & 0x80 != 0{
+        ret.push(0);
+    }
+    ret.extend_from_slice(&bytes[start..]);
+    ret
+}
+
+/// Encodes a time as UTCTime (0x17) or GeneralizedTime (0x18) content.
+pub fn encode_time(tag: u8, time: &DateTime<Utc>) -> Vec<u8>{
+    let format = if tag == 0x17 { "%y%m%d%H%M%SZ" } else { "%Y%m%d%H%M%SZ" };
+    time.format(format).to_string().into_bytes()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn headers(){
+        let h = parse_header(&[0x30, 0x82, 0x01, 0x00], 0).unwrap();
+        assert_eq!((h.tag, h.constructed, h.header_len, h.length), (vec![0x30], true, 4, Some(0x100)));
+
+        let h = parse_header(&[0x9F, 0x81, 0x00, 0x80], 0).unwrap();
+        assert_eq!((h.tag, h.constructed, h.header_len, h.length), (vec![0x9F, 0x81, 0x00], false, 4, None));
+
+        assert!(parse_header(&[0x04, 0x82, 0x01], 0).is_err());
+    }
+
+    #[test]
+    fn integers(){
+        for value in [0u128, 0x7F, 0x80, 0xFFFF, u32::MAX as u128, u128::MAX >> 1]{
+            assert_eq!(parse_uint(&encode_uint(value)), Some(value));
+        }
+        assert_eq!(encode_uint(0x80), [0x00, 0x80]);
+        assert_eq!(parse_uint(&[0xFF]), None);
+    }
+
+    #[test]
+    fn times(){
+        let time = NaiveDateTime::parse_from_str("20491231235959", "%Y%m%d%H%M%S").unwrap().and_utc();
+        for tag in [0x17, 0x18]{
+            assert_eq!(parse_time(tag, &encode_time(tag, &time)), Some(time));
+        }
+        // UTCTime from 50 on is in the 20th century
+        assert_eq!(parse_time(0x17, b"500101000000Z").map(|t| t.format("%Y").to_string()).as_deref(), Some("1950"));
+    }
+
+    #[test]
+    fn oids(){
+        assert_eq!(decode_oid(&crate::encode_oid_from_string("1.2.840.113549.1.9.16.1.24")), "1.2.840.113549.1.9.16.1.24");
+    }
+}
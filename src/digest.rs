@@ -0,0 +1,178 @@
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use wasm_bindgen::prelude::*;
+
+use crate::{encoder, tree_util, State};
+
+const DIGEST_OIDS: &[(DigestAlgorithm, &str)] = &[
+    (DigestAlgorithm::Sha1, "1.3.14.3.2.26"),
+    (DigestAlgorithm::Sha256, "2.16.840.1.101.3.4.2.1"),
+    (DigestAlgorithm::Sha384, "2.16.840.1.101.3.4.2.2"),
+    (DigestAlgorithm::Sha512, "2.16.840.1.101.3.4.2.3"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DigestAlgorithm{
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl DigestAlgorithm{
+    pub fn from_name(name: &str) -> Result<DigestAlgorithm, String>{
+        match name.to_lowercase().replace(['-', '_'], "").as_str(){
+            "sha1" => Ok(DigestAlgorithm::Sha1),
+            "sha256" => Ok(DigestAlgorithm::Sha256),
+            "sha384" => Ok(DigestAlgorithm::Sha384),
+            "sha512" => Ok(DigestAlgorithm::Sha512),
+            _ => Err(format!("Unsupported digest algorithm: {} (use SHA-1, SHA-256, SHA-384 or SHA-512)", name)),
+        }
+    }
+
+    pub fn from_oid(oid: &str) -> Option<DigestAlgorithm>{
+        DIGEST_OIDS.iter().find(|(_, o)| *o == oid).map(|(a, _)| *a)
+    }
+
+    pub fn oid(&self) -> &'static str{
+        DIGEST_OIDS.iter().find(|(a, _)| a == self).map(|(_, o)| *o).unwrap()
+    }
+
+    pub fn name(&self) -> &'static str{
+        match self{
+            DigestAlgorithm::Sha1 => "SHA-1",
+            DigestAlgorithm::Sha256 => "SHA-256",
+            DigestAlgorithm::Sha384 => "SHA-384",
+            DigestAlgorithm::Sha512 => "SHA-512",
+        }
+    }
+
+    pub fn hash(&self, data: &[u8]) -> Vec<u8>{
+        match self{
+            DigestAlgorithm::Sha1 => Sha1::digest(data).to_vec(),
+            DigestAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+            DigestAlgorithm::Sha384 => Sha384::digest(data).to_vec(),
+            DigestAlgorithm::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+}
+
+/// Which bytes of a node are hashed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DigestScope{
+    Full, // Tag, length and content
+    Content,
+    BitStringPayload, // Content of a BIT STRING without the unused-bits octet, e.g. for key identifiers
+}
+
+impl DigestScope{
+    pub fn from_name(name: &str) -> Result<DigestScope, String>{
+        match name{
+            "full" | "tlv" => Ok(DigestScope::Full),
+            "content" => Ok(DigestScope::Content),
+            "bitstring" | "bit_string_payload" => Ok(DigestScope::BitStringPayload),
+            _ => Err(format!("Invalid digest scope: {} (use full, content or bitstring)", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DigestResult{
+    pub hex: String,
+    pub base64: String,
+}
+
+#[wasm_bindgen]
+impl State{
+    /// Hashes a node, returns hex and base64 of the digest as JSON.
+    /// If `target` is set, the digest is also written into that node, e.g. an OCTET STRING of a manifest entry.
+    #[wasm_bindgen]
+    pub fn digest(&mut self, id: usize, algorithm: String, scope: String, target: Option<usize>) -> Result<String, String>{
+        let algorithm = DigestAlgorithm::from_name(&algorithm)?;
+        let scope = DigestScope::from_name(&scope)?;
+        let digest = algorithm.hash(&self.digest_input(id, scope)?);
+
+        if let Some(target) = target{
+            let token = self.tree.tokens.get(&target).ok_or("Invalid target node".to_string())?;
+            if encoder::is_octet_string(token.tag_u) || encoder::is_bit_string(token.tag_u){
+                tree_util::set_string_payload(&mut self.tree, target, &digest)?;
+            }
+            else{
+                tree_util::set_content(&mut self.tree, target, digest.clone());
+            }
+            self.overrides.retain_existing(&self.tree);
+        }
+
+        let result = DigestResult{
+            hex: hex::encode(&digest),
+            base64: base64::encode(&digest),
+        };
+        Ok(serde_json::to_string(&result).unwrap())
+    }
+}
+
+impl State{
+    fn digest_input(&self, id: usize, scope: DigestScope) -> Result<Vec<u8>, String>{
+        let token = self.tree.tokens.get(&id).ok_or("Invalid node".to_string())?;
+
+        match scope{
+            DigestScope::Full => Ok(encoder::encode_element(&self.tree, id, &self.overrides)),
+            DigestScope::Content => Ok(encoder::encode_content(&self.tree, id, &self.overrides)),
+            DigestScope::BitStringPayload => {
+                if !encoder::is_bit_string(token.tag_u){
+                    return Err("Node is not a BIT STRING".to_string());
+                }
+                Ok(tree_util::string_payload(&self.tree, id, &self.overrides))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::cms;
+
+    fn digest(state: &mut State, id: usize, algorithm: &str, scope: &str, target: Option<usize>) -> Result<DigestResult, String>{
+        state.digest(id, algorithm.to_string(), scope.to_string(), target).map(|r| serde_json::from_str(&r).unwrap())
+    }
+
+    #[test]
+    fn scopes(){
+        let mut state = State::load_example("roa").unwrap();
+        let root = state.tree.root_id;
+        let encoded = state.encode();
+        let header_len = crate::der::parse_header(&encoded, 0).unwrap().header_len;
+
+        let full = digest(&mut state, root, "SHA-256", "full", None).unwrap();
+        assert_eq!(full.hex, hex::encode(Sha256::digest(&encoded)));
+        assert_eq!(base64::decode(full.base64).unwrap(), Sha256::digest(&encoded).to_vec());
+        assert_eq!(digest(&mut state, root, "sha512", "content", None).unwrap().hex, hex::encode(Sha512::digest(&encoded[header_len..])));
+
+        // The subject key identifier is the SHA-1 of the payload of the subjectPublicKey
+        let cert = cms::certificate(&state.tree, cms::object_certificate(&state.tree).unwrap()).unwrap();
+        let bits = tree_util::child(&state.tree, cert.spki, 1, "subjectPublicKey").unwrap();
+        assert_eq!(digest(&mut state, bits, "SHA-1", "bitstring", None).unwrap().hex, state.key_identifiers().unwrap().expected_ski);
+        assert!(digest(&mut state, root, "SHA-1", "bitstring", None).is_err());
+    }
+
+    #[test]
+    fn target(){
+        // SEQUENCE { OCTET STRING, INTEGER 5 }, the digest of the INTEGER goes into the OCTET STRING
+        let mut state = State::from_tree(cure_asn1::interface::parse_tree(&[0x30, 0x05, 0x04, 0x00, 0x02, 0x01, 0x05], "").unwrap());
+        let c = tree_util::children(&state.tree, state.tree.root_id).to_vec();
+        digest(&mut state, c[1], "SHA-384", "full", Some(c[0])).unwrap();
+        assert_eq!(tree_util::string_payload(&state.tree, c[0], &state.overrides), Sha384::digest([0x02, 0x01, 0x05]).to_vec());
+        assert!(digest(&mut state, c[1], "SHA-256", "full", Some(1000)).is_err());
+    }
+
+    #[test]
+    fn invalid_options(){
+        let mut state = State::load_example("roa").unwrap();
+        let root = state.tree.root_id;
+        assert!(digest(&mut state, root, "MD5", "full", None).is_err());
+        assert!(digest(&mut state, root, "SHA-256", "header", None).is_err());
+        assert!(digest(&mut state, usize::MAX, "SHA-256", "full", None).is_err());
+        assert_eq!(DigestAlgorithm::from_oid(DigestAlgorithm::Sha384.oid()), Some(DigestAlgorithm::Sha384));
+    }
+}
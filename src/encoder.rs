@@ -0,0 +1,188 @@
+use std::collections::{HashMap, HashSet};
+
+use cure_asn1::tree_parser::Tree;
+
+// Serialises a tree the way the editor shows it. Primitive strings that were
+// expanded into an encapsulated subtree are written from their children, so
+// edits below them end up in the content and length of the string itself.
+
+/// Bytes that are written verbatim instead of, or after, the encoding of a node.
+/// These are not validated in any way and are meant to produce malformed objects.
+/// cure's `Tree::encode` does not know them, so every encoding goes through `encode_tree`.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct Overrides{
+    pub nodes: HashMap<usize, Vec<u8>>, // Replaces tag, length and content of the node
+    pub trailing: HashMap<usize, Vec<u8>>, // Appended after the node, i.e. before its next sibling
+    #[serde(default)]
+    pub marked: HashSet<usize>, // Nodes that are only marked as edited because of their raw bytes
+}
+
+impl Overrides{
+    /// Drops entries of nodes that no longer exist, so they are not picked up by reused ids.
+    pub fn retain_existing(&mut self, tree: &Tree){
+        self.nodes.retain(|id, _| tree.tokens.contains_key(id));
+        self.trailing.retain(|id, _| tree.tokens.contains_key(id));
+        self.marked.retain(|id| tree.tokens.contains_key(id));
+    }
+}
+
+pub fn encode_tree(tree: &Tree, overrides: &Overrides) -> Vec<u8>{
+    encode_node(tree, tree.root_id, overrides)
+}
+
+/// Encoding of a node followed by its trailing bytes.
+pub fn encode_node(tree: &Tree, id: usize, overrides: &Overrides) -> Vec<u8>{
+    let mut ret = encode_element(tree, id, overrides);
+    if let Some(trailing) = overrides.trailing.get(&id){
+        ret.extend(trailing);
+    }
+    ret
+}
+
+/// Tag, length and content of a node only.
+pub fn encode_element(tree: &Tree, id: usize, overrides: &Overrides) -> Vec<u8>{
+    if let Some(raw) = overrides.nodes.get(&id){
+        return raw.clone();
+    }
+
+    let token = &tree.tokens[&id];
+    let content = encode_content(tree, id, overrides);
+
+    let mut ret = if token.visual_tag.is_empty(){
+        vec![token.tag_u]
+    }
+    else{
+        token.visual_tag.clone()
+    };
+
+    // A manually set length is kept as is, even if it does not match the content
+    let length = if token.manipulated_length{
+        token.visual_length
+    }
+    else{
+        content.len()
+    };
+
+    ret.extend(encode_length(length));
+    ret.extend(content);
+    ret
+}
+
+pub fn encode_content(tree: &Tree, id: usize, overrides: &Overrides) -> Vec<u8>{
+    let token = &tree.tokens[&id];
+    if token.children.is_empty(){
+        return token.data.clone();
+    }
+
+    let mut ret = vec![];
+
+    // An expanded BIT STRING keeps its unused-bits octet in front of the encapsulated data
+    if is_bit_string(token.tag_u){
+        ret.push(token.data.first().cloned().unwrap_or(0));
+    }
+
+    for child in token.children.iter(){
+        ret.extend(encode_node(tree, *child, overrides));
+    }
+    ret
+}
+
+pub fn encode_length(length: usize) -> Vec<u8>{
+    if length < 0x80{
+        return vec![length as u8];
+    }
+
+    let bytes = length.to_be_bytes();
+    let first = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len() - 1);
+    let mut ret = vec![0x80 | (bytes.len() - first) as u8];
+    ret.extend_from_slice(&bytes[first..]);
+    ret
+}
+
+pub fn is_bit_string(tag: u8) -> bool{
+    tag == 0x03
+}
+
+pub fn is_octet_string(tag: u8) -> bool{
+    tag == 0x04
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::tree_util;
+
+    // SEQUENCE { INTEGER 5, OCTET STRING "ab" }
+    const SEQUENCE: [u8; 9] = [0x30, 0x07, 0x02, 0x01, 0x05, 0x04, 0x02, 0x61, 0x62];
+
+    fn parse(data: &[u8]) -> Tree{
+        cure_asn1::interface::parse_tree(data, "").unwrap()
+    }
+
+    fn child(tree: &Tree, index: usize) -> usize{
+        tree.tokens[&tree.root_id].children[index]
+    }
+
+    #[test]
+    fn round_trip(){
+        assert_eq!(encode_tree(&parse(&SEQUENCE), &Overrides::default()), SEQUENCE);
+    }
+
+    #[test]
+    fn manipulated_length(){
+        let mut tree = parse(&SEQUENCE);
+        let token = tree.tokens.get_mut(&child(&tree, 0)).unwrap();
+        token.manipulated_length = true;
+        token.visual_length = 0x85;
+
+        // The content is kept, the length does not fit in the short form
+        let expected = [0x30, 0x08, 0x02, 0x81, 0x85, 0x05, 0x04, 0x02, 0x61, 0x62];
+        assert_eq!(encode_tree(&tree, &Overrides::default()), expected);
+    }
+
+    #[test]
+    fn visual_tag(){
+        let mut tree = parse(&SEQUENCE);
+        tree.tokens.get_mut(&child(&tree, 1)).unwrap().visual_tag = vec![0x9F, 0x1F];
+
+        let expected = [0x30, 0x08, 0x02, 0x01, 0x05, 0x9F, 0x1F, 0x02, 0x61, 0x62];
+        assert_eq!(encode_tree(&tree, &Overrides::default()), expected);
+    }
+
+    #[test]
+    fn raw_and_trailing_bytes(){
+        let tree = parse(&SEQUENCE);
+        let mut overrides = Overrides::default();
+        overrides.nodes.insert(child(&tree, 0), vec![0xFF]);
+        overrides.trailing.insert(child(&tree, 1), vec![0x00]);
+        overrides.trailing.insert(tree.root_id, vec![0xAA, 0xBB]);
+
+        let expected = [0x30, 0x06, 0xFF, 0x04, 0x02, 0x61, 0x62, 0x00, 0xAA, 0xBB];
+        assert_eq!(encode_tree(&tree, &overrides), expected);
+    }
+
+    #[test]
+    fn expanded_strings(){
+        // OCTET STRING and BIT STRING with an encapsulated INTEGER 1
+        let data = [0x30, 0x0B, 0x04, 0x03, 0x02, 0x01, 0x01, 0x03, 0x04, 0x00, 0x02, 0x01, 0x01];
+        let mut tree = parse(&data);
+        for index in 0..2{
+            let id = child(&tree, index);
+            tree_util::set_encapsulated(&mut tree, id, &[0x02, 0x01, 0x01]).unwrap();
+        }
+        assert_eq!(encode_tree(&tree, &Overrides::default()), data);
+
+        // Edits below the string change its content and length
+        let octets = child(&tree, 0);
+        tree_util::set_encapsulated(&mut tree, octets, &[0x02, 0x02, 0x01, 0x00]).unwrap();
+        let expected = [0x30, 0x0C, 0x04, 0x04, 0x02, 0x02, 0x01, 0x00, 0x03, 0x04, 0x00, 0x02, 0x01, 0x01];
+        assert_eq!(encode_tree(&tree, &Overrides::default()), expected);
+    }
+
+    #[test]
+    fn length_forms(){
+        assert_eq!(encode_length(0x7F), [0x7F]);
+        assert_eq!(encode_length(0x80), [0x81, 0x80]);
+        assert_eq!(encode_length(0x0100), [0x82, 0x01, 0x00]);
+    }
+}
@@ -0,0 +1,160 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{cms, tree_util, validate::Violation, State};
+
+// Properties allowed by the Ghostbusters vCard profile (RFC 6493 3)
+const ALLOWED: &[&str] = &["BEGIN", "VERSION", "FN", "N", "ORG", "ADR", "TEL", "EMAIL", "END"];
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct VCard{
+    #[serde(rename = "fn")]
+    pub full_name: Option<String>,
+    pub org: Option<String>,
+    pub adr: Option<String>, // Structured value, components separated by ';'
+    pub tel: Option<String>, // e.g. tel:+1-555-555-5555
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct GbrContent{
+    pub vcard: VCard,
+    pub raw: String,
+    pub issues: Vec<Violation>,
+}
+
+/// Content line of a vCard: property name, parameters including the leading ';' and value.
+#[derive(Debug, Clone)]
+struct Line{
+    name: String,
+    params: String,
+    value: String,
+}
+
+impl Line{
+    fn new(name: &str, value: &str) -> Self{
+        Line{ name: name.to_string(), params: String::new(), value: value.to_string() }
+    }
+
+    fn encode(&self) -> String{
+        fold(&format!("{}{}:{}", self.name, self.params, self.value.replace('\n', "\\n")))
+    }
+}
+
+/// Splits a vCard into content lines, folded lines are joined (RFC 6350 3.2).
+fn parse_lines(vcard: &str) -> Vec<Line>{
+    let mut unfolded: Vec<String> = vec![];
+    for l in vcard.split('\n').map(|l| l.strip_suffix('\r').unwrap_or(l)){
+        match (l.strip_prefix([' ', '\t']), unfolded.last_mut()){
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ if l.is_empty() => (),
+            _ => unfolded.push(l.to_string()),
+        }
+    }
+
+    unfolded.iter().map(|l| {
+        let (head, value) = l.split_once(':').unwrap_or((l, ""));
+        let (name, params) = head.find(';').map(|i| head.split_at(i)).unwrap_or((head, ""));
+        Line{ name: name.to_uppercase(), params: params.to_string(), value: value.replace("\\n", "\n") }
+    }).collect()
+}
+
+/// Folds lines longer than 75 octets.
+fn fold(line: &str) -> String{
+    let mut ret = String::new();
+    let mut len = 0;
+    for c in line.chars(){
+        if len + c.len_utf8() > 75{
+            ret.push_str("\r\n ");
+            len = 1;
+        }
+        ret.push(c);
+        len += c.len_utf8();
+    }
+    ret
+}
+
+/// Violations of the vCard profile as section and message.
+pub(crate) fn vcard_issues(vcard: &str) -> Vec<(&'static str, String)>{
+    let mut ret = vec![];
+    let mut check = |ok: bool, section: &'static str, message: &str| if !ok { ret.push((section, message.to_string())) };
+
+    let lines = parse_lines(vcard);
+    let first = lines.first().map(|l| format!("{}:{}", l.name, l.value));
+    let last = lines.last().map(|l| format!("{}:{}", l.name, l.value));
+    let count = |name: &str| lines.iter().filter(|l| l.name == name).count();
+
+    check(first.as_deref() == Some("BEGIN:VCARD"), "RFC 6493 3", "vCard must start with BEGIN:VCARD");
+    check(last.as_deref() == Some("END:VCARD"), "RFC 6493 3", "vCard must end with END:VCARD");
+    check(lines.get(1).map(|l| l.name == "VERSION" && l.value == "4.0").unwrap_or(false), "RFC 6493 3", "VERSION:4.0 must follow BEGIN:VCARD");
+    check(count("FN") == 1, "RFC 6493 3", "vCard must contain exactly one FN");
+    check(count("ADR") + count("TEL") + count("EMAIL") > 0, "RFC 6493 3", "vCard must contain at least one of ADR, TEL or EMAIL");
+    check(!vcard.contains('\n') || vcard.contains("\r\n"), "RFC 6350 3.2", "vCard lines must be delimited by CRLF");
+
+    for l in lines.iter().filter(|l| !ALLOWED.contains(&l.name.as_str())){
+        ret.push(("RFC 6493 3", format!("Property {} is not allowed", l.name)));
+    }
+    ret
+}
+
+#[wasm_bindgen]
+impl State{
+    /// Returns the vCard of a Ghostbusters record as JSON: the FN, ORG, ADR, TEL and EMAIL values,
+    /// the raw vCard and its violations of the RFC 6493 profile.
+    #[wasm_bindgen]
+    pub fn gbr_get(&self) -> Result<String, String>{
+        let econtent = self.signed_payload(cms::OID_CT_GBR, "Ghostbusters record")?;
+        let raw = String::from_utf8_lossy(&tree_util::string_payload(&self.tree, econtent, &self.overrides)).to_string();
+        let lines = parse_lines(&raw);
+        let value = |name: &str| lines.iter().find(|l| l.name == name).map(|l| l.value.clone());
+
+        let content = GbrContent{
+            vcard: VCard{
+                full_name: value("FN"),
+                org: value("ORG"),
+                adr: value("ADR"),
+                tel: value("TEL"),
+                email: value("EMAIL"),
+            },
+            issues: vcard_issues(&raw).into_iter().map(|(section, message)| Violation{ node: econtent, section: section.to_string(), message }).collect(),
+            raw,
+        };
+        Ok(serde_json::to_string(&content).unwrap())
+    }
+
+    /// Sets the fields of the vCard from JSON in the format of `gbr_get().vcard`. Existing properties
+    /// keep their parameters, missing fields are removed. Other properties are kept.
+    /// Digest and signatures are not updated, see `resign`.
+    #[wasm_bindgen]
+    pub fn gbr_set(&mut self, vcard: String) -> Result<(), String>{
+        let vcard: VCard = serde_json::from_str(&vcard).map_err(|e| format!("Invalid vCard: {}", e))?;
+        let econtent = self.signed_payload(cms::OID_CT_GBR, "Ghostbusters record")?;
+
+        let mut lines = parse_lines(&String::from_utf8_lossy(&tree_util::string_payload(&self.tree, econtent, &self.overrides)));
+        if lines.first().map(|l| l.name != "BEGIN").unwrap_or(true) || lines.last().map(|l| l.name != "END").unwrap_or(true){
+            lines = vec![Line::new("BEGIN", "VCARD"), Line::new("VERSION", "4.0"), Line::new("END", "VCARD")];
+        }
+
+        let fields = [("FN", vcard.full_name), ("ORG", vcard.org), ("ADR", vcard.adr), ("TEL", vcard.tel), ("EMAIL", vcard.email)];
+        for (name, value) in fields{
+            match (lines.iter().position(|l| l.name == name), value){
+                (Some(pos), Some(value)) => lines[pos].value = value,
+                (None, Some(value)) => {
+                    let end = lines.len() - 1;
+                    lines.insert(end, Line::new(name, &value));
+                }
+                (_, None) => lines.retain(|l| l.name != name),
+            }
+        }
+
+        self.gbr_set_raw(lines.iter().map(|l| l.encode() + "\r\n").collect())
+    }
+
+    /// Replaces the vCard with arbitrary text, e.g. to test invalid cards.
+    #[wasm_bindgen]
+    pub fn gbr_set_raw(&mut self, vcard: String) -> Result<(), String>{
+        let econtent = self.signed_payload(cms::OID_CT_GBR, "Ghostbusters record")?;
+        tree_util::set_string_payload(&mut self.tree, econtent, vcard.as_bytes())?;
+        self.overrides.retain_existing(&self.tree);
+        Ok(())
+    }
+}
@@ -0,0 +1,348 @@
+use cure_pp::repository_util::ObjectKey;
+use std::{fs, path::Path};
+use wasm_bindgen::prelude::*;
+
+use crate::{cms, encoder, keys, read_tar_gz, repo::{self, Repository, RepoOptions}, rrdp, seed::{self, Seed}, tak::{self, TaKey}, validate, RepoFiles, State};
+
+// Repositories read back from an archive, a directory or a snapshot. The keys of the CAs are
+// not part of a repository, so fixing manifests and CRLs signs the whole tree again with new
+// keys, from the trust anchor down, and the TAL gets the new key. Either both are fixed or
+// none, a single one would be signed with a key its CA no longer has.
+
+/// Object of an imported repository with its rsync URI.
+#[derive(Debug, Clone)]
+pub(crate) struct Published{
+    pub uri: String,
+    pub object: State,
+}
+
+/// Objects of an imported repository with the URIs of its TAL and its RRDP session.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Imported{
+    pub objects: Vec<Published>,
+    pub tal_uris: Vec<String>,
+    pub session: Option<(String, u64)>, // Session and serial of the snapshot
+    pub notification_uri: Option<String>,
+}
+
+/// A CA of the repository with the objects in its directory, by index.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ca{
+    pub index: usize,
+    pub uri: String,
+    pub manifest: Option<usize>,
+    pub crl: Option<usize>,
+    pub objects: Vec<usize>,
+    pub children: Vec<Ca>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Hierarchy{
+    pub cas: Vec<Ca>, // CAs that are not published by another CA, e.g. the trust anchor
+    pub other: Vec<usize>, // Objects outside of the directories of the CAs
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectInfo{
+    pub index: usize,
+    pub uri: String,
+    pub object_type: String,
+}
+
+fn parse(uri: &str, data: &[u8]) -> Result<Published, String>{
+    let tree = cure_asn1::interface::parse_tree(data, "").ok_or(format!("{} could not be parsed as ASN.1", uri))?;
+    let mut object = State::from_tree(tree);
+    object.label_rsc();
+    object.label_aspa();
+    Ok(Published{ uri: uri.to_string(), object })
+}
+
+fn host(uri: &str) -> Option<&str>{
+    uri.split_once("://").and_then(|(_, r)| r.split('/').next()).filter(|h| !h.is_empty())
+}
+
+impl Imported{
+    /// Objects of the snapshot if `files` has RRDP files, otherwise of the rsync directories. The
+    /// trust anchor certificate is taken from the path of the TAL.
+    fn from_files(files: &RepoFiles) -> Result<Imported, String>{
+        let tal = files.iter().find(|(name, _)| name == "ta.tal").or_else(|| files.iter().find(|(name, _)| name.ends_with(".tal")));
+        let tal_uris = tal.map(|(_, data)| tak::tal_uris(&String::from_utf8_lossy(data))).unwrap_or_default();
+        let rsync_uris = tal_uris.iter().filter(|u| u.starts_with("rsync://")).cloned().collect::<Vec<String>>();
+        let mut imported = Imported{ tal_uris, ..Imported::default() };
+
+        let mut objects = vec![];
+        if files.iter().any(|(name, _)| name.ends_with("notification.xml")){
+            let (snapshot, notification) = rrdp::read(files)?;
+            imported.session = Some((snapshot.session_id, snapshot.serial));
+            imported.notification_uri = Some(notification);
+            objects = snapshot.objects;
+            for uri in rsync_uris{
+                if let Some((_, data)) = files.iter().find(|(name, _)| uri.ends_with(name.as_str())){
+                    objects.push((uri, data.clone()));
+                }
+            }
+        }
+        else{
+            // Signed checklists are not published, they are next to the repository
+            let host = rsync_uris.first().and_then(|u| host(u)).unwrap_or("localhost").to_string();
+            for (name, data) in files.iter().filter(|(name, _)| !name.ends_with(".tal") && !name.ends_with(".sig")){
+                objects.push((format!("rsync://{}/{}", host, name.trim_start_matches('/')), data.clone()));
+            }
+        }
+
+        objects.sort_by(|a, b| a.0.cmp(&b.0));
+        objects.dedup_by(|a, b| a.0 == b.0);
+        if objects.is_empty(){
+            return Err("Repository has no objects".to_string());
+        }
+        imported.objects = objects.iter().map(|(uri, data)| parse(uri, data)).collect::<Result<_, _>>()?;
+        Ok(imported)
+    }
+
+    /// CA certificates with the objects in the directory of their SIA.
+    pub(crate) fn hierarchy(&self) -> Hierarchy{
+        let dirs: Vec<(usize, String)> = self.objects.iter().enumerate()
+            .filter_map(|(i, p)| validate::sia_uri(&p.object, validate::OID_AD_CA_REPOSITORY).map(|d| (i, format!("{}/", d.trim_end_matches('/')))))
+            .collect();
+        let members = |dir: &str, ca: usize| (0..self.objects.len())
+            .filter(|i| *i != ca)
+            .filter(|i| self.objects[*i].uri.strip_prefix(dir).map(|r| !r.contains('/')).unwrap_or(false))
+            .collect::<Vec<usize>>();
+
+        let mut placed = vec![false; self.objects.len()];
+        let roots = dirs.iter()
+            .filter(|(i, _)| !dirs.iter().any(|(ca, dir)| ca != i && members(dir, *ca).contains(i)))
+            .map(|(i, _)| *i)
+            .collect::<Vec<usize>>();
+
+        fn build(imported: &Imported, index: usize, dirs: &[(usize, String)], members: &dyn Fn(&str, usize) -> Vec<usize>, placed: &mut Vec<bool>) -> Ca{
+            placed[index] = true;
+            let object = &imported.objects[index];
+            let dir = &dirs.iter().find(|(i, _)| *i == index).unwrap().1;
+            let manifest_uri = validate::sia_uri(&object.object, validate::OID_AD_MANIFEST);
+            let mut ca = Ca{ index, uri: object.uri.clone(), manifest: None, crl: None, objects: vec![], children: vec![] };
+
+            for m in members(dir, index){
+                if placed[m]{
+                    continue;
+                }
+                let member = &imported.objects[m];
+                if dirs.iter().any(|(i, _)| *i == m){
+                    ca.children.push(build(imported, m, dirs, members, placed));
+                    continue;
+                }
+                placed[m] = true;
+                match member.object.object_type().as_str(){
+                    "mft" if ca.manifest.is_none() && manifest_uri.as_deref().map(|u| u == member.uri).unwrap_or(true) => ca.manifest = Some(m),
+                    "crl" if ca.crl.is_none() => ca.crl = Some(m),
+                    _ => ca.objects.push(m),
+                }
+            }
+            ca
+        }
+
+        let cas = roots.into_iter().map(|r| build(self, r, &dirs, &members, &mut placed)).collect();
+        Hierarchy{
+            cas,
+            other: (0..self.objects.len()).filter(|i| !placed[*i]).collect(),
+        }
+    }
+
+    /// The archive files of the repository, with manifests and CRLs fixed as in the options.
+    pub(crate) fn files(&self, options: &RepoOptions) -> Result<RepoFiles, String>{
+        let mut objects = self.objects.clone();
+        let hierarchy = self.hierarchy();
+
+        // Without the TAL there is no way to tell the trust anchor from the CAs a snapshot publishes
+        let ta = hierarchy.cas.iter()
+            .find(|ca| self.tal_uris.contains(&objects[ca.index].uri))
+            .ok_or("Repository has no TAL with its trust anchor certificate, it cannot be written out")?;
+        match (options.fix_manifest, options.fix_crl){
+            (true, true) => {
+                let mut seed = options.seed.map(|s| Seed::new(s, options.time.as_deref())).transpose()?;
+                reissue(&mut objects, ta, None, &mut seed)?;
+            }
+            (false, false) => {}
+            _ => return Err("The CA keys of an imported repository are unknown, set fixManifest and fixCrl both to true or both to false".to_string()),
+        }
+
+        let ta_cert = &objects[ta.index];
+        let cert = cms::certificate(&ta_cert.object.tree, ta_cert.object.tree.root_id)?;
+        let tal = tak::tal_text(&TaKey{
+            comments: vec![],
+            certificate_uris: self.tal_uris.clone(),
+            public_key: base64::encode(encoder::encode_element(&ta_cert.object.tree, cert.spki, &ta_cert.object.overrides)),
+        });
+
+        let session_id = match (options.session_id.clone(), self.session.as_ref(), options.seed){
+            (Some(s), _, _) => s,
+            (None, Some((s, _)), _) => s.clone(),
+            (None, None, Some(seed)) => seed::session_id(seed),
+            (None, None, None) => {
+                let mut random = [0u8; 8];
+                getrandom::getrandom(&mut random).map_err(|e| e.to_string())?;
+                seed::session_id(u64::from_be_bytes(random))
+            }
+        };
+        let serial = options.serial.or(self.session.as_ref().map(|(_, s)| s + 1)).unwrap_or(1);
+        let rrdp_uri = match (options.rrdp_uri.clone(), self.notification_uri.as_ref()){
+            (Some(uri), _) => uri,
+            (None, Some(uri)) => uri.trim_end_matches("notification.xml").to_string(),
+            (None, None) => format!("https://{}/data/rrdp/", host(&ta_cert.uri).unwrap_or("localhost")),
+        };
+
+        // The trust anchor certificate is next to the repository
+        let published = objects.iter()
+            .filter(|p| !self.tal_uris.contains(&p.uri))
+            .map(|p| (p.uri.clone(), p.object.encode()))
+            .collect();
+        let mut files = rrdp::write(&rrdp_uri, &session_id, serial, &published)?;
+        files.push((repo::ta_certificate_path(&tal), ta_cert.object.encode()));
+        files.push(("ta.tal".to_string(), tal.into_bytes()));
+        Ok(files)
+    }
+}
+
+fn new_key(seed: &mut Option<Seed>) -> Result<ObjectKey, String>{
+    match seed.as_mut(){
+        Some(seed) => seed.key(),
+        None => keys::load_key(None),
+    }
+}
+
+/// Signs the CA and everything below it with new keys, including manifest and CRL. Returns the
+/// old and new hash of its certificate for the manifest of the issuer.
+fn reissue(objects: &mut [Published], ca: &Ca, issuer: Option<(&State, &ObjectKey)>, seed: &mut Option<Seed>) -> Result<(String, String), String>{
+    let key = new_key(seed)?;
+    let mut cert = objects[ca.index].object.clone();
+    let old_cert = seed::hash(&cert);
+    cert.set_certificate_key(&key)?;
+    match issuer{
+        Some((parent, parent_key)) => cert.issue_certificate_from(parent, parent_key)?,
+        None => cert.sign_certificate_with(&key)?,
+    }
+    objects[ca.index].object = cert.clone();
+
+    let mut hashes = vec![];
+    for child in ca.children.iter(){
+        hashes.push(reissue(objects, child, Some((&cert, &key)), seed)?);
+    }
+
+    for i in ca.objects.iter(){
+        let mut object = objects[*i].object.clone();
+        let old = seed::hash(&object);
+        if cms::signed_object(&object.tree).is_ok(){
+            object.issue_from(&cert, &key, &new_key(seed)?)?;
+        }
+        else if cms::object_certificate(&object.tree).is_ok(){
+            object.issue_certificate_from(&cert, &key)?;
+        }
+        hashes.push((old, seed::hash(&object)));
+        objects[*i].object = object;
+    }
+
+    if let Some(i) = ca.crl{
+        let mut crl = objects[i].object.clone();
+        let old = seed::hash(&crl);
+        crl.issue_crl_from(&cert, &key)?;
+        hashes.push((old, seed::hash(&crl)));
+        objects[i].object = crl;
+    }
+
+    if let Some(i) = ca.manifest{
+        let mut mft = objects[i].object.clone();
+        mft.manifest_replace_hashes(&hashes)?;
+        mft.issue_from(&cert, &key, &new_key(seed)?)?;
+        objects[i].object = mft;
+    }
+    Ok((old_cert, seed::hash(&cert)))
+}
+
+/// Files below `dir` with their path relative to `root`.
+fn read_directory(root: &Path, dir: &Path, files: &mut RepoFiles) -> std::io::Result<()>{
+    for entry in fs::read_dir(dir)?{
+        let path = entry?.path();
+        if path.is_dir(){
+            read_directory(root, &path, files)?;
+        }
+        else{
+            let name = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+            files.push((name, fs::read(&path)?));
+        }
+    }
+    Ok(())
+}
+
+#[wasm_bindgen]
+impl Repository{
+    /// Reads an archive of `repositorify` (or any tar.gz with RRDP files or rsync directories and a TAL).
+    /// Its objects can be edited and written out again with `repositorify`. With the default options
+    /// manifests and CRLs are fixed, which signs everything again with new keys; with fixManifest
+    /// and fixCrl false the objects are written as they are. Fixing only one of them is an error.
+    #[wasm_bindgen]
+    pub fn from_tar_gz(data: Vec<u8>) -> Result<Repository, String>{
+        let files = read_tar_gz(&data).map_err(|e| format!("Invalid archive: {}", e))?;
+        Ok(Repository::imported(Imported::from_files(&files)?))
+    }
+
+    /// Reads the objects of an RRDP snapshot. It has no trust anchor certificate, the CAs it
+    /// publishes are the top of the hierarchy. Its objects can be edited, but without a TAL the
+    /// repository cannot be written out.
+    #[wasm_bindgen]
+    pub fn from_snapshot(xml: String) -> Result<Repository, String>{
+        let snapshot = rrdp::parse_snapshot(&xml)?;
+        let objects = snapshot.objects.iter().map(|(uri, data)| parse(uri, data)).collect::<Result<_, _>>()?;
+        Ok(Repository::imported(Imported{
+            objects,
+            session: Some((snapshot.session_id, snapshot.serial)),
+            ..Imported::default()
+        }))
+    }
+
+    /// Reads an extracted archive or an rsync directory, not available in the browser.
+    #[wasm_bindgen]
+    pub fn from_directory(path: String) -> Result<Repository, String>{
+        let root = Path::new(&path);
+        let mut files = vec![];
+        read_directory(root, root, &mut files).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(Repository::imported(Imported::from_files(&files)?))
+    }
+
+    /// Index, URI and type of the imported objects as JSON.
+    #[wasm_bindgen]
+    pub fn objects(&self) -> Result<String, String>{
+        let imported = self.imported.as_ref().ok_or("Repository is not imported")?;
+        let info = imported.objects.iter().enumerate().map(|(index, p)| ObjectInfo{
+            index,
+            uri: p.uri.clone(),
+            object_type: p.object.object_type(),
+        }).collect::<Vec<ObjectInfo>>();
+        Ok(serde_json::to_string(&info).unwrap())
+    }
+
+    /// The CAs of the imported repository as a tree with the indices of their objects, as JSON.
+    #[wasm_bindgen]
+    pub fn hierarchy(&self) -> Result<String, String>{
+        let imported = self.imported.as_ref().ok_or("Repository is not imported")?;
+        Ok(serde_json::to_string(&imported.hierarchy()).unwrap())
+    }
+
+    /// Copy of the imported object at `index`.
+    #[wasm_bindgen]
+    pub fn object(&self, index: usize) -> Result<State, String>{
+        let imported = self.imported.as_ref().ok_or("Repository is not imported")?;
+        imported.objects.get(index).map(|p| p.object.clone()).ok_or(format!("No object at index {}", index))
+    }
+
+    /// Replaces the imported object at `index` with a copy of `object`, its URI is kept.
+    #[wasm_bindgen]
+    pub fn set_object(&mut self, index: usize, object: &State) -> Result<(), String>{
+        let imported = self.imported.as_mut().ok_or("Repository is not imported")?;
+        let published = imported.objects.get_mut(index).ok_or(format!("No object at index {}", index))?;
+        published.object = object.clone();
+        Ok(())
+    }
+}
@@ -0,0 +1,703 @@
+use base64::{alphabet, engine::{fast_portable::{FastPortable, FastPortableConfig, PAD}, DecodePaddingMode}};
+use regex::Regex;
+use std::sync::OnceLock;
+
+use crate::{encode_oid_from_string, encoder::encode_length};
+
+// Textual input formats accepted by `State::new`. Formats are tried in order and
+// the first one whose detector matches decodes the input, so more specific
+// formats have to come before the generic hex and base64 ones.
+
+pub struct InputFormat{
+    pub name: &'static str,
+    detect: fn(&str) -> bool,
+    decode: fn(&str) -> Result<Vec<u8>, String>,
+}
+
+const FORMATS: &[InputFormat] = &[
+    InputFormat{ name: "pem", detect: is_pem, decode: decode_pem },
+    InputFormat{ name: "asn1parse", detect: is_asn1parse, decode: decode_asn1parse },
+    InputFormat{ name: "hexdump", detect: is_hexdump, decode: decode_hexdump },
+    InputFormat{ name: "xxd", detect: is_xxd, decode: decode_xxd },
+    InputFormat{ name: "byte_array", detect: is_byte_array, decode: decode_byte_array },
+    InputFormat{ name: "colon_hex", detect: is_colon_hex, decode: decode_colon_hex },
+    InputFormat{ name: "spaced_hex", detect: is_spaced_hex, decode: decode_spaced_hex },
+    InputFormat{ name: "hex", detect: is_hex, decode: decode_hex },
+    InputFormat{ name: "base64", detect: is_base64, decode: decode_base64 },
+    InputFormat{ name: "base64url", detect: is_base64_url, decode: decode_base64_url },
+];
+
+// Decoding accepts missing padding, encoding always pads unless asked otherwise
+const BASE64_CONFIG: FastPortableConfig = PAD.with_decode_padding_mode(DecodePaddingMode::Indifferent);
+pub const BASE64_STANDARD: FastPortable = FastPortable::from(&alphabet::STANDARD, BASE64_CONFIG);
+pub const BASE64_URL: FastPortable = FastPortable::from(&alphabet::URL_SAFE, BASE64_CONFIG);
+pub const BASE64_STANDARD_NO_PAD: FastPortable = FastPortable::from(&alphabet::STANDARD, BASE64_CONFIG.with_encode_padding(false));
+pub const BASE64_URL_NO_PAD: FastPortable = FastPortable::from(&alphabet::URL_SAFE, BASE64_CONFIG.with_encode_padding(false));
+
+// Line length of MIME base64 (RFC 2045)
+const MIME_LINE_LENGTH: usize = 76;
+
+/// Decodes textual input into bytes, returns the bytes and the name of the detected format.
+pub fn decode(data: &str) -> Result<(Vec<u8>, &'static str), String>{
+    let data = data.trim().replace("\r", "");
+
+    for format in FORMATS.iter(){
+        if (format.detect)(&data){
+            let decoded = (format.decode)(&data)?;
+            return Ok((decoded, format.name));
+        }
+    }
+
+    let names = FORMATS.iter().map(|f| f.name).collect::<Vec<&str>>().join(", ");
+    Err(format!("Invalid data, format not recognised (supported: {})", names))
+}
+
+fn is_pem(s: &str) -> bool{
+    if s.starts_with("---"){
+        return true;
+    }
+    false
+}
+
+fn decode_pem(s: &str) -> Result<Vec<u8>, String>{
+    let s = s.split("\n").collect::<Vec<&str>>();
+
+    if s.len() < 3{
+        return Err("Invalid data, looked like PEM but isnt".to_string());
+    }
+
+    // Remove first and last line to get rid of PEM Header / Footer
+    let s = &s[1..s.len() -1];
+
+    decode_base64(&s.join(""))
+}
+
+fn strip_hex_prefix(s: &str) -> &str{
+    s.strip_prefix("0x").unwrap_or(s)
+}
+
+fn is_hex(s: &str) -> bool {
+    static HEX: OnceLock<Regex> = OnceLock::new();
+    let hex_regex = HEX.get_or_init(|| Regex::new(r"^(?:[0-9A-Fa-f]{2})+$").unwrap());
+    hex_regex.is_match(&strip_hex_prefix(s).replace("\n", ""))
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, String>{
+    hex::decode(strip_hex_prefix(s).replace("\n", "")).map_err(|_| "Invalid hex data".to_string())
+}
+
+// Checks the alphabet and the padding of base64 with arbitrary line wrapping (e.g. MIME).
+// `extra` are the two alphabet specific characters.
+fn is_base64_with(s: &str, extra: [char; 2]) -> bool{
+    let compact = s.split_whitespace().collect::<String>();
+    let data = compact.trim_end_matches('=');
+    let padding = compact.len() - data.len();
+
+    if data.is_empty() || padding > 2 || data.len() % 4 == 1{
+        return false;
+    }
+    if padding > 0 && compact.len() % 4 != 0{
+        return false;
+    }
+    data.chars().all(|c| c.is_ascii_alphanumeric() || extra.contains(&c))
+}
+
+fn is_base64(s: &str) -> bool {
+    is_base64_with(s, ['+', '/'])
+}
+
+fn is_base64_url(s: &str) -> bool{
+    is_base64_with(s, ['-', '_'])
+}
+
+fn decode_base64(s: &str) -> Result<Vec<u8>, String>{
+    let compact = s.split_whitespace().collect::<String>();
+    base64::decode_engine(compact, &BASE64_STANDARD).map_err(|_| "Invalid base64 data".to_string())
+}
+
+fn decode_base64_url(s: &str) -> Result<Vec<u8>, String>{
+    let compact = s.split_whitespace().collect::<String>();
+    base64::decode_engine(compact, &BASE64_URL).map_err(|_| "Invalid base64url data".to_string())
+}
+
+/// Standard base64 wrapped into lines of 76 characters, separated by CRLF.
+pub fn encode_base64_mime(data: &[u8]) -> String{
+    let encoded = base64::encode_engine(data, &BASE64_STANDARD);
+    let lines = encoded.as_bytes().chunks(MIME_LINE_LENGTH)
+        .map(|l| String::from_utf8_lossy(l).to_string())
+        .collect::<Vec<String>>();
+    lines.join("\r\n")
+}
+
+fn parse_hex_tokens<'a>(tokens: impl Iterator<Item = &'a str>) -> Result<Vec<u8>, String>{
+    let mut ret = vec![];
+    for t in tokens{
+        let t = strip_hex_prefix(t);
+        if t.len() != 2{
+            return Err(format!("Invalid hex byte: {}", t));
+        }
+        ret.push(u8::from_str_radix(t, 16).map_err(|_| format!("Invalid hex byte: {}", t))?);
+    }
+    Ok(ret)
+}
+
+// 30 82 05 6b 30 82 ...
+fn is_spaced_hex(s: &str) -> bool{
+    let tokens = s.split_whitespace().collect::<Vec<&str>>();
+    tokens.len() > 1 && tokens.iter().all(|t| {
+        let t = strip_hex_prefix(t);
+        t.len() == 2 && t.chars().all(|c| c.is_ascii_hexdigit())
+    })
+}
+
+fn decode_spaced_hex(s: &str) -> Result<Vec<u8>, String>{
+    parse_hex_tokens(s.split_whitespace())
+}
+
+// 30:82:05:6b, also the wrapped form printed by openssl x509 -text
+fn is_colon_hex(s: &str) -> bool{
+    static COLON_HEX: OnceLock<Regex> = OnceLock::new();
+    let re = COLON_HEX.get_or_init(|| Regex::new(r"^[0-9A-Fa-f]{2}(?::[0-9A-Fa-f]{2})+:?$").unwrap());
+    re.is_match(&s.split_whitespace().collect::<String>())
+}
+
+fn decode_colon_hex(s: &str) -> Result<Vec<u8>, String>{
+    let joined = s.split_whitespace().collect::<String>();
+    parse_hex_tokens(joined.trim_end_matches(':').split(':'))
+}
+
+// Rust or C byte array literal, e.g. [0x30, 0x82] or unsigned char d[] = {48, 130};
+fn byte_array_content(s: &str) -> Option<&str>{
+    for (start, c) in s.char_indices(){
+        let close = match c{
+            '[' => ']',
+            '{' => '}',
+            _ => continue,
+        };
+        let end = match s[start + 1..].find(close){
+            Some(e) => start + 1 + e,
+            None => continue,
+        };
+        let content = &s[start + 1..end];
+        if parse_byte_array(content).is_ok(){
+            return Some(content);
+        }
+    }
+    None
+}
+
+fn parse_byte_array(content: &str) -> Result<Vec<u8>, String>{
+    let mut ret = vec![];
+    for (i, el) in content.split(',').enumerate(){
+        let el = el.trim();
+        if el.is_empty(){
+            // Trailing comma
+            if i > 0 && content.trim_end().ends_with(','){
+                continue;
+            }
+            return Err("Empty array element".to_string());
+        }
+
+        let el = el.trim_end_matches("u8").trim_end_matches('_');
+        let val = if let Some(h) = el.strip_prefix("0x").or(el.strip_prefix("0X")){
+            u8::from_str_radix(h, 16)
+        }
+        else{
+            el.parse::<u8>()
+        };
+        ret.push(val.map_err(|_| format!("Invalid array element: {}", el))?);
+    }
+    Ok(ret)
+}
+
+fn is_byte_array(s: &str) -> bool{
+    byte_array_content(s).is_some()
+}
+
+fn decode_byte_array(s: &str) -> Result<Vec<u8>, String>{
+    let content = byte_array_content(s).ok_or("Invalid byte array".to_string())?;
+    parse_byte_array(content)
+}
+
+// xxd: 00000000: 3082 056b 3082 0353 a003 0201 0202 1100  0..k0..S........
+fn is_xxd(s: &str) -> bool{
+    static XXD: OnceLock<Regex> = OnceLock::new();
+    let re = XXD.get_or_init(|| Regex::new(r"^[0-9a-fA-F]{7,8}: [0-9a-fA-F]{2}").unwrap());
+    re.is_match(s)
+}
+
+fn decode_xxd(s: &str) -> Result<Vec<u8>, String>{
+    let mut ret = vec![];
+    for line in s.lines().map(|l| l.trim()).filter(|l| !l.is_empty()){
+        let (_, rest) = line.split_once(": ").ok_or(format!("Invalid xxd line: {}", line))?;
+
+        // Hex groups are separated by single spaces, the ASCII column starts after two spaces
+        let hex_part = rest.split("  ").next().unwrap_or("");
+        let hex_str = hex_part.split_whitespace().collect::<String>();
+        ret.extend(hex::decode(&hex_str).map_err(|_| format!("Invalid xxd line: {}", line))?);
+    }
+    Ok(ret)
+}
+
+// Upper bound of the data a hexdump gives per character of the dump, '*' lines repeat data
+const MAX_SQUEEZE_FACTOR: usize = 64;
+
+// hexdump -C: 00000000  30 82 05 6b 30 82 03 53  a0 03 02 01 02 02 11 00  |0..k0..S........|
+fn is_hexdump(s: &str) -> bool{
+    static HEXDUMP: OnceLock<Regex> = OnceLock::new();
+    let re = HEXDUMP.get_or_init(|| Regex::new(r"^[0-9a-fA-F]{8}  [0-9a-fA-F]{2} ").unwrap());
+    re.is_match(s)
+}
+
+fn decode_hexdump(s: &str) -> Result<Vec<u8>, String>{
+    let mut ret: Vec<u8> = vec![];
+    let mut last_line: Vec<u8> = vec![];
+    let mut squeezed = false;
+
+    for line in s.lines().map(|l| l.trim()).filter(|l| !l.is_empty()){
+        // Identical lines are collapsed into a single '*'
+        if line == "*"{
+            squeezed = true;
+            continue;
+        }
+
+        let line = line.split('|').next().unwrap();
+        let mut tokens = line.split_whitespace();
+        let offset = tokens.next().ok_or("Invalid hexdump line".to_string())?;
+        let offset = usize::from_str_radix(offset, 16).map_err(|_| format!("Invalid hexdump offset: {}", offset))?;
+
+        if squeezed{
+            if last_line.is_empty(){
+                return Err("Invalid hexdump, '*' without preceding line".to_string());
+            }
+            // The offset is untrusted, a '*' never stands for more than a small multiple of the dump
+            if offset > s.len().saturating_mul(MAX_SQUEEZE_FACTOR){
+                return Err(format!("Invalid hexdump, offset {:08x} after '*' is too large", offset));
+            }
+            while ret.len() < offset{
+                ret.extend(last_line.iter());
+            }
+            squeezed = false;
+        }
+        if ret.len() != offset{
+            return Err(format!("Invalid hexdump, expected offset {:08x} but found {:08x}", ret.len(), offset));
+        }
+
+        last_line = parse_hex_tokens(tokens)?;
+        ret.extend(last_line.iter());
+    }
+    Ok(ret)
+}
+
+// openssl asn1parse [-i] [-dump]:
+//     0:d=0  hl=4 l=1387 cons: SEQUENCE
+//    10:d=3  hl=2 l=   1 prim:    INTEGER           :02
+// Only values that openssl prints in a reversible way can be restored, other
+// primitives need the hex dump lines of -dump.
+
+#[derive(Debug)]
+struct DumpEntry{
+    offset: usize,
+    depth: usize,
+    header_len: usize,
+    length: usize,
+    constructed: bool,
+    typ: String,
+    value: Option<String>,
+    hex_value: bool, // Value printed as [HEX DUMP]
+    dump: Vec<u8>,
+    children: Vec<DumpEntry>,
+}
+
+fn asn1parse_line_regex() -> &'static Regex{
+    static LINE: OnceLock<Regex> = OnceLock::new();
+    LINE.get_or_init(|| Regex::new(r"^\s*(\d+):d=\s*(\d+)\s+hl=\s*(\d+)\s+l=\s*(\d+|inf)\s+(prim|cons):\s*(.*)$").unwrap())
+}
+
+fn is_asn1parse(s: &str) -> bool{
+    asn1parse_line_regex().is_match(s.lines().next().unwrap_or(""))
+}
+
+fn decode_asn1parse(s: &str) -> Result<Vec<u8>, String>{
+    let line_re = asn1parse_line_regex();
+    static DUMP: OnceLock<Regex> = OnceLock::new();
+    let dump_re = DUMP.get_or_init(|| Regex::new(r"^\s*[0-9a-fA-F]{4} - (.*)$").unwrap());
+
+    let mut entries: Vec<DumpEntry> = vec![];
+    for line in s.lines().filter(|l| !l.trim().is_empty()){
+        if let Some(caps) = line_re.captures(line){
+            if &caps[4] == "inf"{
+                return Err("Indefinite lengths in asn1parse output are not supported".to_string());
+            }
+
+            let rest = caps[6].trim_start();
+            let (typ, value) = match rest.split_once(':'){
+                Some((t, v)) => (t.trim(), Some(v.to_string())),
+                None => (rest.trim(), None),
+            };
+            let (typ, hex_value) = match typ.strip_suffix("[HEX DUMP]"){
+                Some(t) => (t.trim().to_string(), true),
+                None => (typ.to_string(), false),
+            };
+
+            let number = |i: usize| caps[i].parse::<usize>().map_err(|_| format!("Invalid asn1parse line: {}", line.trim()));
+            entries.push(DumpEntry{
+                offset: number(1)?,
+                depth: number(2)?,
+                header_len: number(3)?,
+                length: number(4)?,
+                constructed: &caps[5] == "cons",
+                typ,
+                value,
+                hex_value,
+                dump: vec![],
+                children: vec![],
+            });
+        }
+        else if let Some(caps) = dump_re.captures(line){
+            let last = entries.last_mut().ok_or("Hex dump without element".to_string())?;
+            if caps[1].contains("<SPACES/NULS>"){
+                return Err(format!("Element at offset {} has an abbreviated hex dump", last.offset));
+            }
+
+            // 16 bytes per line, the middle separator is a dash, the ASCII column follows
+            let hex_part = caps[1].chars().take(47).collect::<String>().replace('-', " ");
+            let tokens = hex_part.split_whitespace().take_while(|t| t.len() == 2 && t.chars().all(|c| c.is_ascii_hexdigit()));
+            last.dump.extend(parse_hex_tokens(tokens)?);
+        }
+        else{
+            return Err(format!("Unrecognised asn1parse line: {}", line.trim()));
+        }
+    }
+
+    // Nest entries by their depth
+    let mut stack: Vec<DumpEntry> = vec![];
+    let mut roots: Vec<DumpEntry> = vec![];
+    for entry in entries{
+        while stack.last().map(|l| l.depth >= entry.depth).unwrap_or(false){
+            let done = stack.pop().unwrap();
+            match stack.last_mut(){
+                Some(p) => p.children.push(done),
+                None => roots.push(done),
+            }
+        }
+        stack.push(entry);
+    }
+    while let Some(done) = stack.pop(){
+        match stack.last_mut(){
+            Some(p) => p.children.push(done),
+            None => roots.push(done),
+        }
+    }
+
+    let mut ret = vec![];
+    for root in roots.iter(){
+        ret.extend(encode_dump_entry(root)?);
+    }
+    Ok(ret)
+}
+
+fn encode_dump_entry(entry: &DumpEntry) -> Result<Vec<u8>, String>{
+    let tag = tag_from_name(&entry.typ, entry.constructed).ok_or(format!("Unknown type '{}' at offset {}", entry.typ, entry.offset))?;
+
+    let content = if entry.constructed{
+        let mut c = vec![];
+        for child in entry.children.iter(){
+            c.extend(encode_dump_entry(child)?);
+        }
+        c
+    }
+    else{
+        primitive_content(entry)?
+    };
+
+    if content.len() != entry.length{
+        return Err(format!("Content of element at offset {} has {} bytes instead of {}", entry.offset, content.len(), entry.length));
+    }
+
+    // Keep the length form of the dump, e.g. non-minimal long form lengths
+    let length_width = entry.header_len.saturating_sub(tag.len());
+    let length = encode_length_with_width(entry.length, length_width)
+        .ok_or(format!("Header length of element at offset {} does not fit", entry.offset))?;
+
+    let mut ret = tag;
+    ret.extend(length);
+    ret.extend(content);
+    Ok(ret)
+}
+
+fn primitive_content(entry: &DumpEntry) -> Result<Vec<u8>, String>{
+    if entry.length == 0{
+        return Ok(vec![]);
+    }
+    if !entry.dump.is_empty(){
+        return Ok(entry.dump.clone());
+    }
+
+    let missing = || format!("Value of {} at offset {} is not printed, use asn1parse -dump", entry.typ, entry.offset);
+    let value = entry.value.as_ref().ok_or_else(missing)?;
+
+    if entry.hex_value{
+        return hex::decode(value.trim()).map_err(|_| format!("Invalid hex dump at offset {}", entry.offset));
+    }
+
+    match entry.typ.as_str(){
+        "BOOLEAN" => {
+            let v = value.trim().parse::<u8>().map_err(|_| format!("Invalid BOOLEAN at offset {}", entry.offset))?;
+            Ok(vec![v])
+        }
+        "INTEGER" | "ENUMERATED" => {
+            let (negative, digits) = match value.trim().strip_prefix('-'){
+                Some(d) => (true, d),
+                None => (false, value.trim()),
+            };
+            let digits = if digits.len() % 2 == 1 { format!("0{}", digits) } else { digits.to_string() };
+            let mut bytes = hex::decode(&digits).map_err(|_| format!("Invalid INTEGER at offset {}", entry.offset))?;
+
+            // openssl prints the magnitude, pad to the encoded length and negate in two's complement
+            while bytes.len() < entry.length{
+                bytes.insert(0, 0);
+            }
+            if negative{
+                let mut carry = true;
+                for b in bytes.iter_mut().rev(){
+                    *b = !*b;
+                    if carry{
+                        let (v, c) = b.overflowing_add(1);
+                        *b = v;
+                        carry = c;
+                    }
+                }
+            }
+            Ok(bytes)
+        }
+        "OBJECT" => {
+            let oid = oid_from_openssl_name(value.trim()).ok_or(format!("Unknown OID name '{}' at offset {}, use asn1parse -dump", value.trim(), entry.offset))?;
+            Ok(encode_oid_from_string(&oid))
+        }
+        "OCTET STRING" | "PRINTABLESTRING" | "IA5STRING" | "UTF8STRING" | "T61STRING" | "VISIBLESTRING" | "NUMERICSTRING"
+        | "UTCTIME" | "GENERALIZEDTIME" => {
+            Ok(value.as_bytes().to_vec())
+        }
+        _ => Err(missing()),
+    }
+}
+
+// Names openssl prints that differ from the ones in the cure_asn1 OID map
+const OPENSSL_OID_NAMES: &[(&str, &str)] = &[
+    ("X509v3 Subject Key Identifier", "2.5.29.14"),
+    ("X509v3 Key Usage", "2.5.29.15"),
+    ("X509v3 Basic Constraints", "2.5.29.19"),
+    ("X509v3 CRL Number", "2.5.29.20"),
+    ("X509v3 CRL Distribution Points", "2.5.29.31"),
+    ("X509v3 Certificate Policies", "2.5.29.32"),
+    ("X509v3 Authority Key Identifier", "2.5.29.35"),
+    ("X509v3 Extended Key Usage", "2.5.29.37"),
+    ("Authority Information Access", "1.3.6.1.5.5.7.1.1"),
+    ("Subject Information Access", "1.3.6.1.5.5.7.1.11"),
+    ("sbgp-ipAddrBlock", "1.3.6.1.5.5.7.1.7"),
+    ("sbgp-autonomousSysNum", "1.3.6.1.5.5.7.1.8"),
+    ("ipAddr-asNumber", "1.3.6.1.5.5.7.14.2"),
+    ("CA Issuers", "1.3.6.1.5.5.7.48.2"),
+    ("CA Repository", "1.3.6.1.5.5.7.48.5"),
+    ("RPKI Manifest", "1.3.6.1.5.5.7.48.10"),
+    ("Signed Object", "1.3.6.1.5.5.7.48.11"),
+    ("RPKI Notify", "1.3.6.1.5.5.7.48.13"),
+    ("pkcs7-signedData", "1.2.840.113549.1.7.2"),
+    ("sha256", "2.16.840.1.101.3.4.2.1"),
+    ("id-ct-routeOriginAuthz", "1.2.840.113549.1.9.16.1.24"),
+    ("id-ct-rpkiManifest", "1.2.840.113549.1.9.16.1.26"),
+    ("id-ct-rpkiGhostbusters", "1.2.840.113549.1.9.16.1.35"),
+];
+
+fn oid_from_openssl_name(name: &str) -> Option<String>{
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_digit() || c == '.'){
+        return Some(name.to_string());
+    }
+
+    if let Some((_, oid)) = OPENSSL_OID_NAMES.iter().find(|(n, _)| *n == name){
+        return Some(oid.to_string());
+    }
+
+    cure_asn1::tree_parser::rpki_oid_map().iter()
+        .find(|(_, n)| n.eq_ignore_ascii_case(name))
+        .map(|(oid, _)| oid.to_string())
+}
+
+fn tag_from_name(name: &str, constructed: bool) -> Option<Vec<u8>>{
+    let cons_bit = if constructed { 0x20 } else { 0x00 };
+
+    static CLASS: OnceLock<Regex> = OnceLock::new();
+    let class_re = CLASS.get_or_init(|| Regex::new(r"^(cont|appl|priv) \[\s*(\d+)\s*\]$").unwrap());
+    if let Some(caps) = class_re.captures(name){
+        let class = match &caps[1]{
+            "appl" => 0x40,
+            "cont" => 0x80,
+            _ => 0xC0,
+        };
+        return Some(encode_tag(class | cons_bit, caps[2].parse().ok()?));
+    }
+
+    static UNIVERSAL: OnceLock<Regex> = OnceLock::new();
+    let universal_re = UNIVERSAL.get_or_init(|| Regex::new(r"^<ASN1 (\d+)>$").unwrap());
+    if let Some(caps) = universal_re.captures(name){
+        return Some(encode_tag(cons_bit, caps[1].parse().ok()?));
+    }
+
+    let number = match name{
+        "BOOLEAN" => 1,
+        "INTEGER" => 2,
+        "BIT STRING" => 3,
+        "OCTET STRING" => 4,
+        "NULL" => 5,
+        "OBJECT" => 6,
+        "OBJECT DESCRIPTOR" => 7,
+        "EXTERNAL" => 8,
+        "REAL" => 9,
+        "ENUMERATED" => 10,
+        "UTF8STRING" => 12,
+        "SEQUENCE" => 16,
+        "SET" => 17,
+        "NUMERICSTRING" => 18,
+        "PRINTABLESTRING" => 19,
+        "T61STRING" => 20,
+        "VIDEOTEXSTRING" => 21,
+        "IA5STRING" => 22,
+        "UTCTIME" => 23,
+        "GENERALIZEDTIME" => 24,
+        "GRAPHICSTRING" => 25,
+        "VISIBLESTRING" => 26,
+        "GENERALSTRING" => 27,
+        "UNIVERSALSTRING" => 28,
+        "BMPSTRING" => 30,
+        _ => return None,
+    };
+    Some(encode_tag(cons_bit, number))
+}
+
+fn encode_tag(class_and_form: u8, number: u32) -> Vec<u8>{
+    if number < 31{
+        return vec![class_and_form | number as u8];
+    }
+
+    let mut ret = vec![class_and_form | 0x1F];
+    let mut groups = vec![];
+    let mut n = number;
+    while n > 0{
+        groups.push((n & 0x7F) as u8);
+        n >>= 7;
+    }
+    for (i, g) in groups.iter().rev().enumerate(){
+        ret.push(if i + 1 < groups.len() { g | 0x80 } else { *g });
+    }
+    ret
+}
+
+fn encode_length_with_width(length: usize, width: usize) -> Option<Vec<u8>>{
+    let minimal = encode_length(length);
+    if width == minimal.len(){
+        return Some(minimal);
+    }
+    if width < 2 || width - 1 > std::mem::size_of::<usize>(){
+        return None;
+    }
+
+    let bytes = length.to_be_bytes();
+    let num = width - 1;
+    if bytes[..bytes.len() - num].iter().any(|b| *b != 0){
+        return None;
+    }
+
+    let mut ret = vec![0x80 | num as u8];
+    ret.extend_from_slice(&bytes[bytes.len() - num..]);
+    Some(ret)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    // SEQUENCE { INTEGER 5 }
+    const DER: [u8; 5] = [0x30, 0x03, 0x02, 0x01, 0x05];
+
+    fn assert_decodes(input: &str, format: &str){
+        assert_eq!(decode(input).unwrap(), (DER.to_vec(), format), "{}", input);
+    }
+
+    #[test]
+    fn hex(){
+        assert_decodes("3003020105", "hex");
+        assert_decodes("0x3003020105", "hex");
+        assert_decodes("30 03 02 01 05", "spaced_hex");
+        assert_decodes("30:03:02:01:05", "colon_hex");
+        assert_decodes("30:03:\n    02:01:05", "colon_hex");
+    }
+
+    #[test]
+    fn byte_arrays(){
+        assert_decodes("[0x30, 0x03, 0x02, 0x01, 0x05]", "byte_array");
+        assert_decodes("let der: [u8; 5] = [0x30, 0x03, 0x02, 0x01, 0x05,];", "byte_array");
+        assert_decodes("unsigned char der[] = {48, 3, 2, 1, 5};", "byte_array");
+    }
+
+    #[test]
+    fn dumps(){
+        assert_decodes("00000000: 3003 0201 05                             0....", "xxd");
+        assert_decodes("00000000  30 03 02 01 05                                    |0....|\n00000005", "hexdump");
+    }
+
+    #[test]
+    fn squeezed_hexdump(){
+        let dump = "00000000  04 20 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |. ..............|\n\
+                    00000010  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|\n\
+                    *\n\
+                    00000020  00 00                                             |..|\n\
+                    00000022";
+        let mut expected = vec![0x04, 0x20];
+        expected.extend([0; 32]);
+        assert_eq!(decode(dump).unwrap(), (expected, "hexdump"));
+    }
+
+    #[test]
+    fn hostile_dumps(){
+        let dump = "00000000  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|\n*\nffffffff";
+        assert!(decode_hexdump(dump).is_err());
+
+        let dump = "    0:d=0  hl=2 l=99999999999999999999999 cons: SEQUENCE";
+        assert!(decode(dump).unwrap_err().starts_with("Invalid asn1parse line"));
+    }
+
+    #[test]
+    fn asn1parse(){
+        let dump = "    0:d=0  hl=2 l=   3 cons: SEQUENCE\n    2:d=1  hl=2 l=   1 prim:  INTEGER           :05";
+        assert_decodes(dump, "asn1parse");
+
+        // Long form lengths of the dump are kept
+        let dump = "    0:d=0  hl=3 l=   3 cons: SEQUENCE\n    3:d=1  hl=2 l=   1 prim:  INTEGER           :-01";
+        assert_eq!(decode(dump).unwrap(), (vec![0x30, 0x81, 0x03, 0x02, 0x01, 0xFF], "asn1parse"));
+    }
+
+    #[test]
+    fn base64_variants(){
+        assert_decodes("MAMCAQU=", "base64");
+        assert_decodes("MAMCAQU", "base64");
+        assert_decodes("-----BEGIN DATA-----\nMAMCAQU=\n-----END DATA-----", "pem");
+
+        // OCTET STRING { fb ff } needs the alphabet specific characters
+        assert_eq!(decode("BAL7/w==").unwrap(), (vec![0x04, 0x02, 0xFB, 0xFF], "base64"));
+        assert_eq!(decode("BAL7_w").unwrap(), (vec![0x04, 0x02, 0xFB, 0xFF], "base64url"));
+    }
+
+    #[test]
+    fn mime_round_trip(){
+        let data = (0..=255).collect::<Vec<u8>>();
+        let mime = encode_base64_mime(&data);
+        assert!(mime.lines().all(|l| l.trim_end().len() <= MIME_LINE_LENGTH));
+        assert_eq!(decode(&mime).unwrap(), (data, "base64"));
+    }
+
+    #[test]
+    fn unknown_format(){
+        assert!(decode("not asn.1 at all!").is_err());
+    }
+}
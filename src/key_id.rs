@@ -0,0 +1,104 @@
+use cure_asn1::tree_parser::Tree;
+use wasm_bindgen::prelude::*;
+
+use crate::{cms, der, encoder::Overrides, keys, tree_util, State};
+
+/// Key identifiers of the certificate of an object (RFC 6487 4.8.2 and 4.8.3), as hex.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct KeyIdentifiers{
+    pub certificate: usize,
+    pub ski_node: Option<usize>, // extnValue of the SubjectKeyIdentifier extension
+    pub ski: Option<String>,
+    pub expected_ski: String, // SHA-1 of the subjectPublicKey bits
+    pub ski_valid: bool,
+    pub aki_node: Option<usize>, // extnValue of the AuthorityKeyIdentifier extension
+    pub aki: Option<String>,
+}
+
+#[wasm_bindgen]
+impl State{
+    /// Returns SKI and AKI of the certificate (or EE certificate) as JSON, together with the SKI
+    /// that matches the current public key.
+    #[wasm_bindgen]
+    pub fn get_key_identifiers(&self) -> Result<String, String>{
+        Ok(serde_json::to_string(&self.key_identifiers()?).unwrap())
+    }
+
+    /// Recomputes the SKI from the current public key and writes it into the SKI extension
+    /// and, for signed objects, into the SignerIdentifier.
+    #[wasm_bindgen]
+    pub fn fix_key_identifiers(&mut self) -> Result<String, String>{
+        let ids = self.key_identifiers()?;
+        let key_id = hex::decode(&ids.expected_ski).unwrap();
+
+        let ski_node = ids.ski_node.ok_or("Certificate has no SubjectKeyIdentifier extension")?;
+        tree_util::set_string_payload(&mut self.tree, ski_node, &der::tlv(0x04, &key_id))?;
+
+        if let Ok(obj) = cms::signed_object(&self.tree){
+            let signer = cms::signer_info(&self.tree, obj.signer_info)?;
+            if tree_util::tag(&self.tree, signer.sid) == 0x80{
+                tree_util::set_content(&mut self.tree, signer.sid, key_id);
+            }
+        }
+
+        self.overrides.retain_existing(&self.tree);
+        self.get_key_identifiers()
+    }
+
+    /// Sets the AKI of this object to the SKI of `parent`, e.g. an EE certificate to its CA.
+    /// Uses the SKI extension of the parent if present, otherwise the one computed from its key.
+    #[wasm_bindgen]
+    pub fn link_to_parent(&mut self, parent: &State) -> Result<String, String>{
+        let parent_ids = parent.key_identifiers()?;
+        let parent_ski = parent_ids.ski.unwrap_or(parent_ids.expected_ski);
+
+        let ids = self.key_identifiers()?;
+        let aki_node = ids.aki_node.ok_or("Certificate has no AuthorityKeyIdentifier extension")?;
+
+        let aki = der::tlv(0x30, &der::tlv(0x80, &hex::decode(&parent_ski).unwrap()));
+        tree_util::set_string_payload(&mut self.tree, aki_node, &aki)?;
+
+        self.overrides.retain_existing(&self.tree);
+        self.get_key_identifiers()
+    }
+}
+
+impl State{
+    pub(crate) fn key_identifiers(&self) -> Result<KeyIdentifiers, String>{
+        let cert_id = cms::object_certificate(&self.tree)?;
+        let cert = cms::certificate(&self.tree, cert_id)?;
+
+        let bits = tree_util::child(&self.tree, cert.spki, 1, "subjectPublicKey")?;
+        let expected = hex::encode(keys::key_identifier(&tree_util::string_payload(&self.tree, bits, &self.overrides)));
+
+        let ski_node = cert.extensions.and_then(|e| cms::extension_value(&self.tree, e, cms::OID_SKI));
+        let aki_node = cert.extensions.and_then(|e| cms::extension_value(&self.tree, e, cms::OID_AKI));
+
+        let ski = ski_node.and_then(|n| key_id_from_extension(&self.tree, n, &self.overrides, None));
+        let aki = aki_node.and_then(|n| key_id_from_extension(&self.tree, n, &self.overrides, Some(0x80)));
+
+        Ok(KeyIdentifiers{
+            certificate: cert_id,
+            ski_node,
+            ski_valid: ski.as_deref() == Some(expected.as_str()),
+            ski,
+            expected_ski: expected,
+            aki_node,
+            aki,
+        })
+    }
+}
+
+/// Key identifier from an extnValue. The SKI is an OCTET STRING, the AKI a SEQUENCE
+/// holding it as `[0] keyIdentifier`.
+fn key_id_from_extension(tree: &Tree, ext_value: usize, overrides: &Overrides, inner_tag: Option<u8>) -> Option<String>{
+    let value = tree_util::parse_encapsulated(&tree_util::string_payload(tree, ext_value, overrides)).ok()?;
+    let root = &value.tokens[&value.root_id];
+
+    let key_id = match inner_tag{
+        Some(t) => &value.tokens[&tree_util::child_by_tag(&value, value.root_id, t)?].data,
+        None if root.tag_u == 0x04 => &root.data,
+        None => return None,
+    };
+    Some(hex::encode(key_id))
+}
@@ -0,0 +1,98 @@
+use cure_pp::repository_util::{self, ObjectKey};
+use p256::{ecdsa::SigningKey, elliptic_curve::sec1::ToEncodedPoint};
+use rsa::{pkcs1::DecodeRsaPrivateKey, RsaPrivateKey};
+use sha1::{Digest, Sha1};
+
+use crate::der;
+
+// Key handling on top of cure_pp. Keys are either taken from the cached key
+// pool or given by the user as base64 encoded PKCS#1 private key. P-256 keys
+// for BGPsec router certificates are generated here.
+
+pub const OID_RSA_ENCRYPTION: &str = "1.2.840.113549.1.1.1";
+pub const OID_EC_PUBLIC_KEY: &str = "1.2.840.10045.2.1";
+pub const OID_SECP256R1: &str = "1.2.840.10045.3.1.7";
+
+/// Returns the user supplied key, or a random one from the key pool.
+pub fn load_key(key: Option<&str>) -> Result<ObjectKey, String>{
+    match key{
+        Some(k) if !k.trim().is_empty() => {
+            let k = k.lines().filter(|l| !l.starts_with("-----")).collect::<String>();
+            let der = base64::decode(&k).map_err(|_| "Private key must be base64 or PEM encoded".to_string())?;
+            if RsaPrivateKey::from_pkcs1_der(&der).is_err(){
+                return Err("Private key must be an RSA private key in PKCS#1".to_string());
+            }
+            Ok(repository_util::parse_cached_key(&k))
+        }
+        _ => {
+            let conf = repository_util::create_default_config();
+            Ok(repository_util::load_random_key(&conf).1)
+        }
+    }
+}
+
+/// DER encoded SubjectPublicKeyInfo of the key.
+pub fn subject_public_key_info(key: &ObjectKey) -> Vec<u8>{
+    let pub_key = key.get_pub_key();
+
+    // Depending on the key type this is already a SubjectPublicKeyInfo or only the RSAPublicKey
+    if is_subject_public_key_info(&pub_key){
+        return pub_key;
+    }
+
+    let mut algorithm = der::tlv(0x06, &crate::encode_oid_from_string(OID_RSA_ENCRYPTION));
+    algorithm.extend(der::tlv(0x05, &[]));
+
+    let mut bits = vec![0];
+    bits.extend(pub_key);
+
+    let mut content = der::tlv(0x30, &algorithm);
+    content.extend(der::tlv(0x03, &bits));
+    der::tlv(0x30, &content)
+}
+
+fn is_subject_public_key_info(data: &[u8]) -> bool{
+    // SEQUENCE { SEQUENCE { OID ... } ... }, an RSAPublicKey starts with an INTEGER instead
+    let outer = match der::parse_header(data, 0){
+        Ok(h) if h.tag == [0x30] => h,
+        _ => return false,
+    };
+    match der::parse_header(data, outer.header_len){
+        Ok(inner) => inner.tag == [0x30] && data.get(outer.header_len + inner.header_len) == Some(&0x06),
+        Err(_) => false,
+    }
+}
+
+/// Key identifier as defined in RFC 6487 4.8.2, the SHA-1 hash of the subjectPublicKey bits.
+pub fn key_identifier(public_key_bits: &[u8]) -> Vec<u8>{
+    Sha1::digest(public_key_bits).to_vec()
+}
+
+/// New P-256 key pair as private scalar and uncompressed public point.
+pub fn generate_p256() -> (Vec<u8>, Vec<u8>){
+    let key = SigningKey::random(&mut rand_core::OsRng);
+    let point = key.verifying_key().to_encoded_point(false);
+    (key.to_bytes().to_vec(), point.as_bytes().to_vec())
+}
+
+/// Checks that `point` is an uncompressed point on P-256, the form RFC 8208 3.1 requires.
+pub fn check_p256_point(point: &[u8]) -> Result<(), String>{
+    if point.len() != 65 || point[0] != 0x04{
+        return Err("Public key must be an uncompressed P-256 point of 65 bytes starting with 04".to_string());
+    }
+    p256::PublicKey::from_sec1_bytes(point).map_err(|_| "Public key is not a point on P-256".to_string())?;
+    Ok(())
+}
+
+/// DER encoded SubjectPublicKeyInfo of an EC public point on P-256 (RFC 8208 3.1).
+pub fn ec_subject_public_key_info(point: &[u8]) -> Vec<u8>{
+    let mut algorithm = der::tlv(0x06, &crate::encode_oid_from_string(OID_EC_PUBLIC_KEY));
+    algorithm.extend(der::tlv(0x06, &crate::encode_oid_from_string(OID_SECP256R1)));
+
+    let mut bits = vec![0];
+    bits.extend(point);
+
+    let mut content = der::tlv(0x30, &algorithm);
+    content.extend(der::tlv(0x03, &bits));
+    der::tlv(0x30, &content)
+}
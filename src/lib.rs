@@ -1,14 +1,42 @@
 use chrono::Utc;
-use cure_pp::{cure_object::CureObject, cure_repo::{self, new_repo}, repository_util::{self, load_random_key, random_fname}};
+use cure_pp::cure_repo::{self, CureRepository};
 use regex::Regex;
 use tar::Builder;
 use wasm_bindgen::prelude::*;
 use cure_asn1::{rpki::ObjectType, tree_parser::{self, Tree, Types}};
-use std::{fs, io::Cursor};
+use encoder::Overrides;
+use recovery::ParseIssue;
+use repo::RepoOptions;
+use std::{fs, io::{Cursor, Read}};
 
-use flate2::write::GzEncoder;
+use flate2::{read::GzDecoder, write::GzEncoder};
 use flate2::Compression;
 // mod cert; 
+mod aspa;
+mod cert_resources;
+mod cms;
+mod crl;
+mod der;
+mod digest;
+mod encoder;
+mod gbr;
+mod import;
+mod input;
+mod key_id;
+mod keys;
+mod manifest;
+mod recovery;
+mod repo;
+mod resign;
+mod resources;
+mod roa;
+mod router;
+mod rrdp;
+mod rsc;
+mod seed;
+mod tak;
+mod tree_util;
+mod validate;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Node{
@@ -20,10 +48,13 @@ pub struct Node{
     pub children: Vec<usize>,
     pub parent: usize,
     pub edited: bool,
+    pub encapsulated: bool, // OCTET STRING or BIT STRING shown as expanded subtree
+    pub raw: Option<String>, // Hex bytes emitted instead of the node
+    pub trailing: Option<String>, // Hex bytes emitted after the node
 }
 
 
-pub fn encode_node(tree: &Tree, node_id: usize) -> Vec<Node>{
+pub fn encode_node(tree: &Tree, node_id: usize, overrides: &Overrides) -> Vec<Node>{
     let mut nodes = vec![];
 
     let token = &tree.tokens[&node_id];
@@ -37,35 +68,25 @@ pub fn encode_node(tree: &Tree, node_id: usize) -> Vec<Node>{
         children: token.children.clone(),
         parent: token.parent,
         edited: token.manipulated,
+        encapsulated: is_expandable(token.tag_u) && !token.children.is_empty(),
+        raw: overrides.nodes.get(&node_id).map(hex::encode),
+        trailing: overrides.trailing.get(&node_id).map(hex::encode),
     };
 
     nodes.push(node);
     for child in token.children.iter(){
-        nodes.extend(encode_node(tree, *child));
+        nodes.extend(encode_node(tree, *child, overrides));
     }
 
     nodes
 }
 
-pub fn encode_tree(tree: &Tree) -> Vec<Node>{
-    encode_node(tree, tree.root_id)
+pub fn encode_tree(tree: &Tree, overrides: &Overrides) -> Vec<Node>{
+    encode_node(tree, tree.root_id, overrides)
 }
 
-fn is_hex(s: &str) -> bool {
-    let hex_regex = Regex::new(r"^(?:[0-9A-Fa-f]{2})+$").unwrap();
-    hex_regex.is_match(s)
-}
-
-fn is_base64(s: &str) -> bool {
-    let base64_regex = Regex::new(r"^(?:[A-Za-z0-9+/]{4}\s*)*(?:[A-Za-z0-9+/]{2}==\s*|[A-Za-z0-9+/]{3}=\s*)?$").unwrap();
-    base64_regex.is_match(s)
-}
-
-fn is_pem(s: &str) -> bool{
-    if s.starts_with("---"){
-        return true;
-    }
-    false
+fn is_expandable(tag: u8) -> bool{
+    encoder::is_octet_string(tag) || encoder::is_bit_string(tag)
 }
 
 fn create_tar_gz_in_memory(files: Vec<(String, Vec<u8>)>) -> std::io::Result<Vec<u8>> {
@@ -94,6 +115,22 @@ fn create_tar_gz_in_memory(files: Vec<(String, Vec<u8>)>) -> std::io::Result<Vec
     Ok(cursor.into_inner())              // Return the buffer
 }
 
+fn read_tar_gz(data: &[u8]) -> std::io::Result<RepoFiles>{
+    let mut archive = tar::Archive::new(GzDecoder::new(data));
+    let mut files = vec![];
+    for entry in archive.entries()?{
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file(){
+            continue;
+        }
+        let path = entry.path()?.to_string_lossy().to_string();
+        let mut contents = vec![];
+        entry.read_to_end(&mut contents)?;
+        files.push((path, contents));
+    }
+    Ok(files)
+}
+
 // fn create_zip_in_memory(files: Vec<(String, Vec<u8>)>) -> zip::result::ZipResult<Vec<u8>> {
 //     let buffer = Vec::new();
 //     let cursor = Cursor::new(buffer);
@@ -113,6 +150,9 @@ fn create_tar_gz_in_memory(files: Vec<(String, Vec<u8>)>) -> std::io::Result<Vec
 // }
 
 
+// Path and content of the files of a repository
+type RepoFiles = Vec<(String, Vec<u8>)>;
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[wasm_bindgen]
 pub struct RrdpEntry{
@@ -142,61 +182,109 @@ impl RrdpEntry {
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct State{
     tree: Tree, 
+    #[serde(default)]
+    overrides: Overrides,
+    #[serde(default)]
+    parse_issues: Vec<ParseIssue>,
+    #[serde(default)]
+    input_format: String,
 }
 
-#[wasm_bindgen]
 impl State{
-    #[wasm_bindgen(constructor)]
-    pub fn new(data: String) -> Result<State, String>{
-        // Takes either hex or base64 encoded data is input
-        let mut data = data.trim().to_string();
-        if data.starts_with("0x"){
-            data = data[2..].to_string();
-        }
-        data = data.replace("\r", "");
-
-        if is_pem(&data){
-            let s = data.split("\n").collect::<Vec<&str>>();
-
-            if s.len() < 3{
-                return Err("Invalid data, looked like PEM but isnt".to_string());
-            }
-
-            // Remove first and last line to get rid of PEM Header / Footer
-            let s = &s[1..s.len() -1];
-
-            data = s.join("");
-            
+    fn from_tree(tree: Tree) -> State{
+        State{
+            tree,
+            overrides: Overrides::default(),
+            parse_issues: vec![],
+            input_format: String::new(),
         }
+    }
 
-        data = data.replace("\n", "");
-
+    /// Encoding of the tree including raw overrides and trailing bytes.
+    pub(crate) fn encode(&self) -> Vec<u8>{
+        encoder::encode_tree(&self.tree, &self.overrides)
+    }
 
-        if is_hex(&data) == false && is_base64(&data) == false{
-            return Err("Invalid data, neither hex nor base64".to_string());
+    /// Tree that cure encodes to the same bytes as `encode`, for objects that are handed to cure.
+    /// Raw bytes, trailing bytes and expanded strings are only part of the bytes, so the tree is
+    /// parsed again from them. Overrides are not repository-safe: cure has no tree for bytes that
+    /// do not parse back to the same encoding, such objects are rejected with an error.
+    pub(crate) fn encoded_tree(&self) -> Result<Tree, String>{
+        let data = self.encode();
+        if self.tree.encode() == data{
+            return Ok(self.tree.clone());
         }
 
-        let decoded;
-        if is_hex(&data){
-            decoded = hex::decode(&data).map_err(|_| "Invalid hex data".to_string());
+        let mut tree = cure_asn1::interface::parse_tree(&data, "")
+            .ok_or("Object with raw or trailing bytes is not valid ASN.1 and cannot be put into a repository".to_string())?;
+        if encoder::encode_tree(&tree, &Overrides::default()) != data{
+            return Err("Object with raw or trailing bytes does not parse back to the same encoding and cannot be put into a repository".to_string());
         }
-        else{
-            decoded = base64::decode(&data).map_err(|_| "Invalid base64 data".to_string());
+        if tree.obj_type.is_empty() || tree.obj_type == "unknown"{
+            tree.obj_type = self.tree.obj_type.clone();
         }
+        Ok(tree)
+    }
 
-        if decoded.is_err(){
-            return Err(decoded.err().unwrap());
+    /// Type name as used by cure ("roa", "asa", ...), derived from the content if the parser did not set one.
+    pub(crate) fn object_type(&self) -> String{
+        if !self.tree.obj_type.is_empty() && self.tree.obj_type != "unknown"{
+            return self.tree.obj_type.clone();
         }
+        cms::object_type_name(&self.tree).unwrap_or("unknown").to_string()
+    }
+}
 
-        let tree = cure_asn1::interface::parse_tree(&decoded.unwrap(), "");
+#[wasm_bindgen]
+impl State{
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: String) -> Result<State, String>{
+        let (decoded, format) = input::decode(&data)?;
+        let tree = cure_asn1::interface::parse_tree(&decoded, "");
 
         if tree.is_none(){
-            return Err("Invalid data3".to_string());
+            return Err("Invalid data, could not be parsed as ASN.1".to_string());
         }
 
+        let mut state = State::from_tree(tree.unwrap());
+        state.input_format = format.to_string();
+        state.label_rsc();
+        state.label_aspa();
+        Ok(state)
+    }
+
+    /// Like `new`, but recovers as much as possible from truncated or malformed input.
+    /// Unparseable regions become opaque error nodes, see `get_parse_issues`.
+    #[wasm_bindgen]
+    pub fn new_lenient(data: String) -> Result<State, String>{
+        let (decoded, format) = input::decode(&data)?;
+        if let Some(tree) = cure_asn1::interface::parse_tree(&decoded, ""){
+            let mut state = State::from_tree(tree);
+            state.input_format = format.to_string();
+            state.label_rsc();
+            state.label_aspa();
+            return Ok(state);
+        }
+
+        let (tree, overrides, parse_issues) = recovery::recover(&decoded)?;
         Ok(State{
-            tree: tree.unwrap(),
-        }) 
+            tree,
+            overrides,
+            parse_issues,
+            input_format: format.to_string(),
+        })
+    }
+
+    /// Name of the textual format the input was given in, e.g. "pem", "xxd" or "byte_array".
+    #[wasm_bindgen]
+    pub fn get_input_format(&self) -> String{
+        self.input_format.clone()
+    }
+
+    /// Where the structure of the input broke, as JSON list of offsets, reasons and lengths.
+    #[wasm_bindgen]
+    pub fn get_parse_issues(&self) -> String{
+        serde_json::to_string(&self.parse_issues).unwrap()
     }
 
     #[wasm_bindgen]
@@ -231,74 +319,41 @@ impl State{
 
 
     
+    /// Repository with this object published in it. Fails for objects whose raw or trailing bytes
+    /// cure cannot take, see `encoded_tree`.
     #[wasm_bindgen]
-    pub fn repositorify(&self) -> Vec<u8>{
-        let (repo_files, tal, ca_cert) = self.into_rpki_repo();
-        let mut files = repo_files.clone();
-        files.push(("ta.tal".to_string(), tal));
-        files.push(("data/repo/ta/ta.cer".to_string(), ca_cert));
-        create_tar_gz_in_memory(files).unwrap_or_default()
+    pub fn repositorify(&self) -> Result<Vec<u8>, String>{
+        self.repositorify_with(String::new())
     }
 
-    // (Snapshot and Notification, TAL, CA Cert)
-    fn into_rpki_repo(&self) -> (Vec<(String, Vec<u8>)>, Vec<u8>, Vec<u8>){
-        // let rpki_object = RpkiObject::new(self.tree.clone(), self.tree.obj_type.clone());
-        let parent_key;
-        let mut repo;
-        if self.tree.obj_type == "cer"{
-            repo = cure_repo::new_parent_child();
-            parent_key = repo.child_repos[0].certificate.parent_key.clone();
-        }
-        else{
-            let conf = repository_util::RepoConfig::default();
-            repo = new_repo(&conf, &ObjectType::UNKNOWN, true);
-            repo.fix_all_objects(true);
-
-            parent_key = repo.certificate.child_key.clone();
-        }
-
-        let child_key = load_random_key(&repo.conf).1;
-        let name = format!("{}.{}", random_fname(), self.tree.obj_type);
-
-        let cure_obj = CureObject::new(
-            ObjectType::from_string(&self.tree.obj_type),
-            parent_key,
-            child_key,
-            self.tree.clone(),
-            name,
-        );
-
-
-        if cure_obj.op_type.is_payload(){
-            repo.payloads = vec![cure_obj];
-        }
-        else{
-            match cure_obj.op_type{
-                ObjectType::MFT => {
-                    repo.manifest = cure_obj;
-                }
-                ObjectType::CRL => {
-                    repo.crl = cure_obj;
-                }
-                ObjectType::CERTCA => {
-                    repo.child_repos[0].certificate = cure_obj;
-                    repo.child_repos[0].fix_all_objects(true);
-
-                }
-                _ => {}
-            }
-        } 
+    /// Like `repositorify` with options as JSON, see `RepoOptions`.
+    #[wasm_bindgen]
+    pub fn repositorify_with(&self, options: String) -> Result<Vec<u8>, String>{
+        let options = RepoOptions::from_json(&options)?;
+        repo::archive(&[repo::Entry::single(self, &options)?], &[], &options)
+    }
 
-        repo.fix_all_objects(true);
+    // Repository with this object published in it, and files that belong next to the repository
+    fn build_rpki_repo(&self, options: &RepoOptions) -> Result<(CureRepository, RepoFiles), String>{
+        repo::build_repository(&[repo::Entry::single(self, options)?], &[], options)
+    }
 
+    // (Snapshot and Notification, TAL, CA Cert)
+    fn export_rpki_repo(mut repo: CureRepository, extra_files: RepoFiles, options: &RepoOptions) -> Result<(RepoFiles, Vec<u8>, Vec<u8>), String>{
         repo.conf.base_rrdp_dir_l = repo.conf.base_rrdp_dir.clone();
-        let ret = repo.create_snapshot_notification(&repo.conf);
+        let mut ret = repo.create_snapshot_notification(&repo.conf);
+        let session_id = options.session_id.clone().or(options.seed.map(seed::session_id));
+        let serial = options.serial.or(options.seed.map(|_| 1));
+        if session_id.is_some() || serial.is_some(){
+            rrdp::set_session(&mut ret, session_id.as_deref(), serial)?;
+        }
+        ret.extend(extra_files);
         // let mut ret_vec = vec![];
         // for (name, data) in ret{
         //     let s_data = from_utf8(&data).unwrap().to_string();
         //     ret_vec.push((name, s_data));
         // }
-        (ret, repo.get_tal().as_bytes().to_vec(), repo.certificate.tree.encode())
+        Ok((ret, repo.get_tal().as_bytes().to_vec(), repo.certificate.tree.encode()))
     }
 
 
@@ -309,6 +364,16 @@ impl State{
             return Ok(state);
         }
 
+        // Names used by the fuzzing view
+        let typ = match typ{
+            "aspa" | "asa" => return State::example_aspa(),
+            "manifest" => "mft",
+            "rsc" | "sig" => return State::example_rsc(),
+            "router" | "bgpsec" => return State::example_router(),
+            "tak" => return State::example_tak(),
+            t => t,
+        };
+
         let conf = cure_pp::repository_util::create_default_config();
         let ob_typ = ObjectType::from_string(typ);
         if ob_typ == ObjectType::UNKNOWN{
@@ -316,14 +381,14 @@ impl State{
         }
         let mut obj = cure_pp::cure_object::new_object(&conf, &ob_typ);
         obj.fix_fields(&cure_repo::FixingLevel::Full, &conf, None);
-        let state = State{tree: obj.tree};
+        let state = State::from_tree(obj.tree);
         Ok(state)
     }
 
 
     #[wasm_bindgen]
     pub fn get_nodes(&self) -> String{
-        let nodes = encode_tree(&self.tree);
+        let nodes = encode_tree(&self.tree, &self.overrides);
         serde_json::to_string(&nodes).unwrap().clone()
     }
 
@@ -348,6 +413,13 @@ impl State{
 
     #[wasm_bindgen]
     pub fn adapt_node_content(&mut self, id: usize, new_content: String) -> Result<(), String>{
+        if !self.tree.tokens.contains_key(&id){
+            return Err("Invalid node".to_string());
+        }
+        if is_expandable(self.tree.tokens[&id].tag_u) && !self.tree.tokens[&id].children.is_empty(){
+            return Err("Node is expanded, collapse it before editing its content".to_string());
+        }
+
         let val = val_to_bytes(self.tree.tokens[&id].tag_u, new_content.clone())?;
         self.tree.tokens.get_mut(&id).unwrap().data = val;
         self.tree.tokens.get_mut(&id).unwrap().manipulated = true;
@@ -407,22 +479,134 @@ impl State{
     }
 
 
+    /// Reinterprets the content of an OCTET STRING or BIT STRING as encapsulated ASN.1.
+    /// Edits in the new subtree are written back into the string on export.
+    #[wasm_bindgen]
+    pub fn expand_node(&mut self, id: usize) -> Result<(), String>{
+        let token = self.tree.tokens.get(&id).ok_or("Invalid node".to_string())?;
+        if !is_expandable(token.tag_u){
+            return Err("Only OCTET STRING and BIT STRING nodes can be expanded".to_string());
+        }
+        if !token.children.is_empty(){
+            return Err("Node is already expanded".to_string());
+        }
+
+        let payload = if encoder::is_bit_string(token.tag_u){
+            if token.data.first() != Some(&0){
+                return Err("BIT STRING has unused bits and cannot contain encapsulated data".to_string());
+            }
+            token.data[1..].to_vec()
+        }
+        else{
+            token.data.clone()
+        };
+
+        tree_util::set_encapsulated(&mut self.tree, id, &payload)
+    }
+
+    /// Turns an expanded string back into opaque bytes, keeping the current encoding of its subtree.
+    #[wasm_bindgen]
+    pub fn collapse_node(&mut self, id: usize) -> Result<(), String>{
+        let token = self.tree.tokens.get(&id).ok_or("Invalid node".to_string())?;
+        if !is_expandable(token.tag_u) || token.children.is_empty(){
+            return Err("Node is not expanded".to_string());
+        }
+
+        let content = encoder::encode_content(&self.tree, id, &self.overrides);
+        for child in self.tree.tokens[&id].children.clone(){
+            self.tree.deep_delete(child);
+        }
+
+        let token = self.tree.tokens.get_mut(&id).unwrap();
+        token.children.clear();
+        token.data = content;
+        token.manipulated = true;
+        token.tainted = true;
+        self.tree.taint_parents(id);
+        self.tree.fix_sizes(true);
+        self.overrides.retain_existing(&self.tree);
+        Ok(())
+    }
+
+    /// Replaces tag, length and content of a node with arbitrary hex bytes, emitted verbatim on export.
+    /// An empty string removes the node from the encoding.
+    #[wasm_bindgen]
+    pub fn set_raw_bytes(&mut self, id: usize, hex_bytes: String) -> Result<(), String>{
+        if !self.tree.tokens.contains_key(&id){
+            return Err("Invalid node".to_string());
+        }
+
+        let raw = parse_hex_bytes(&hex_bytes)?;
+        self.overrides.nodes.insert(id, raw);
+        let token = self.tree.tokens.get_mut(&id).unwrap();
+        if !token.manipulated{
+            token.manipulated = true;
+            self.overrides.marked.insert(id);
+        }
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_raw_bytes(&mut self, id: usize) -> Result<(), String>{
+        self.overrides.nodes.remove(&id).ok_or("Node has no raw bytes".to_string())?;
+        if self.overrides.marked.remove(&id){
+            self.tree.tokens.get_mut(&id).unwrap().manipulated = false;
+        }
+        Ok(())
+    }
+
+    /// Appends arbitrary hex bytes after a node, i.e. between it and its next sibling or after the root.
+    /// An empty string removes the trailing bytes.
+    #[wasm_bindgen]
+    pub fn set_trailing_bytes(&mut self, id: usize, hex_bytes: String) -> Result<(), String>{
+        if !self.tree.tokens.contains_key(&id){
+            return Err("Invalid node".to_string());
+        }
+
+        let trailing = parse_hex_bytes(&hex_bytes)?;
+        if trailing.is_empty(){
+            self.overrides.trailing.remove(&id);
+        }
+        else{
+            self.overrides.trailing.insert(id, trailing);
+        }
+        Ok(())
+    }
+
     #[wasm_bindgen]
     pub fn remove_node(&mut self, id: usize) -> Result<(), String>{
         self.tree.taint_parents(id);
         self.tree.deep_delete(id);
         self.tree.fix_sizes(true);
+        self.overrides.retain_existing(&self.tree);
         Ok(())
     }
 
     #[wasm_bindgen]
     pub fn export_bin(&self) -> Vec<u8>{
-        self.tree.encode()
+        self.encode()
     }
 
     #[wasm_bindgen]
     pub fn export_base64(&self) -> String{
-        base64::encode(self.tree.encode())
+        base64::encode(self.encode())
+    }
+
+    #[wasm_bindgen]
+    pub fn export_base64_url(&self, padded: bool) -> String{
+        let engine = if padded { input::BASE64_URL } else { input::BASE64_URL_NO_PAD };
+        base64::encode_engine(self.encode(), &engine)
+    }
+
+    #[wasm_bindgen]
+    pub fn export_base64_nopad(&self) -> String{
+        base64::encode_engine(self.encode(), &input::BASE64_STANDARD_NO_PAD)
+    }
+
+    /// Base64 wrapped into lines of 76 characters as used in MIME.
+    #[wasm_bindgen]
+    pub fn export_base64_mime(&self) -> String{
+        input::encode_base64_mime(&self.encode())
     }
 
     #[wasm_bindgen]
@@ -613,6 +797,20 @@ fn val_to_bytes(typ: u8, value: String) -> Result<Vec<u8>, String>{
     };
 }
 
+/// Exact bytes for raw and trailing bytes, in hex with an optional 0x prefix and separated by
+/// whitespace or colons. Nothing is padded, every byte needs both of its digits.
+fn parse_hex_bytes(value: &str) -> Result<Vec<u8>, String>{
+    let mut ret = vec![];
+    for group in value.split(|c: char| c.is_whitespace() || c == ':').filter(|g| !g.is_empty()){
+        let group = group.strip_prefix("0x").unwrap_or(group);
+        if group.len() % 2 != 0{
+            return Err(format!("Odd number of hex digits in {}", group));
+        }
+        ret.extend(hex::decode(group).map_err(|_| format!("Invalid hex bytes {}", group))?);
+    }
+    Ok(ret)
+}
+
 fn parse_string_as_hex(value: &str)-> Result<Vec<u8>, String>{
     let mut trimmed = value.trim().to_string();
     if trimmed.starts_with("0x"){
@@ -779,4 +977,22 @@ jNPElpzVmbUq4JUagEiuTDkHzsxHpFKVK7q4+63SM1N95R1NbdWhscdCb+ZAJzVc
 oyi3B43njTOQ5yOf+1CceWxG1bQVs5ZufpsMljq4Ui0/1lvh+wjChP4kqKOJ2qxq
 4RgqsahDYVvTH9w7jXbyLeiNdd8XM2w9U/t7y0Ff/9yi0GE44Za4rF2LN9d11TPA
 mRGunUHBcnWEvgJBQl9nJEiU0Zsnvgc/ubhPgXRR4Xq37Z0j4r7g1SgEEzwxA57d
-emyPxgcYxn/eR44/KJ4EBs+lVDR3veyJm+kXQ99b21/+jh5Xos1AnX5iItreGCc=";
\ No newline at end of file
+emyPxgcYxn/eR44/KJ4EBs+lVDR3veyJm+kXQ99b21/+jh5Xos1AnX5iItreGCc=";
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn raw_bytes_are_exact(){
+        // SEQUENCE { INTEGER 5 }
+        let mut state = State::new("3003020105".to_string()).unwrap();
+        let id = state.tree.tokens[&state.tree.root_id].children[0];
+        assert!(state.set_raw_bytes(id, "123".to_string()).is_err());
+        assert!(state.set_trailing_bytes(id, "aa:b".to_string()).is_err());
+
+        state.set_raw_bytes(id, "02:01 ff".to_string()).unwrap();
+        state.set_trailing_bytes(id, "0xAABB".to_string()).unwrap();
+        assert_eq!(state.encode(), [0x30, 0x05, 0x02, 0x01, 0xFF, 0xAA, 0xBB]);
+    }
+}
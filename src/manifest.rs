@@ -0,0 +1,391 @@
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+use wasm_bindgen::prelude::*;
+
+use crate::{cms, der, tree_util::{self, children, tag}, State};
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ManifestEntry{
+    pub file: String,
+    pub hash: String, // Hex
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestInfo{
+    pub manifest_number: String, // Decimal, may be longer than 64 bit
+    pub this_update: String, // RFC 3339, or the raw value if it is not a valid time
+    pub next_update: String,
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// Primitive field of a manifest. Its original encoding is kept until the content is set.
+struct Field{
+    tag: u8,
+    content: Vec<u8>,
+    encoding: Option<Vec<u8>>,
+}
+
+impl Field{
+    fn set(&mut self, content: Vec<u8>){
+        self.content = content;
+        self.encoding = None;
+    }
+
+    fn encode(&self) -> Vec<u8>{
+        self.encoding.clone().unwrap_or_else(|| der::tlv(self.tag, &self.content))
+    }
+}
+
+/// FileAndHash of the fileList. Its original encoding is kept until the entry is edited, so
+/// hand-crafted entries survive edits of other fields.
+struct FileAndHash{
+    file: String,
+    hash: Vec<u8>, // Without the unused-bits octet
+    encoding: Option<Vec<u8>>,
+}
+
+impl FileAndHash{
+    fn new(file: String, hash: Vec<u8>) -> FileAndHash{
+        FileAndHash{ file, hash, encoding: None }
+    }
+
+    fn set_hash(&mut self, hash: Vec<u8>){
+        self.hash = hash;
+        self.encoding = None;
+    }
+
+    fn encode(&self) -> Vec<u8>{
+        if let Some(e) = &self.encoding{
+            return e.clone();
+        }
+        let mut bits = vec![0];
+        bits.extend(&self.hash);
+
+        let mut entry = der::tlv(0x16, self.file.as_bytes());
+        entry.extend(der::tlv(0x03, &bits));
+        der::tlv(0x30, &entry)
+    }
+}
+
+/// Fields of a manifest payload. Fields that are not edited keep their encoding.
+struct ManifestModel{
+    version: Option<Vec<u8>>,
+    number: Field, // INTEGER
+    this_update: Field,
+    next_update: Field,
+    hash_alg: Vec<u8>,
+    files: Vec<FileAndHash>,
+}
+
+impl ManifestModel{
+    fn encode(&self) -> Vec<u8>{
+        let list = self.files.iter().flat_map(|f| f.encode()).collect::<Vec<u8>>();
+
+        let mut ret = self.version.clone().unwrap_or_default();
+        ret.extend(self.number.encode());
+        ret.extend(self.this_update.encode());
+        ret.extend(self.next_update.encode());
+        ret.extend(self.hash_alg.iter());
+        ret.extend(der::tlv(0x30, &list));
+        der::tlv(0x30, &ret)
+    }
+
+    fn position(&self, file: &str) -> Result<usize, String>{
+        self.files.iter().position(|f| f.file == file).ok_or(format!("Manifest has no entry {}", file))
+    }
+}
+
+fn format_time(time: &Field) -> String{
+    match der::parse_time(time.tag, &time.content){
+        Some(t) => t.to_rfc3339(),
+        None => String::from_utf8_lossy(&time.content).to_string(),
+    }
+}
+
+fn parse_rfc3339(value: &str) -> Result<DateTime<Utc>, String>{
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| "Invalid ISO 8601 format. Example: 2025-02-12T14:30:00Z".to_string())
+}
+
+fn parse_hash(hash: &str) -> Result<Vec<u8>, String>{
+    hex::decode(hash.trim().trim_start_matches("0x")).map_err(|_| "Hash must be hex encoded".to_string())
+}
+
+#[wasm_bindgen]
+impl State{
+    /// Returns manifestNumber, thisUpdate, nextUpdate and the file list of a manifest as JSON.
+    #[wasm_bindgen]
+    pub fn manifest_entries(&self) -> Result<String, String>{
+        let m = self.manifest_model()?;
+        let number = match der::parse_uint(&m.number.content){
+            Some(n) => n.to_string(),
+            None => format!("0x{}", hex::encode(&m.number.content)),
+        };
+
+        let info = ManifestInfo{
+            manifest_number: number,
+            this_update: format_time(&m.this_update),
+            next_update: format_time(&m.next_update),
+            entries: m.files.iter().map(|f| ManifestEntry{ file: f.file.clone(), hash: hex::encode(&f.hash) }).collect(),
+        };
+        Ok(serde_json::to_string(&info).unwrap())
+    }
+
+    /// Adds an entry with a hex hash, the name must not be listed yet.
+    #[wasm_bindgen]
+    pub fn manifest_add_entry(&mut self, file: String, hash: String) -> Result<(), String>{
+        let hash = parse_hash(&hash)?;
+        self.edit_manifest(|m| {
+            if m.position(&file).is_ok(){
+                return Err(format!("Manifest already lists {}", file));
+            }
+            m.files.push(FileAndHash::new(file, hash));
+            Ok(())
+        })
+    }
+
+    #[wasm_bindgen]
+    pub fn manifest_remove_entry(&mut self, file: String) -> Result<(), String>{
+        self.edit_manifest(|m| {
+            let pos = m.position(&file)?;
+            m.files.remove(pos);
+            Ok(())
+        })
+    }
+
+    #[wasm_bindgen]
+    pub fn manifest_rename_entry(&mut self, file: String, new_name: String) -> Result<(), String>{
+        self.edit_manifest(|m| {
+            let pos = m.position(&file)?;
+            m.files[pos] = FileAndHash::new(new_name, m.files[pos].hash.clone());
+            Ok(())
+        })
+    }
+
+    /// Sets the hash of an entry to any hex value, e.g. to build inconsistent manifests.
+    #[wasm_bindgen]
+    pub fn manifest_set_hash(&mut self, file: String, hash: String) -> Result<(), String>{
+        let hash = parse_hash(&hash)?;
+        self.edit_manifest(|m| {
+            let pos = m.position(&file)?;
+            m.files[pos].set_hash(hash);
+            Ok(())
+        })
+    }
+
+    /// Adds or updates the entry `file` with the SHA-256 hash of the encoding of `object`.
+    #[wasm_bindgen]
+    pub fn manifest_hash_object(&mut self, file: String, object: &State) -> Result<(), String>{
+        let hash = Sha256::digest(object.encode()).to_vec();
+        self.edit_manifest(|m| {
+            match m.position(&file){
+                Ok(pos) => m.files[pos].set_hash(hash),
+                Err(_) => m.files.push(FileAndHash::new(file, hash)),
+            }
+            Ok(())
+        })
+    }
+
+    /// Sets the manifestNumber to a decimal value.
+    #[wasm_bindgen]
+    pub fn manifest_set_number(&mut self, number: String) -> Result<(), String>{
+        let number = number.trim().parse::<u128>().map_err(|_| "Invalid manifest number".to_string())?;
+        self.edit_manifest(|m| {
+            m.number.set(der::encode_uint(number));
+            Ok(())
+        })
+    }
+
+    #[wasm_bindgen]
+    pub fn manifest_bump_number(&mut self) -> Result<(), String>{
+        self.edit_manifest(|m| {
+            let number = der::parse_uint(&m.number.content).ok_or("manifestNumber is not a valid number")?;
+            m.number.set(der::encode_uint(number.checked_add(1).ok_or("manifestNumber is too large")?));
+            Ok(())
+        })
+    }
+
+    /// Sets thisUpdate and nextUpdate (ISO 8601), the time types of the manifest are kept.
+    #[wasm_bindgen]
+    pub fn manifest_set_window(&mut self, this_update: String, next_update: String) -> Result<(), String>{
+        let this_update = parse_rfc3339(&this_update)?;
+        let next_update = parse_rfc3339(&next_update)?;
+        self.edit_manifest(|m| {
+            m.this_update.set(der::encode_time(m.this_update.tag, &this_update));
+            m.next_update.set(der::encode_time(m.next_update.tag, &next_update));
+            Ok(())
+        })
+    }
+
+    /// Moves thisUpdate and nextUpdate by `seconds`, which can be negative.
+    #[wasm_bindgen]
+    pub fn manifest_shift_window(&mut self, seconds: i64) -> Result<(), String>{
+        let shift = Duration::try_seconds(seconds).ok_or("Invalid duration")?;
+        self.edit_manifest(|m| {
+            for time in [&mut m.this_update, &mut m.next_update]{
+                let t = der::parse_time(time.tag, &time.content).ok_or("Manifest contains an invalid time")?;
+                time.set(der::encode_time(time.tag, &(t + shift)));
+            }
+            Ok(())
+        })
+    }
+}
+
+impl State{
+    fn manifest_model(&self) -> Result<ManifestModel, String>{
+        let econtent = self.signed_payload(cms::OID_CT_MANIFEST, "manifest")?;
+        let p = tree_util::payload(&self.tree, econtent, &self.overrides).ok_or("eContent is not valid ASN.1")?;
+        let t = &p.tree;
+
+        let c = children(t, p.root);
+        let version = c.first().filter(|v| tag(t, **v) == 0xA0).map(|v| p.encode(*v, &self.overrides));
+        let c = &c[version.is_some() as usize..];
+        if c.len() != 5{
+            return Err("Manifest must contain manifestNumber, thisUpdate, nextUpdate, fileHashAlg and fileList".to_string());
+        }
+
+        let field = |id: usize| Field{
+            tag: tag(t, id),
+            content: t.tokens[&id].data.clone(),
+            encoding: Some(p.encode(id, &self.overrides)),
+        };
+        let mut files = vec![];
+        for entry in children(t, c[4]){
+            let e = children(t, *entry);
+            if e.len() != 2{
+                return Err("FileAndHash must contain file and hash".to_string());
+            }
+            let hash = &t.tokens[&e[1]].data;
+            files.push(FileAndHash{
+                file: String::from_utf8_lossy(&t.tokens[&e[0]].data).to_string(),
+                hash: hash.get(1..).unwrap_or_default().to_vec(),
+                encoding: Some(p.encode(*entry, &self.overrides)),
+            });
+        }
+
+        Ok(ManifestModel{
+            version,
+            number: field(c[0]),
+            this_update: field(c[1]),
+            next_update: field(c[2]),
+            hash_alg: p.encode(c[3], &self.overrides),
+            files,
+        })
+    }
+
+    /// Applies `edit` to the manifest and writes the payload back. Digest and signatures are not updated.
+    fn edit_manifest(&mut self, edit: impl FnOnce(&mut ManifestModel) -> Result<(), String>) -> Result<(), String>{
+        let mut m = self.manifest_model()?;
+        edit(&mut m)?;
+
+        let econtent = self.signed_payload(cms::OID_CT_MANIFEST, "manifest")?;
+        tree_util::set_string_payload(&mut self.tree, econtent, &m.encode())?;
+        self.overrides.retain_existing(&self.tree);
+        Ok(())
+    }
+
+    /// Replaces the hashes that are listed in `hashes` as old and new hex hash, e.g. after the
+    /// objects of the manifest were signed again.
+    pub(crate) fn manifest_replace_hashes(&mut self, hashes: &[(String, String)]) -> Result<(), String>{
+        self.edit_manifest(|m| {
+            for f in m.files.iter_mut(){
+                if let Some((_, new)) = hashes.iter().find(|(old, _)| *old == hex::encode(&f.hash)){
+                    f.set_hash(parse_hash(new)?);
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn info(state: &State) -> ManifestInfo{
+        serde_json::from_str(&state.manifest_entries().unwrap()).unwrap()
+    }
+
+    fn payload(state: &State) -> Vec<u8>{
+        let econtent = state.signed_payload(cms::OID_CT_MANIFEST, "manifest").unwrap();
+        tree_util::string_payload(&state.tree, econtent, &state.overrides)
+    }
+
+    #[test]
+    fn entries(){
+        let mut state = State::load_example("mft").unwrap();
+        let before = info(&state).entries.len();
+        let hash = hex::encode([0xAB; 32]);
+
+        state.manifest_add_entry("x.roa".to_string(), hash.clone()).unwrap();
+        state.manifest_rename_entry("x.roa".to_string(), "y.roa".to_string()).unwrap();
+        state.manifest_set_hash("y.roa".to_string(), "0x01".to_string()).unwrap();
+        let entries = info(&state).entries;
+        assert_eq!(entries.len(), before + 1);
+        assert_eq!((entries[before].file.as_str(), entries[before].hash.as_str()), ("y.roa", "01"));
+
+        let object = State::load_example("roa").unwrap();
+        state.manifest_hash_object("y.roa".to_string(), &object).unwrap();
+        assert_eq!(info(&state).entries[before].hash, hex::encode(Sha256::digest(object.encode())));
+
+        state.manifest_remove_entry("y.roa".to_string()).unwrap();
+        assert_eq!(info(&state).entries.len(), before);
+
+        assert!(state.manifest_remove_entry("y.roa".to_string()).is_err());
+        assert!(state.manifest_set_hash("missing.roa".to_string(), hash.clone()).is_err());
+        assert!(state.manifest_add_entry("z.roa".to_string(), "zz".to_string()).is_err());
+        state.manifest_add_entry("z.roa".to_string(), hash.clone()).unwrap();
+        assert!(state.manifest_add_entry("z.roa".to_string(), hash).is_err());
+    }
+
+    #[test]
+    fn number_and_window(){
+        let mut state = State::load_example("mft").unwrap();
+        state.manifest_set_number("340282366920938463463374607431768211454".to_string()).unwrap();
+        state.manifest_bump_number().unwrap();
+        assert_eq!(info(&state).manifest_number, u128::MAX.to_string());
+        assert!(state.manifest_bump_number().is_err());
+        assert!(state.manifest_set_number("-1".to_string()).is_err());
+
+        state.manifest_set_window("2030-01-01T00:00:00Z".to_string(), "2030-01-02T00:00:00Z".to_string()).unwrap();
+        state.manifest_shift_window(-3600).unwrap();
+        let m = info(&state);
+        assert_eq!((m.this_update.as_str(), m.next_update.as_str()), ("2029-12-31T23:00:00+00:00", "2030-01-01T23:00:00+00:00"));
+        assert!(state.manifest_set_window("yesterday".to_string(), "today".to_string()).is_err());
+    }
+
+    #[test]
+    fn unedited_fields_keep_their_encoding(){
+        let mut state = State::load_example("mft").unwrap();
+        let econtent = state.signed_payload(cms::OID_CT_MANIFEST, "manifest").unwrap();
+
+        // An entry with a UTF8String name and 3 unused bits
+        let mut odd = der::tlv(0x0C, b"odd.roa");
+        odd.extend(der::tlv(0x03, &[[0x03].as_slice(), &[0xF8; 32]].concat()));
+        let odd = der::tlv(0x30, &odd);
+        let mut mft = der::tlv(0x02, &[0x05]);
+        mft.extend(der::tlv(0x18, b"20300101000000Z"));
+        mft.extend(der::tlv(0x18, b"20300102000000Z"));
+        mft.extend(der::tlv(0x30, &der::tlv(0x06, &crate::encode_oid_from_string("2.16.840.1.101.3.4.2.1"))));
+        mft.extend(der::tlv(0x30, &odd));
+        tree_util::set_string_payload(&mut state.tree, econtent, &der::tlv(0x30, &mft)).unwrap();
+
+        state.manifest_shift_window(60).unwrap();
+        state.manifest_add_entry("new.roa".to_string(), hex::encode([0; 32])).unwrap();
+        assert!(payload(&state).windows(odd.len()).any(|w| w == odd));
+
+        // An edited entry is encoded again
+        state.manifest_set_hash("odd.roa".to_string(), hex::encode([0x11; 32])).unwrap();
+        assert!(!payload(&state).windows(odd.len()).any(|w| w == odd));
+        assert_eq!(info(&state).entries[0].hash, hex::encode([0x11; 32]));
+    }
+
+    #[test]
+    fn not_a_manifest(){
+        let mut state = State::load_example("roa").unwrap();
+        assert!(state.manifest_entries().is_err());
+        assert!(state.manifest_bump_number().is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use cure_asn1::tree_parser::Tree;
+
+use crate::{der, encoder::Overrides, tree_util};
+
+// Recovery parser for input that cure_asn1 rejects. Every element that parses on
+// its own is taken over as is, constructed elements are descended into, and
+// whatever is left is kept as an opaque error node whose raw bytes are emitted
+// unchanged on export.
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ParseIssue{
+    pub node: usize,
+    pub offset: usize,
+    pub reason: String,
+    pub expected_length: Option<usize>,
+    pub found_length: Option<usize>,
+}
+
+struct Recovery<'a>{
+    data: &'a [u8],
+    tree: Option<Tree>,
+    overrides: Overrides,
+    issues: Vec<ParseIssue>,
+}
+
+pub fn recover(data: &[u8]) -> Result<(Tree, Overrides, Vec<ParseIssue>), String>{
+    if data.is_empty(){
+        return Err("No data".to_string());
+    }
+
+    let mut rec = Recovery{
+        data,
+        tree: None,
+        overrides: Overrides::default(),
+        issues: vec![],
+    };
+
+    let end = rec.element(0, data.len(), None)?;
+    let mut tree = rec.tree.take().unwrap();
+
+    if end < data.len(){
+        rec.overrides.trailing.insert(tree.root_id, data[end..].to_vec());
+        rec.issues.push(ParseIssue{
+            node: tree.root_id,
+            offset: end,
+            reason: "Trailing data after the root element".to_string(),
+            expected_length: None,
+            found_length: Some(data.len() - end),
+        });
+    }
+
+    tree.fix_sizes(true);
+    Ok((tree, rec.overrides, rec.issues))
+}
+
+impl Recovery<'_>{
+    /// Recovers the element at `offset`, which may not extend past `end`. Returns the offset after it.
+    fn element(&mut self, offset: usize, end: usize, parent: Option<usize>) -> Result<usize, String>{
+        let header = match der::parse_header(&self.data[..end], offset){
+            Ok(h) => h,
+            Err(e) => {
+                self.error_node(offset, end, parent, e, None, None)?;
+                return Ok(end);
+            }
+        };
+
+        let content_start = offset + header.header_len;
+        let available = end - content_start;
+
+        let length = match header.length{
+            Some(l) => l,
+            None => {
+                self.error_node(offset, end, parent, "Indefinite length could not be parsed".to_string(), None, None)?;
+                return Ok(end);
+            }
+        };
+
+        if length <= available{
+            let element = &self.data[offset..content_start + length];
+            if let Some(sub) = cure_asn1::interface::parse_tree(element, ""){
+                self.attach(&sub, parent);
+                return Ok(content_start + length);
+            }
+        }
+
+        // A declared length past the end of the address space is truncated as well
+        let truncated = length > available;
+        let content_end = content_start.checked_add(length).map_or(end, |e| e.min(end));
+
+        if !header.constructed{
+            if truncated{
+                self.error_node(offset, content_end, parent, "Content is truncated".to_string(), Some(length), Some(available))?;
+            }
+            else{
+                self.error_node(offset, content_end, parent, "Content could not be parsed".to_string(), None, None)?;
+            }
+            return Ok(content_end);
+        }
+
+        // Constructed element, keep the original header and recover the children one by one
+        let mut shell_bytes = header.tag.clone();
+        shell_bytes.push(0);
+        let shell = match cure_asn1::interface::parse_tree(&shell_bytes, ""){
+            Some(s) => s,
+            None => {
+                self.error_node(offset, content_end, parent, "Unknown constructed tag".to_string(), None, None)?;
+                return Ok(content_end);
+            }
+        };
+        let id = self.attach(&shell, parent);
+
+        if truncated{
+            let token = self.tree.as_mut().unwrap().tokens.get_mut(&id).unwrap();
+            token.visual_length = length;
+            token.manipulated_length = true;
+            self.issues.push(ParseIssue{
+                node: id,
+                offset,
+                reason: "Element is longer than the remaining data".to_string(),
+                expected_length: Some(length),
+                found_length: Some(available),
+            });
+        }
+
+        let mut pos = content_start;
+        while pos < content_end{
+            pos = self.element(pos, content_end, Some(id))?;
+        }
+
+        Ok(content_end)
+    }
+
+    /// Adds an opaque node that carries `data[offset..end]` verbatim.
+    fn error_node(&mut self, offset: usize, end: usize, parent: Option<usize>, reason: String, expected_length: Option<usize>, found_length: Option<usize>) -> Result<usize, String>{
+        let placeholder = cure_asn1::interface::parse_tree(&[0x04, 0x00], "").ok_or("Failed to create error node".to_string())?;
+        let id = self.attach(&placeholder, parent);
+
+        let token = self.tree.as_mut().unwrap().tokens.get_mut(&id).unwrap();
+        token.info = format!("error: {}", reason);
+        self.overrides.nodes.insert(id, self.data[offset..end].to_vec());
+
+        self.issues.push(ParseIssue{
+            node: id,
+            offset,
+            reason,
+            expected_length,
+            found_length,
+        });
+        Ok(id)
+    }
+
+    fn attach(&mut self, sub: &Tree, parent: Option<usize>) -> usize{
+        match (self.tree.as_mut(), parent){
+            (Some(tree), Some(p)) => tree_util::graft(tree, sub, p, None),
+            _ => {
+                self.tree = Some(sub.clone());
+                sub.root_id
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::encoder;
+
+    fn recover_exactly(data: &[u8]) -> Vec<ParseIssue>{
+        let (tree, overrides, issues) = recover(data).unwrap();
+        assert_eq!(encoder::encode_tree(&tree, &overrides), data);
+        issues
+    }
+
+    #[test]
+    fn truncated_content(){
+        // SEQUENCE { INTEGER 5, OCTET STRING of 3 bytes with only 1 present }
+        let issues = recover_exactly(&[0x30, 0x06, 0x02, 0x01, 0x05, 0x04, 0x03, 0x61]);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].offset, 5);
+        assert_eq!(issues[0].reason, "Content is truncated");
+        assert_eq!((issues[0].expected_length, issues[0].found_length), (Some(3), Some(1)));
+    }
+
+    #[test]
+    fn truncated_constructed(){
+        // The SEQUENCE claims 8 bytes, its child is recovered and the length is kept
+        let (tree, overrides, issues) = recover(&[0x30, 0x08, 0x02, 0x01, 0x05]).unwrap();
+        assert_eq!(encoder::encode_tree(&tree, &overrides), [0x30, 0x08, 0x02, 0x01, 0x05]);
+        assert_eq!(tree.tokens[&tree.root_id].children.len(), 1);
+        assert_eq!(issues[0].reason, "Element is longer than the remaining data");
+        assert_eq!((issues[0].expected_length, issues[0].found_length), (Some(8), Some(3)));
+    }
+
+    #[test]
+    fn trailing_data(){
+        let issues = recover_exactly(&[0x02, 0x01, 0x05, 0xAA, 0xBB]);
+        assert_eq!(issues.len(), 1);
+        assert_eq!((issues[0].offset, issues[0].found_length), (3, Some(2)));
+    }
+
+    #[test]
+    fn broken_header(){
+        // The length of the second child is cut off
+        let issues = recover_exactly(&[0x30, 0x04, 0x02, 0x01, 0x05, 0x04]);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].offset, 5);
+    }
+
+    #[test]
+    fn huge_length(){
+        // The OCTET STRING claims almost usize::MAX bytes, the INTEGER after the SEQUENCE is kept
+        let data = [0x30, 0x0C, 0x04, 0x88, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFA, 0x61, 0x62, 0x02, 0x01, 0x05];
+        let (tree, overrides, issues) = recover(&data).unwrap();
+        assert_eq!(encoder::encode_tree(&tree, &overrides), data);
+        assert_eq!(issues[0].reason, "Content is truncated");
+        assert_eq!((issues[0].offset, issues[0].found_length), (2, Some(2)));
+    }
+
+    #[test]
+    fn empty(){
+        assert!(recover(&[]).is_err());
+    }
+}
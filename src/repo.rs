@@ -0,0 +1,600 @@
+use cure_asn1::rpki::ObjectType;
+use cure_pp::{cure_object::CureObject, cure_repo::{self, new_repo, CureRepository}, repository_util::{load_random_key, random_fname, ObjectKey, RepoConfig}};
+use wasm_bindgen::prelude::*;
+
+use crate::{create_tar_gz_in_memory, import::Imported, keys, read_tar_gz, resign::ResignOptions, router, rrdp, seed::{Placed, Seed}, tak, RepoFiles, RrdpEntry, State};
+
+// Repositories are generated by cure with the objects of the user placed in them. Objects
+// cure does not generate are added after its objects are fixed, so the manifest is updated
+// and signed again here. CAs below the first child are copies of cure's child CA, cure
+// names their directories, manifests and CRLs after `ca_name`.
+
+/// Options of `repositorify_with`. URIs and keys that are not given are cure's defaults.
+/// File name, validity and key of the object are not used by `Repository`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RepoOptions{
+    pub rsync_uri: Option<String>, // Base URI of the repository, e.g. rsync://my.server.com/data/repo/
+    pub rrdp_uri: Option<String>, // Base URI of the RRDP files on the same host, e.g. https://my.server.com/data/rrdp/
+    pub file_name: Option<String>, // Name of the object, the type is appended if there is no extension
+    pub not_before: Option<String>, // Validity of the object certificate, ISO 8601
+    pub not_after: Option<String>,
+    pub private_key: Option<String>, // Key of the object, base64 or PEM
+    pub ca_private_key: Option<String>, // Key of the CA at `depth`
+    pub session_id: Option<String>,
+    pub serial: Option<u64>,
+    pub depth: usize, // CAs above the object: 1 is the trust anchor, each level adds a CA below it
+    pub seed: Option<u64>, // Derive keys, names, serials and times from the seed instead of random values
+    pub time: Option<String>, // Time of a seeded repository, ISO 8601
+    pub fix_manifest: bool, // With false an edited manifest is published as it is
+    pub fix_crl: bool, // The same for an edited CRL
+    pub output: Output, // rrdp, rsync or both
+}
+
+/// Layout of the repository in the archive.
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Output{
+    #[default]
+    Rrdp, // Notification and snapshot
+    Rsync, // Objects in directories as in their rsync URIs, the TAL has only rsync URIs
+    Both, // RRDP files and the directories, with ta-rsync.tal next to ta.tal
+}
+
+impl Default for RepoOptions{
+    fn default() -> Self{
+        RepoOptions{
+            rsync_uri: None,
+            rrdp_uri: None,
+            file_name: None,
+            not_before: None,
+            not_after: None,
+            private_key: None,
+            ca_private_key: None,
+            session_id: None,
+            serial: None,
+            depth: 1,
+            seed: None,
+            time: None,
+            fix_manifest: true,
+            fix_crl: true,
+            output: Output::Rrdp,
+        }
+    }
+}
+
+/// Host and path of a URI with the given scheme, the path ends with a slash.
+fn split_uri(uri: &str, scheme: &str) -> Result<(String, String), String>{
+    let rest = uri.trim().strip_prefix(scheme).ok_or(format!("{} must start with {}", uri, scheme))?;
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    if host.is_empty(){
+        return Err(format!("{} has no host", uri));
+    }
+    let path = path.trim_end_matches('/');
+    Ok((host.to_string(), if path.is_empty() { String::new() } else { format!("{}/", path) }))
+}
+
+impl RepoOptions{
+    /// Parses options as JSON, an empty string gives the defaults.
+    pub(crate) fn from_json(options: &str) -> Result<RepoOptions, String>{
+        match options.trim().is_empty(){
+            true => Ok(RepoOptions::default()),
+            false => serde_json::from_str(options).map_err(|e| format!("Invalid options: {}", e)),
+        }
+    }
+
+    /// cure configuration with the URIs of the options. cure uses the same host for rsync and RRDP.
+    pub(crate) fn config(&self) -> Result<RepoConfig, String>{
+        let mut conf = RepoConfig::default();
+        let mut host = None;
+        if let Some(uri) = self.rsync_uri.as_ref(){
+            let (h, path) = split_uri(uri, "rsync://")?;
+            conf.base_repo_dir = path;
+            host = Some(h);
+        }
+        if let Some(uri) = self.rrdp_uri.as_ref(){
+            let (h, path) = split_uri(uri, "https://")?;
+            if host.as_ref().map(|r| *r != h).unwrap_or(false){
+                return Err("rsync and RRDP URIs must have the same host".to_string());
+            }
+            conf.base_rrdp_dir = path;
+            host = Some(h);
+        }
+        if let Some(host) = host{
+            conf.domain = host;
+        }
+        Ok(conf)
+    }
+}
+
+/// Path of a CA in the tree, the indices of the child CAs from the trust anchor down.
+pub(crate) type CaPath = Vec<usize>;
+
+/// The repository of the CA at `path`, the empty path is the trust anchor.
+pub(crate) fn ca_at<'a>(repo: &'a mut CureRepository, path: &[usize]) -> Result<&'a mut CureRepository, String>{
+    let mut ca = repo;
+    for index in path{
+        ca = ca.child_repos.get_mut(*index).ok_or("Repository has no CA at this path")?;
+    }
+    Ok(ca)
+}
+
+/// Path of the CA at `depth` of the options, each level is the first child CA.
+fn chain(depth: usize) -> CaPath{
+    vec![0; depth.max(1) - 1]
+}
+
+/// Name of the CA at `path`, CAs that were not named get one from their path. cure's
+/// child CA keeps its own name.
+fn ca_name(path: &[usize], cas: &[(String, CaPath)]) -> Option<String>{
+    match cas.iter().find(|(_, p)| p == path){
+        Some((name, _)) => Some(name.clone()),
+        None if path == [0] => None,
+        None => Some(format!("ca-{}", path.iter().map(|i| (i + 1).to_string()).collect::<Vec<_>>().join("-"))),
+    }
+}
+
+/// Adds the CAs on `path` that are missing as copies of `template` with new keys.
+fn grow(repo: &mut CureRepository, path: &[usize], template: &CureRepository, cas: &[(String, CaPath)]) -> Result<(), String>{
+    let conf = repo.conf.clone();
+    let mut ca = repo;
+    for (depth, index) in path.iter().enumerate(){
+        while ca.child_repos.len() <= *index{
+            let at = [&path[..depth], &[ca.child_repos.len()]].concat();
+            let mut child = template.clone();
+            child.conf = conf.clone();
+            child.conf.ca_name = ca_name(&at, cas).ok_or("CA has no name")?;
+            set_ca_key(&mut child, &load_random_key(&conf).1);
+            child.certificate.parent_key = ca.certificate.child_key.clone();
+            child.manifest.child_key = load_random_key(&conf).1;
+            for p in child.payloads.iter_mut(){
+                p.child_key = load_random_key(&conf).1;
+            }
+            ca.child_repos.push(child);
+        }
+        ca = &mut ca.child_repos[*index];
+    }
+    Ok(())
+}
+
+/// Fixes the objects of all CAs, each CA after the CAs below it.
+fn fix_tree(repo: &mut CureRepository){
+    for child in repo.child_repos.iter_mut(){
+        fix_tree(child);
+    }
+    repo.fix_all_objects(true);
+}
+
+/// Uses `key` as the key of the CA of `repo` for its certificate and everything it issues.
+pub(crate) fn set_ca_key(repo: &mut CureRepository, key: &ObjectKey){
+    repo.certificate.child_key = key.clone();
+    repo.manifest.parent_key = key.clone();
+    repo.crl.parent_key = key.clone();
+    for p in repo.payloads.iter_mut(){
+        p.parent_key = key.clone();
+    }
+    for c in repo.child_repos.iter_mut(){
+        c.certificate.parent_key = key.clone();
+    }
+}
+
+/// Path of the trust anchor certificate in the archive, taken from the rsync URI of the TAL.
+pub(crate) fn ta_certificate_path(tal: &str) -> String{
+    tak::tal_uris(tal).iter()
+        .find_map(|u| u.strip_prefix("rsync://").and_then(|r| r.split_once('/')).map(|(_, path)| path.to_string()))
+        .unwrap_or("data/repo/ta/ta.cer".to_string())
+}
+
+/// The TAL with only its rsync URIs.
+fn rsync_tal(tal: &[u8]) -> Result<Vec<u8>, String>{
+    let tal = String::from_utf8_lossy(tal);
+    if !tak::tal_uris(&tal).iter().any(|u| u.starts_with("rsync://")){
+        return Err("TAL has no rsync URI".to_string());
+    }
+    let lines = tal.lines().filter(|l| !l.trim().starts_with("https://"));
+    Ok(lines.map(|l| format!("{}\n", l)).collect::<String>().into_bytes())
+}
+
+/// Publishes `object` as `name` in `repo` and adds it to the manifest. `key` is the key
+/// of the object, its parent is the CA of the repository.
+pub(crate) fn publish(repo: &mut CureRepository, name: &str, object: &State, key: ObjectKey) -> Result<(), String>{
+    let mut manifest = State::from_tree(repo.manifest.tree.clone());
+    manifest.manifest_hash_object(name.to_string(), object)?;
+    let options = ResignOptions{
+        ee_key: false,
+        ee_signature: false,
+        ..ResignOptions::default()
+    };
+    manifest.resign_with(&options, &repo.manifest.child_key, None)?;
+    repo.manifest.tree = manifest.encoded_tree()?;
+
+    let typ = name.rsplit('.').next().unwrap_or_default();
+    let parent_key = repo.certificate.child_key.clone();
+    repo.payloads.push(CureObject::new(ObjectType::from_string(typ), parent_key, key, object.encoded_tree()?, name.to_string()));
+    Ok(())
+}
+
+/// Where an object goes in the repository.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Role{
+    Payload,
+    Manifest,
+    Crl,
+    Ca, // Certificate of the CA itself
+}
+
+/// Object of the user with its role at the CA at `ca`.
+#[derive(Debug, Clone)]
+pub(crate) struct Entry{
+    pub object: State,
+    pub role: Role,
+    pub ca: CaPath,
+    pub file_name: Option<String>,
+    pub private_key: Option<String>,
+}
+
+impl Entry{
+    /// The object of `repositorify_with`, its role follows from its type.
+    pub(crate) fn single(object: &State, options: &RepoOptions) -> Result<Entry, String>{
+        let role = match object.object_type().as_str(){
+            "mft" => Role::Manifest,
+            "crl" => Role::Crl,
+            "cer" if !router::is_router_certificate(&object.tree) => Role::Ca,
+            _ => Role::Payload,
+        };
+
+        // A CA certificate is the one of the CA below the CA at the depth
+        let depth = options.depth;
+        if depth == 0{
+            return Err("Depth must be at least 1".to_string());
+        }
+        let ca = vec![0; if role == Role::Ca { depth } else { depth - 1 }];
+
+        let mut object = object.clone();
+        if options.not_before.is_some() || options.not_after.is_some(){
+            object.set_cert_validity(options.not_before.clone().unwrap_or_default(), options.not_after.clone().unwrap_or_default())?;
+        }
+        Ok(Entry{
+            object,
+            role,
+            ca,
+            file_name: options.file_name.clone(),
+            private_key: options.private_key.clone(),
+        })
+    }
+}
+
+/// Several objects of the user that are put into one repository.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default)]
+pub struct Repository{
+    entries: Vec<Entry>,
+    cas: Vec<(String, CaPath)>, // Named CAs below the trust anchor
+    pub(crate) imported: Option<Imported>, // Objects of a repository that was read back
+}
+
+#[wasm_bindgen]
+impl Repository{
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Repository{
+        Repository::default()
+    }
+
+    /// Adds a copy of `object` with its role: payload, manifest, crl, childCa (certificate of the CA
+    /// below the trust anchor), childPayload, childManifest or childCrl. Without a file name one
+    /// is generated.
+    #[wasm_bindgen]
+    pub fn add(&mut self, object: &State, role: String, file_name: Option<String>) -> Result<(), String>{
+        let (role, ca) = match role.as_str(){
+            "payload" => (Role::Payload, vec![]),
+            "manifest" => (Role::Manifest, vec![]),
+            "crl" => (Role::Crl, vec![]),
+            "childCa" => (Role::Ca, vec![0]),
+            "childPayload" => (Role::Payload, vec![0]),
+            "childManifest" => (Role::Manifest, vec![0]),
+            "childCrl" => (Role::Crl, vec![0]),
+            r => return Err(format!("Unknown role {}", r)),
+        };
+        self.push(object, role, ca, file_name)
+    }
+
+    /// Adds a CA named `name` below the CA `parent`, the trust anchor is named ta. Each CA has
+    /// its own manifest and CRL, CAs without objects of the user are generated.
+    #[wasm_bindgen]
+    pub fn add_ca(&mut self, name: String, parent: String) -> Result<(), String>{
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'){
+            return Err(format!("Invalid CA name {}, use letters, digits, - and _", name));
+        }
+        if name == "ta" || self.cas.iter().any(|(n, _)| *n == name){
+            return Err(format!("CA {} exists already", name));
+        }
+
+        let mut path = self.ca_path(&parent)?;
+        let siblings = self.cas.iter().filter(|(_, p)| p.len() == path.len() + 1 && p.starts_with(&path)).count();
+        path.push(siblings);
+        self.cas.push((name, path));
+        Ok(())
+    }
+
+    /// Adds a copy of `object` at the CA `ca` with its role: payload, manifest, crl or certificate
+    /// (of that CA). Without a file name one is generated.
+    #[wasm_bindgen]
+    pub fn place(&mut self, object: &State, ca: String, role: String, file_name: Option<String>) -> Result<(), String>{
+        let role = match role.as_str(){
+            "payload" => Role::Payload,
+            "manifest" => Role::Manifest,
+            "crl" => Role::Crl,
+            "certificate" if ca == "ta" => return Err("The trust anchor certificate is always generated".to_string()),
+            "certificate" => Role::Ca,
+            r => return Err(format!("Unknown role {}", r)),
+        };
+        let ca = self.ca_path(&ca)?;
+        self.push(object, role, ca, file_name)
+    }
+
+    #[wasm_bindgen]
+    pub fn count(&self) -> usize{
+        self.imported.as_ref().map(|i| i.objects.len()).unwrap_or(self.entries.len())
+    }
+
+    /// Generates one repository with all objects, like `State::repositorify_with`. An imported
+    /// repository is written out with its objects.
+    #[wasm_bindgen]
+    pub fn repositorify(&self, options: String) -> Result<Vec<u8>, String>{
+        let options = RepoOptions::from_json(&options)?;
+        let files = layout(self.files(&options)?, options.output)?;
+        Ok(create_tar_gz_in_memory(files).unwrap_or_default())
+    }
+
+    /// Generates the repository as the next state of `previous`, an archive of `repositorify` with
+    /// RRDP files. It continues the RRDP session of `previous` with the next serial and a delta to
+    /// its snapshot. Use the same seed as before so that unchanged objects stay the same.
+    #[wasm_bindgen]
+    pub fn repositorify_next(&self, previous: Vec<u8>, options: String) -> Result<Vec<u8>, String>{
+        Ok(create_tar_gz_in_memory(self.next_files(&previous, &options)?).unwrap_or_default())
+    }
+
+    /// The RRDP files of `repositorify_next`: notification, snapshot and deltas with their URIs.
+    #[wasm_bindgen]
+    pub fn rrdp_next(&self, previous: Vec<u8>, options: String) -> Result<Vec<RrdpEntry>, String>{
+        rrdp::entries(&self.next_files(&previous, &options)?)
+    }
+}
+
+impl Repository{
+    pub(crate) fn imported(imported: Imported) -> Repository{
+        Repository{
+            imported: Some(imported),
+            ..Repository::default()
+        }
+    }
+
+    fn files(&self, options: &RepoOptions) -> Result<RepoFiles, String>{
+        match self.imported.as_ref(){
+            Some(imported) => imported.files(options),
+            None if self.entries.is_empty() => Err("Repository has no objects".to_string()),
+            None => files(&self.entries, &self.cas, options),
+        }
+    }
+
+    fn ca_path(&self, name: &str) -> Result<CaPath, String>{
+        match name{
+            "ta" => Ok(vec![]),
+            n => self.cas.iter().find(|(c, _)| c == n).map(|(_, p)| p.clone()).ok_or(format!("Unknown CA {}", n)),
+        }
+    }
+
+    fn next_files(&self, previous: &[u8], options: &str) -> Result<RepoFiles, String>{
+        let previous = read_tar_gz(previous).map_err(|e| format!("Invalid archive: {}", e))?;
+        let options = RepoOptions::from_json(options)?;
+        let mut files = self.files(&options)?;
+        rrdp::next_state(&previous, &mut files)?;
+        layout(files, options.output)
+    }
+
+    fn push(&mut self, object: &State, role: Role, ca: CaPath, file_name: Option<String>) -> Result<(), String>{
+        if self.imported.is_some(){
+            return Err("Objects of an imported repository are replaced with set_object".to_string());
+        }
+        if self.entries.iter().any(|e| e.role == role && e.ca == ca && role != Role::Payload){
+            return Err("Only one object can have this role".to_string());
+        }
+
+        self.entries.push(Entry{
+            object: object.clone(),
+            role,
+            ca,
+            file_name,
+            private_key: None,
+        });
+        Ok(())
+    }
+}
+
+/// Generates the repository and returns the tar.gz archive with TAL and trust anchor certificate.
+pub(crate) fn archive(entries: &[Entry], cas: &[(String, CaPath)], options: &RepoOptions) -> Result<Vec<u8>, String>{
+    let files = layout(files(entries, cas, options)?, options.output)?;
+    Ok(create_tar_gz_in_memory(files).unwrap_or_default())
+}
+
+/// The files of `archive`.
+fn files(entries: &[Entry], cas: &[(String, CaPath)], options: &RepoOptions) -> Result<RepoFiles, String>{
+    let (repo, extra_files) = build_repository(entries, cas, options)?;
+    let (mut files, tal, ca_cert) = State::export_rpki_repo(repo, extra_files, options)?;
+    files.push((ta_certificate_path(&String::from_utf8_lossy(&tal)), ca_cert));
+    files.push(("ta.tal".to_string(), tal));
+    Ok(files)
+}
+
+/// Adds the rsync directories for `output`, for rsync only the RRDP files are removed.
+fn layout(mut files: RepoFiles, output: Output) -> Result<RepoFiles, String>{
+    if output == Output::Rrdp{
+        return Ok(files);
+    }
+    let tree = rrdp::rsync_tree(&files)?;
+    let tal = files.iter().find(|(name, _)| name == "ta.tal").ok_or("Repository has no TAL")?;
+    let tal = rsync_tal(&tal.1)?;
+
+    if output == Output::Rsync{
+        files.retain(|(name, _)| !rrdp::is_rrdp_file(name) && name != "ta.tal");
+        files.push(("ta.tal".to_string(), tal));
+    }
+    else{
+        files.push(("ta-rsync.tal".to_string(), tal));
+    }
+    for file in tree{
+        if !files.iter().any(|(name, _)| *name == file.0){
+            files.push(file);
+        }
+    }
+    Ok(files)
+}
+
+/// Repository with the objects placed in it, and files that belong next to the repository.
+/// `cas` names CAs of the tree, CAs on the paths of the objects are added as needed.
+pub(crate) fn build_repository(entries: &[Entry], cas: &[(String, CaPath)], options: &RepoOptions) -> Result<(CureRepository, RepoFiles), String>{
+    let mut paths: Vec<CaPath> = cas.iter().map(|(_, p)| p.clone()).chain(entries.iter().map(|e| e.ca.clone())).collect();
+    paths.push(chain(options.depth));
+    paths.sort();
+
+    let conf = options.config()?;
+    let mut repo;
+    if paths.iter().any(|p| !p.is_empty()){
+        repo = cure_repo::new_parent_child();
+        let mut template = repo.child_repos[0].clone();
+        template.child_repos.clear();
+        repo.conf = conf.clone();
+        for child in repo.child_repos.iter_mut(){
+            let name = ca_name(&[0], cas).unwrap_or(child.conf.ca_name.clone());
+            child.conf = conf.clone();
+            child.conf.ca_name = name;
+        }
+        for path in paths.iter(){
+            grow(&mut repo, path, &template, cas)?;
+        }
+    }
+    else{
+        repo = new_repo(&conf, &ObjectType::UNKNOWN, true);
+        repo.fix_all_objects(true);
+    }
+
+    let mut seed = options.seed.map(|s| Seed::new(s, options.time.as_deref())).transpose()?;
+    if let Some(seed) = seed.as_mut(){
+        seed.set_keys(&mut repo)?;
+    }
+    if let Some(key) = options.ca_private_key.as_deref(){
+        let path = chain(options.depth);
+        let key = keys::load_key(Some(key))?;
+        let ca = ca_at(&mut repo, &path)?;
+        set_ca_key(ca, &key);
+        // The trust anchor signs its own certificate
+        if path.is_empty(){
+            ca.certificate.parent_key = key;
+        }
+    }
+
+    // Objects cure places are fixed with the repository, the others are added afterwards
+    let mut placed = vec![];
+    let mut late = vec![];
+    let mut cleared = vec![];
+    for entry in entries{
+        let obj_type = entry.object.object_type();
+        let issuer = if entry.role == Role::Ca { &entry.ca[..entry.ca.len() - 1] } else { &entry.ca[..] };
+        let parent_key = ca_at(&mut repo, issuer)?.certificate.child_key.clone();
+        let ca = ca_at(&mut repo, &entry.ca)?;
+
+        let child_key = match (entry.private_key.as_deref(), seed.as_mut()){
+            (Some(key), _) => keys::load_key(Some(key))?,
+            (None, Some(seed)) => seed.key()?,
+            (None, None) => load_random_key(&conf).1,
+        };
+        let base_name = match seed.as_mut(){
+            Some(seed) => seed.name(),
+            None => random_fname(),
+        };
+        let name = match entry.file_name.as_ref(){
+            Some(n) if n.contains('.') => n.clone(),
+            Some(n) => format!("{}.{}", n, obj_type),
+            None => format!("{}.{}", base_name, obj_type),
+        };
+
+        let cure_obj = CureObject::new(
+            ObjectType::from_string(&obj_type),
+            parent_key,
+            child_key.clone(),
+            entry.object.encoded_tree()?,
+            name.clone(),
+        );
+
+        match entry.role{
+            Role::Payload if cure_obj.op_type.is_payload() && !router::is_router_certificate(&entry.object.tree) => {
+                // The payloads of cure's example are replaced
+                if !cleared.contains(&entry.ca){
+                    ca.payloads.clear();
+                    cleared.push(entry.ca.clone());
+                }
+                placed.push((entry.ca.clone(), Placed::Payload(ca.payloads.len()), entry.object.clone()));
+                ca.payloads.push(cure_obj);
+            }
+            Role::Payload => late.push((entry, obj_type, name, child_key)),
+            Role::Manifest => {
+                ca.manifest = cure_obj;
+                placed.push((entry.ca.clone(), Placed::Manifest, entry.object.clone()));
+            }
+            Role::Crl => {
+                ca.crl = cure_obj;
+                placed.push((entry.ca.clone(), Placed::Crl, entry.object.clone()));
+            }
+            Role::Ca => {
+                ca.certificate = cure_obj;
+                if seed.is_some(){
+                    set_ca_key(ca, &child_key);
+                }
+                placed.push((entry.ca.clone(), Placed::Certificate, entry.object.clone()));
+            }
+        }
+    }
+
+    fix_tree(&mut repo);
+
+    for entry in entries.iter().filter(|e| e.role == Role::Manifest && !options.fix_manifest){
+        ca_at(&mut repo, &entry.ca)?.manifest.tree = entry.object.encoded_tree()?;
+    }
+    for entry in entries.iter().filter(|e| e.role == Role::Crl && !options.fix_crl){
+        ca_at(&mut repo, &entry.ca)?.crl.tree = entry.object.encoded_tree()?;
+    }
+
+    // Signed checklists are not published (RFC 9323 2), they are issued once the CA is final
+    let mut checklists = vec![];
+    for (entry, obj_type, name, key) in late{
+        let ca = ca_at(&mut repo, &entry.ca)?;
+        if obj_type == "sig"{
+            checklists.push((entry, name, key));
+        }
+        else if router::is_router_certificate(&entry.object.tree){
+            placed.push((entry.ca.clone(), Placed::Payload(ca.payloads.len()), entry.object.clone()));
+            entry.object.publish_router_certificate(ca, &name, key)?;
+        }
+        else{
+            let ca_cert = State::from_tree(ca.certificate.tree.clone());
+            let mut object = entry.object.clone();
+            object.issue_from(&ca_cert, &ca.certificate.child_key, &key)?;
+            placed.push((entry.ca.clone(), Placed::Payload(ca.payloads.len()), entry.object.clone()));
+            publish(ca, &name, &object, key)?;
+        }
+    }
+
+    if let Some(seed) = seed.as_mut(){
+        seed.normalize(&mut repo, &placed, options)?;
+    }
+
+    let mut extra_files = vec![];
+    for (entry, name, key) in checklists{
+        let ca = ca_at(&mut repo, &entry.ca)?;
+        let ca_cert = State::from_tree(ca.certificate.tree.clone());
+        let mut rsc = entry.object.clone();
+        rsc.issue_from(&ca_cert, &ca.certificate.child_key, &key)?;
+        extra_files.push((format!("data/rsc/{}", name), rsc.encode()));
+    }
+    Ok((repo, extra_files))
+}
@@ -0,0 +1,265 @@
+use sha2::{Digest, Sha256};
+use cure_pp::repository_util::ObjectKey;
+use wasm_bindgen::prelude::*;
+
+use crate::{cms, der, encoder, keys, tree_util, State};
+
+/// Steps of `State::resign`, each can be disabled to leave that part stale on purpose.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ResignOptions{
+    pub ee_key: bool, // Put the public key of the signing key into the EE certificate (SPKI, SKI, sid)
+    pub ee_signature: bool, // Sign the EE certificate with the issuer key
+    pub message_digest: bool, // Write the digest of the eContent into the messageDigest attribute
+    pub signature: bool, // Sign the signed attributes with the EE key
+    pub ee_private_key: Option<String>, // Base64 or PEM, a random key is used if not set. Required without ee_key
+    pub issuer_private_key: Option<String>,
+}
+
+impl Default for ResignOptions{
+    fn default() -> Self{
+        ResignOptions{
+            ee_key: true,
+            ee_signature: true,
+            message_digest: true,
+            signature: true,
+            ee_private_key: None,
+            issuer_private_key: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct ResignReport{
+    pub econtent_digest: String,
+    pub ee_key_identifier: Option<String>,
+    pub steps: Vec<String>,
+}
+
+#[wasm_bindgen]
+impl State{
+    /// Recomputes digest and signatures of an RPKI signed object after its content was edited.
+    /// Takes the options as JSON (see `ResignOptions`), returns a JSON report of what was done.
+    #[wasm_bindgen]
+    pub fn resign(&mut self, options: String) -> Result<String, String>{
+        let options: ResignOptions = if options.trim().is_empty(){
+            ResignOptions::default()
+        }
+        else{
+            serde_json::from_str(&options).map_err(|e| format!("Invalid options: {}", e))?
+        };
+
+        // The EE certificate keeps its key, so only that key gives a signature that matches it
+        if !options.ee_key && options.signature && options.ee_private_key.is_none(){
+            return Err("ee_private_key is required if the key of the EE certificate is kept".to_string());
+        }
+
+        let ee_key = keys::load_key(options.ee_private_key.as_deref())?;
+        if !options.ee_key && options.signature{
+            let obj = cms::signed_object(&self.tree)?;
+            let cert = cms::certificate(&self.tree, obj.certificate.ok_or("Object has no EE certificate")?)?;
+            if encoder::encode_element(&self.tree, cert.spki, &self.overrides) != keys::subject_public_key_info(&ee_key){
+                return Err("ee_private_key is not the key of the EE certificate".to_string());
+            }
+        }
+        let issuer_key = match options.ee_signature{
+            true => Some(keys::load_key(options.issuer_private_key.as_deref())?),
+            false => None,
+        };
+
+        let report = self.resign_with(&options, &ee_key, issuer_key.as_ref())?;
+        Ok(serde_json::to_string(&report).unwrap())
+    }
+
+    /// Signs the certificate, or the EE certificate of a signed object, with the issuer key
+    /// (base64 or PEM). A random key is used if it is empty.
+    #[wasm_bindgen]
+    pub fn sign_certificate(&mut self, issuer_private_key: String) -> Result<(), String>{
+        let key = keys::load_key(Some(&issuer_private_key))?;
+        self.sign_certificate_with(&key)
+    }
+}
+
+impl State{
+    /// The steps of `resign` with given keys, the key fields of `options` are ignored.
+    pub(crate) fn resign_with(&mut self, options: &ResignOptions, ee_key: &ObjectKey, issuer_key: Option<&ObjectKey>) -> Result<ResignReport, String>{
+        let obj = cms::signed_object(&self.tree)?;
+        let signer = cms::signer_info(&self.tree, obj.signer_info)?;
+        if !tree_util::is_oid(&self.tree, signer.digest_algorithm, cms::OID_SHA256){
+            return Err("Only SHA-256 is supported as digest algorithm".to_string());
+        }
+
+        let mut report = ResignReport::default();
+
+        if options.ee_key{
+            let cert = cms::certificate(&self.tree, obj.certificate.ok_or("Object has no EE certificate")?)?;
+            let spki = tree_util::replace_node(&mut self.tree, cert.spki, &keys::subject_public_key_info(ee_key))?;
+
+            let bits = tree_util::child(&self.tree, spki, 1, "subjectPublicKey")?;
+            let key_id = keys::key_identifier(&tree_util::string_payload(&self.tree, bits, &self.overrides));
+
+            if let Some(ski) = cert.extensions.and_then(|e| cms::extension_value(&self.tree, e, cms::OID_SKI)){
+                tree_util::set_string_payload(&mut self.tree, ski, &crate::der::tlv(0x04, &key_id))?;
+            }
+
+            // RPKI signed objects identify the signer by its subject key identifier
+            if tree_util::tag(&self.tree, signer.sid) == 0x80{
+                tree_util::set_content(&mut self.tree, signer.sid, key_id.clone());
+            }
+
+            report.ee_key_identifier = Some(hex::encode(&key_id));
+            report.steps.push("ee_key".to_string());
+        }
+
+        if options.ee_signature{
+            self.sign_certificate_with(issuer_key.ok_or("No issuer key given")?)?;
+            report.steps.push("ee_signature".to_string());
+        }
+
+        let econtent = tree_util::string_payload(&self.tree, obj.econtent, &self.overrides);
+        let digest = Sha256::digest(&econtent).to_vec();
+        report.econtent_digest = hex::encode(&digest);
+
+        if options.message_digest{
+            let attrs = signer.signed_attrs.ok_or("SignerInfo has no signed attributes")?;
+            let md = cms::signed_attribute(&self.tree, attrs, cms::OID_MESSAGE_DIGEST).ok_or("Missing messageDigest attribute")?;
+            tree_util::set_string_payload(&mut self.tree, md, &digest)?;
+            report.steps.push("message_digest".to_string());
+        }
+
+        if options.signature{
+            // The signature covers the DER encoding of the signed attributes with a SET OF tag
+            let signed = match signer.signed_attrs{
+                Some(attrs) => {
+                    let mut encoded = encoder::encode_element(&self.tree, attrs, &self.overrides);
+                    if let Some(tag) = encoded.first_mut(){
+                        *tag = 0x31;
+                    }
+                    encoded
+                }
+                None => econtent,
+            };
+            tree_util::set_string_payload(&mut self.tree, signer.signature, &ee_key.sign(&signed))?;
+            report.steps.push("signature".to_string());
+        }
+
+        self.overrides.retain_existing(&self.tree);
+        Ok(report)
+    }
+
+    /// Signs the certificate (or EE certificate) with `key`.
+    pub(crate) fn sign_certificate_with(&mut self, key: &ObjectKey) -> Result<(), String>{
+        let cert = cms::certificate(&self.tree, cms::object_certificate(&self.tree)?)?;
+        let tbs = encoder::encode_element(&self.tree, cert.tbs, &self.overrides);
+        tree_util::set_string_payload(&mut self.tree, cert.signature, &key.sign(&tbs))?;
+        self.overrides.retain_existing(&self.tree);
+        Ok(())
+    }
+
+    /// Signs the TBSCertList of a CRL with `key`.
+    pub(crate) fn sign_crl_with(&mut self, key: &ObjectKey) -> Result<(), String>{
+        let crl = cms::crl(&self.tree)?;
+        let signature = tree_util::child(&self.tree, self.tree.root_id, 2, "signature")?;
+        let tbs = encoder::encode_element(&self.tree, crl.tbs, &self.overrides);
+        tree_util::set_string_payload(&mut self.tree, signature, &key.sign(&tbs))?;
+        self.overrides.retain_existing(&self.tree);
+        Ok(())
+    }
+
+    /// Puts the public key of `key` into the certificate and updates its SKI, it is not signed again.
+    pub(crate) fn set_certificate_key(&mut self, key: &ObjectKey) -> Result<(), String>{
+        let cert = cms::certificate(&self.tree, cms::object_certificate(&self.tree)?)?;
+        tree_util::replace_node(&mut self.tree, cert.spki, &keys::subject_public_key_info(key))?;
+        self.fix_key_identifiers().map(|_| ())
+    }
+
+    /// Takes issuer name and AKI of the CRL from the CA certificate `ca`, then signs it with `ca_key`.
+    pub(crate) fn issue_crl_from(&mut self, ca: &State, ca_key: &ObjectKey) -> Result<(), String>{
+        let ca_cert = cms::certificate(&ca.tree, cms::object_certificate(&ca.tree)?)?;
+        let subject = encoder::encode_element(&ca.tree, ca_cert.subject, &ca.overrides);
+        let ids = ca.key_identifiers()?;
+        let ski = ids.ski.unwrap_or(ids.expected_ski);
+
+        // The issuer is the field before thisUpdate
+        let crl = cms::crl(&self.tree)?;
+        let fields = tree_util::children(&self.tree, crl.tbs);
+        let issuer = fields.iter().position(|f| *f == crl.this_update).and_then(|p| p.checked_sub(1)).map(|p| fields[p]).ok_or("CRL has no issuer")?;
+        tree_util::replace_node(&mut self.tree, issuer, &subject)?;
+
+        let crl = cms::crl(&self.tree)?;
+        if let Some(aki) = crl.extensions.and_then(|e| cms::extension_value(&self.tree, e, cms::OID_AKI)){
+            let value = der::tlv(0x30, &der::tlv(0x80, &hex::decode(&ski).unwrap()));
+            tree_util::set_string_payload(&mut self.tree, aki, &value)?;
+        }
+        self.sign_crl_with(ca_key)
+    }
+
+    /// Takes issuer name and AKI of the certificate (or EE certificate) from the CA certificate `ca`.
+    fn adopt_issuer(&mut self, ca: &State) -> Result<(), String>{
+        let ca_cert = cms::certificate(&ca.tree, cms::object_certificate(&ca.tree)?)?;
+        let subject = encoder::encode_element(&ca.tree, ca_cert.subject, &ca.overrides);
+
+        let cert = cms::certificate(&self.tree, cms::object_certificate(&self.tree)?)?;
+        tree_util::replace_node(&mut self.tree, cert.issuer, &subject)?;
+        self.link_to_parent(ca).map(|_| ())
+    }
+
+    /// Makes this signed object a child of the CA certificate `ca`: issuer name and AKI are taken
+    /// from the CA, then the EE certificate is signed with `ca_key` and the object with `ee_key`.
+    pub(crate) fn issue_from(&mut self, ca: &State, ca_key: &ObjectKey, ee_key: &ObjectKey) -> Result<(), String>{
+        self.adopt_issuer(ca)?;
+        self.resign_with(&ResignOptions::default(), ee_key, Some(ca_key)).map(|_| ())
+    }
+
+    /// Like `issue_from` for a certificate that is not part of a signed object, e.g. a router certificate.
+    pub(crate) fn issue_certificate_from(&mut self, ca: &State, ca_key: &ObjectKey) -> Result<(), String>{
+        self.adopt_issuer(ca)?;
+        self.sign_certificate_with(ca_key)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn resign(state: &mut State, options: &str) -> Result<ResignReport, String>{
+        state.resign(options.to_string()).map(|r| serde_json::from_str(&r).unwrap())
+    }
+
+    #[test]
+    fn steps(){
+        let mut state = State::load_example("roa").unwrap();
+        state.roa_set(r#"{"asn": 64496, "prefixes": [{"prefix": "192.0.2.0/24"}]}"#.to_string(), false).unwrap();
+        let report = resign(&mut state, "").unwrap();
+        assert_eq!(report.steps, ["ee_key", "ee_signature", "message_digest", "signature"]);
+        assert_eq!(report.ee_key_identifier.unwrap(), state.key_identifiers().unwrap().expected_ski);
+
+        let obj = cms::signed_object(&state.tree).unwrap();
+        let econtent = tree_util::string_payload(&state.tree, obj.econtent, &state.overrides);
+        assert_eq!(report.econtent_digest, hex::encode(Sha256::digest(econtent)));
+
+        // Disabled steps leave their part as it is
+        let encoded = state.encode();
+        let report = resign(&mut state, r#"{"ee_key": false, "ee_signature": false, "signature": false}"#).unwrap();
+        assert_eq!(report.steps, ["message_digest"]);
+        assert_eq!(state.encode(), encoded);
+    }
+
+    #[test]
+    fn invalid_options(){
+        let mut state = State::load_example("roa").unwrap();
+        assert!(resign(&mut state, "{").is_err());
+        assert!(resign(&mut state, r#"{"ee_key": false}"#).is_err());
+        assert!(resign(&mut state, r#"{"ee_private_key": "!"}"#).is_err());
+        assert!(resign(&mut State::load_example("crl").unwrap(), "").is_err());
+    }
+
+    #[test]
+    fn empty_signed_attributes(){
+        let mut state = State::load_example("roa").unwrap();
+        let obj = cms::signed_object(&state.tree).unwrap();
+        let attrs = cms::signer_info(&state.tree, obj.signer_info).unwrap().signed_attrs.unwrap();
+        state.overrides.nodes.insert(attrs, vec![]);
+        assert!(resign(&mut state, r#"{"message_digest": false}"#).is_ok());
+    }
+}
@@ -0,0 +1,430 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use cure_asn1::tree_parser::Tree;
+
+use crate::{der, tree_util::{child_by_tag, children, tag}};
+
+// Reads and writes RFC 3779 IP and AS resources. Addresses of both families are kept as u128,
+// ranges are inclusive.
+
+pub const OID_IP_RESOURCES: &str = "1.3.6.1.5.5.7.1.7";
+pub const OID_AS_RESOURCES: &str = "1.3.6.1.5.5.7.1.8";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IpFamily{
+    pub afi: u16, // 1 IPv4, 2 IPv6
+    pub inherit: bool,
+    pub ranges: Vec<(u128, u128)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AsResources{
+    pub inherit: bool,
+    pub ranges: Vec<(u128, u128)>,
+}
+
+pub fn address_bits(afi: u16) -> Option<u32>{
+    match afi{
+        1 => Some(32),
+        2 => Some(128),
+        _ => None,
+    }
+}
+
+pub fn parse_afi(data: &[u8]) -> Option<u16>{
+    // An optional third octet carries the SAFI
+    if data.len() < 2 || data.len() > 3{
+        return None;
+    }
+    Some(u16::from_be_bytes([data[0], data[1]]))
+}
+
+/// Address and prefix length of a BIT STRING content, including the unused-bits octet.
+pub fn parse_prefix(data: &[u8], bits: u32) -> Option<(u128, u32)>{
+    let (unused, bytes) = data.split_first()?;
+    if *unused > 7 || (bytes.is_empty() && *unused != 0) || bytes.len() as u32 * 8 > bits{
+        return None;
+    }
+
+    let mut addr = 0u128;
+    for (i, b) in bytes.iter().enumerate(){
+        addr |= (*b as u128) << (bits - 8 * (i as u32 + 1));
+    }
+    Some((addr, bytes.len() as u32 * 8 - *unused as u32))
+}
+
+/// First and last address covered by a prefix.
+pub fn prefix_range(addr: u128, len: u32, bits: u32) -> (u128, u128){
+    let host = bits - len;
+    let mask = if host >= 128 { u128::MAX } else { (1u128 << host) - 1 };
+    (addr & !mask, addr | mask)
+}
+
+/// Range covered by an IPAddressOrRange node, a prefix BIT STRING or a SEQUENCE of min and max.
+pub fn address_or_range(tree: &Tree, id: usize, bits: u32) -> Option<(u128, u128)>{
+    match tag(tree, id){
+        0x03 => {
+            let (addr, len) = parse_prefix(&tree.tokens[&id].data, bits)?;
+            Some(prefix_range(addr, len, bits))
+        }
+        0x30 => {
+            let c = children(tree, id);
+            if c.len() != 2{
+                return None;
+            }
+            let (min, _) = parse_prefix(&tree.tokens[&c[0]].data, bits)?;
+            // Missing bits of the maximum are ones
+            let (max, len) = parse_prefix(&tree.tokens[&c[1]].data, bits)?;
+            Some((min, prefix_range(max, len, bits).1))
+        }
+        _ => None,
+    }
+}
+
+/// IPAddrBlocks, a SEQUENCE of IPAddressFamily.
+pub fn ip_resources(tree: &Tree, id: usize) -> Result<Vec<IpFamily>, String>{
+    let mut ret = vec![];
+    for family in children(tree, id){
+        let c = children(tree, *family);
+        if c.len() != 2{
+            return Err("IPAddressFamily must have addressFamily and ipAddressChoice".to_string());
+        }
+        let afi = parse_afi(&tree.tokens[&c[0]].data).ok_or("Invalid addressFamily".to_string())?;
+        let bits = address_bits(afi).ok_or(format!("Unknown address family {}", afi))?;
+
+        let mut entry = IpFamily{
+            afi,
+            inherit: tag(tree, c[1]) == 0x05,
+            ranges: vec![],
+        };
+        if !entry.inherit{
+            for a in children(tree, c[1]){
+                entry.ranges.push(address_or_range(tree, *a, bits).ok_or("Invalid IPAddressOrRange".to_string())?);
+            }
+        }
+        ret.push(entry);
+    }
+    Ok(ret)
+}
+
+/// ASIdentifiers, only the asnum part is read.
+pub fn as_resources(tree: &Tree, id: usize) -> Result<AsResources, String>{
+    let mut ret = AsResources::default();
+    let choice = match child_by_tag(tree, id, 0xA0).and_then(|a| children(tree, a).first().cloned()){
+        Some(c) => c,
+        None => return Ok(ret),
+    };
+
+    if tag(tree, choice) == 0x05{
+        ret.inherit = true;
+        return Ok(ret);
+    }
+
+    for a in children(tree, choice){
+        let range = match tag(tree, *a){
+            0x02 => der::parse_uint(&tree.tokens[a].data).map(|v| (v, v)),
+            0x30 => {
+                let c = children(tree, *a);
+                match (c.first(), c.get(1)){
+                    (Some(min), Some(max)) => der::parse_uint(&tree.tokens[min].data).zip(der::parse_uint(&tree.tokens[max].data)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        ret.ranges.push(range.ok_or("Invalid ASIdOrRange".to_string())?);
+    }
+    Ok(ret)
+}
+
+/// Sorted ranges with overlapping and adjacent ones joined.
+pub fn merge(ranges: &[(u128, u128)]) -> Vec<(u128, u128)>{
+    let mut sorted = ranges.to_vec();
+    sorted.sort();
+
+    let mut ret: Vec<(u128, u128)> = vec![];
+    for (min, max) in sorted{
+        match ret.last_mut(){
+            Some(last) if min <= last.1.saturating_add(1) => last.1 = last.1.max(max),
+            _ => ret.push((min, max)),
+        }
+    }
+    ret
+}
+
+/// Whether `range` lies completely within `ranges`.
+pub fn covered(ranges: &[(u128, u128)], range: (u128, u128)) -> bool{
+    merge(ranges).iter().any(|(min, max)| *min <= range.0 && range.1 <= *max)
+}
+
+pub fn format_address(afi: u16, addr: u128) -> String{
+    match afi{
+        1 => Ipv4Addr::from(addr as u32).to_string(),
+        _ => Ipv6Addr::from(addr).to_string(),
+    }
+}
+
+pub fn format_prefix(afi: u16, addr: u128, len: u32) -> String{
+    format!("{}/{}", format_address(afi, addr), len)
+}
+
+/// BIT STRING content of the first `len` bits of `addr`, including the unused-bits octet.
+pub fn encode_bits(addr: u128, len: u32, bits: u32) -> Vec<u8>{
+    let num = len.div_ceil(8);
+    let mut ret = vec![(num * 8 - len) as u8];
+    for i in 0..num{
+        ret.push((addr >> (bits - 8 * (i + 1))) as u8);
+    }
+    // Bits after the prefix are zero
+    if let Some(last) = ret.last_mut().filter(|_| num > 0){
+        *last &= 0xFFu8 << (num * 8 - len);
+    }
+    ret
+}
+
+/// Prefix length if the range is exactly one prefix.
+pub fn range_as_prefix(min: u128, max: u128, bits: u32) -> Option<u32>{
+    let host = min.trailing_zeros().min(max.trailing_ones()).min(bits);
+    let mask = if host >= 128 { u128::MAX } else { (1u128 << host) - 1 };
+    (min | mask == max).then_some(bits - host)
+}
+
+/// IPAddressOrRange, a prefix where possible (RFC 3779 2.2.3.7), otherwise a range with
+/// trailing zeros of the minimum and trailing ones of the maximum left out.
+pub fn encode_address_or_range(min: u128, max: u128, bits: u32) -> Vec<u8>{
+    if let Some(len) = range_as_prefix(min, max, bits){
+        return der::tlv(0x03, &encode_bits(min, len, bits));
+    }
+    let min_len = bits - min.trailing_zeros().min(bits);
+    let max_len = bits - max.trailing_ones().min(bits);
+
+    let mut content = der::tlv(0x03, &encode_bits(min, min_len, bits));
+    content.extend(der::tlv(0x03, &encode_bits(max, max_len, bits)));
+    der::tlv(0x30, &content)
+}
+
+/// DER IPAddrBlocks in canonical form, families sorted and ranges sorted and joined.
+pub fn encode_ip_resources(families: &[IpFamily]) -> Vec<u8>{
+    let mut sorted = families.to_vec();
+    sorted.sort_by_key(|f| f.afi);
+
+    let mut content = vec![];
+    for family in sorted{
+        let bits = address_bits(family.afi).unwrap_or(128);
+        let choice = if family.inherit{
+            der::tlv(0x05, &[])
+        }
+        else{
+            let ranges = merge(&family.ranges).iter().flat_map(|(min, max)| encode_address_or_range(*min, *max, bits)).collect::<Vec<u8>>();
+            der::tlv(0x30, &ranges)
+        };
+
+        let mut f = der::tlv(0x04, &family.afi.to_be_bytes());
+        f.extend(choice);
+        content.extend(der::tlv(0x30, &f));
+    }
+    der::tlv(0x30, &content)
+}
+
+/// DER ASIdentifiers with only asnum, ranges sorted and joined.
+pub fn encode_as_resources(asn: &AsResources) -> Vec<u8>{
+    let choice = if asn.inherit{
+        der::tlv(0x05, &[])
+    }
+    else{
+        let mut ids = vec![];
+        for (min, max) in merge(&asn.ranges){
+            if min == max{
+                ids.extend(der::tlv(0x02, &der::encode_uint(min)));
+            }
+            else{
+                let mut range = der::tlv(0x02, &der::encode_uint(min));
+                range.extend(der::tlv(0x02, &der::encode_uint(max)));
+                ids.extend(der::tlv(0x30, &range));
+            }
+        }
+        der::tlv(0x30, &ids)
+    };
+    der::tlv(0x30, &der::tlv(0xA0, &choice))
+}
+
+/// A range as prefix if possible, otherwise as `min-max`.
+pub fn format_ip_range(afi: u16, min: u128, max: u128) -> String{
+    let bits = address_bits(afi).unwrap_or(128);
+    match range_as_prefix(min, max, bits){
+        Some(len) => format_prefix(afi, min, len),
+        None => format!("{}-{}", format_address(afi, min), format_address(afi, max)),
+    }
+}
+
+pub fn format_as_range(min: u128, max: u128) -> String{
+    if min == max{
+        format!("AS{}", min)
+    }
+    else{
+        format!("AS{}-AS{}", min, max)
+    }
+}
+
+fn parse_address(value: &str) -> Result<(u16, u128), String>{
+    match value.trim().parse::<IpAddr>().map_err(|_| format!("Invalid address {}", value))?{
+        IpAddr::V4(a) => Ok((1, u32::from(a) as u128)),
+        IpAddr::V6(a) => Ok((2, u128::from(a))),
+    }
+}
+
+fn parse_asn(value: &str) -> Result<u128, String>{
+    let v = value.trim();
+    let v = v.strip_prefix("AS").or(v.strip_prefix("as")).unwrap_or(v);
+    v.parse::<u32>().map(|a| a as u128).map_err(|_| format!("Invalid AS number {}", value))
+}
+
+/// Parses a list like `10.0.0.0/8, 192.168.0.0-192.168.3.255, AS64496-AS64511, inherit`.
+/// A plain `inherit` applies to all resource types, `ipv4 inherit`, `ipv6 inherit` and
+/// `as inherit` to one. Returns the IP families and the AS resources, None if not mentioned.
+pub fn parse_resource_list(text: &str) -> Result<(Vec<IpFamily>, Option<AsResources>), String>{
+    let mut families: Vec<IpFamily> = vec![];
+    let mut asn: Option<AsResources> = None;
+
+    let family = |families: &mut Vec<IpFamily>, afi: u16| -> usize{
+        match families.iter().position(|f| f.afi == afi){
+            Some(p) => p,
+            None => {
+                families.push(IpFamily{ afi, inherit: false, ranges: vec![] });
+                families.len() - 1
+            }
+        }
+    };
+
+    for item in text.split([',', '\n', ';']).map(|i| i.trim()).filter(|i| !i.is_empty()){
+        let lower = item.to_lowercase().replace(':', " ");
+        let words = lower.split_whitespace().collect::<Vec<&str>>();
+
+        if words.last() == Some(&"inherit"){
+            let targets: &[&str] = match words.as_slice(){
+                ["inherit"] => &["ipv4", "ipv6", "as"],
+                [t, "inherit"] => std::slice::from_ref(t),
+                _ => return Err(format!("Invalid entry {}", item)),
+            };
+            for t in targets{
+                match *t{
+                    "ipv4" | "ipv6" => {
+                        let p = family(&mut families, if *t == "ipv4" { 1 } else { 2 });
+                        families[p].inherit = true;
+                    }
+                    "as" | "asn" => asn.get_or_insert_with(AsResources::default).inherit = true,
+                    _ => return Err(format!("Unknown resource type {}, use ipv4, ipv6 or as", t)),
+                }
+            }
+            continue;
+        }
+
+        if item.to_uppercase().starts_with("AS"){
+            let (min, max) = match item.split_once('-'){
+                Some((a, b)) => (parse_asn(a)?, parse_asn(b)?),
+                None => (parse_asn(item)?, parse_asn(item)?),
+            };
+            if min > max{
+                return Err(format!("Invalid AS range {}", item));
+            }
+            asn.get_or_insert_with(AsResources::default).ranges.push((min, max));
+            continue;
+        }
+
+        let (afi, range) = if let Some((addr, len)) = item.split_once('/'){
+            let (afi, addr) = parse_address(addr)?;
+            let bits = address_bits(afi).unwrap();
+            let len = len.trim().parse::<u32>().ok().filter(|l| *l <= bits).ok_or(format!("Invalid prefix length in {}", item))?;
+            let range = prefix_range(addr, len, bits);
+            if range.0 != addr{
+                return Err(format!("Prefix {} has bits set after the prefix length", item));
+            }
+            (afi, range)
+        }
+        else if let Some((min, max)) = item.split_once('-'){
+            let (afi, min) = parse_address(min)?;
+            let (afi_max, max) = parse_address(max)?;
+            if afi != afi_max || min > max{
+                return Err(format!("Invalid address range {}", item));
+            }
+            (afi, (min, max))
+        }
+        else{
+            let (afi, addr) = parse_address(item)?;
+            (afi, (addr, addr))
+        };
+
+        let p = family(&mut families, afi);
+        families[p].ranges.push(range);
+    }
+
+    for f in families.iter(){
+        if f.inherit && !f.ranges.is_empty(){
+            return Err(format!("IPv{} resources cannot be inherited and listed at the same time", if f.afi == 1 { 4 } else { 6 }));
+        }
+    }
+    if asn.as_ref().map(|a| a.inherit && !a.ranges.is_empty()).unwrap_or(false){
+        return Err("AS resources cannot be inherited and listed at the same time".to_string());
+    }
+    Ok((families, asn))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const V4: u32 = 32;
+
+    #[test]
+    fn prefixes(){
+        assert_eq!(range_as_prefix(0x0A00_0000, 0x0AFF_FFFF, V4), Some(8));
+        assert_eq!(range_as_prefix(0, u32::MAX as u128, V4), Some(0));
+        assert_eq!(range_as_prefix(0x0A00_0001, 0x0A00_0001, V4), Some(32));
+        assert_eq!(range_as_prefix(0x0A00_0000, 0x0A00_0002, V4), None);
+        assert_eq!(range_as_prefix(0x0A00_0001, 0x0A00_0002, V4), None);
+    }
+
+    #[test]
+    fn encode_prefix(){
+        assert_eq!(encode_address_or_range(0x0A00_0000, 0x0AFF_FFFF, V4), [0x03, 0x02, 0x00, 0x0A]);
+        assert_eq!(encode_address_or_range(0, u32::MAX as u128, V4), [0x03, 0x01, 0x00]);
+        // 10.64.0.0/12 leaves 4 unused bits
+        assert_eq!(encode_address_or_range(0x0A40_0000, 0x0A4F_FFFF, V4), [0x03, 0x03, 0x04, 0x0A, 0x40]);
+    }
+
+    #[test]
+    fn encode_range(){
+        // 10.0.0.0 - 10.0.0.2, trailing zeros of the minimum and ones of the maximum are left out
+        let expected = [0x30, 0x0B, 0x03, 0x02, 0x01, 0x0A, 0x03, 0x05, 0x00, 0x0A, 0x00, 0x00, 0x02];
+        assert_eq!(encode_address_or_range(0x0A00_0000, 0x0A00_0002, V4), expected);
+    }
+
+    #[test]
+    fn round_trip(){
+        let v6 = 0x2001_0db8u128 << 96;
+        let ranges = [
+            (0x0A00_0000, 0x0AFF_FFFF, V4),
+            (0x0A00_0000, 0x0A00_0002, V4),
+            (0xC000_0201, 0xC000_02FE, V4),
+            (0, u32::MAX as u128, V4),
+            (v6, v6 | ((1u128 << 96) - 1), 128),
+            (v6 | 1, v6 | 0x1_0000, 128),
+        ];
+        for (min, max, bits) in ranges{
+            let encoded = encode_address_or_range(min, max, bits);
+            let tree = cure_asn1::interface::parse_tree(&encoded, "").unwrap();
+            assert_eq!(address_or_range(&tree, tree.root_id, bits), Some((min, max)), "{:x}-{:x}", min, max);
+        }
+    }
+
+    #[test]
+    fn canonical_resources(){
+        let families = [IpFamily{ afi: 1, inherit: false, ranges: vec![(0x0A00_0100, 0x0A00_01FF), (0x0A00_0000, 0x0A00_00FF)] }];
+        let encoded = encode_ip_resources(&families);
+        let tree = cure_asn1::interface::parse_tree(&encoded, "").unwrap();
+
+        // Adjacent ranges are joined into 10.0.0.0/23
+        let parsed = ip_resources(&tree, tree.root_id).unwrap();
+        assert_eq!(parsed, vec![IpFamily{ afi: 1, inherit: false, ranges: vec![(0x0A00_0000, 0x0A00_01FF)] }]);
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+
+use cure_asn1::rpki_utils::parse_ip_from_string;
+use wasm_bindgen::prelude::*;
+
+use crate::{cms, der, encode_oid_from_string, resources::{self, IpFamily}, tree_util::{self, children, tag}, State};
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RoaPrefix{
+    pub prefix: String, // e.g. 10.0.0.0/8
+    #[serde(rename = "maxLength", default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<u32>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RoaContent{
+    pub asn: u32,
+    pub prefixes: Vec<RoaPrefix>,
+}
+
+/// Prefix of `roa_set` with its encoding, ordered by address and length.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Entry{
+    addr: u128,
+    len: u32,
+    encoded: Vec<u8>, // BIT STRING content
+    max_length: Option<u32>,
+}
+
+#[wasm_bindgen]
+impl State{
+    /// Returns AS and prefixes of a ROA as JSON: `{asn, prefixes: [{prefix, maxLength}]}`.
+    #[wasm_bindgen]
+    pub fn roa_get(&self) -> Result<String, String>{
+        let econtent = self.signed_payload(cms::OID_CT_ROA, "ROA")?;
+        let p = tree_util::payload(&self.tree, econtent, &self.overrides).ok_or("eContent is not valid ASN.1")?;
+        let t = &p.tree;
+
+        // Skip an explicit version
+        let c = children(t, p.root);
+        let c = if c.first().map(|v| tag(t, *v) == 0xA0).unwrap_or(false) { &c[1..] } else { c };
+        if c.len() != 2{
+            return Err("RouteOriginAttestation must contain asID and ipAddrBlocks".to_string());
+        }
+
+        let asn = der::parse_uint(&t.tokens[&c[0]].data).filter(|a| *a <= u32::MAX as u128).ok_or("Invalid asID")?;
+
+        let mut prefixes = vec![];
+        for family in children(t, c[1]){
+            let f = children(t, *family);
+            let afi = f.first().and_then(|a| resources::parse_afi(&t.tokens[a].data)).ok_or("Invalid addressFamily")?;
+            let bits = resources::address_bits(afi).ok_or(format!("Unknown address family {}", afi))?;
+
+            for address in f.get(1).map(|a| children(t, *a)).unwrap_or(&[]){
+                let a = children(t, *address);
+                let (addr, len) = a.first().and_then(|b| resources::parse_prefix(&t.tokens[b].data, bits)).ok_or("Invalid prefix")?;
+                let prefix = resources::format_prefix(afi, addr, len);
+                let max_length = a.get(1)
+                    .map(|m| der::parse_uint(&t.tokens[m].data).and_then(|m| u32::try_from(m).ok()).ok_or(format!("Invalid maxLength of {}", prefix)))
+                    .transpose()?;
+                prefixes.push(RoaPrefix{ prefix, max_length });
+            }
+        }
+
+        let content = RoaContent{
+            asn: asn as u32,
+            prefixes,
+        };
+        Ok(serde_json::to_string(&content).unwrap())
+    }
+
+    /// Rebuilds the ROA payload from JSON in the format of `roa_get`. Address families and prefixes are
+    /// sorted, maxLength is written as given so invalid values are possible.
+    /// With `update_ee` the IP resources of the EE certificate are set to the prefixes.
+    /// Digest and signatures are not updated, see `resign`.
+    #[wasm_bindgen]
+    pub fn roa_set(&mut self, content: String, update_ee: bool) -> Result<(), String>{
+        let content: RoaContent = serde_json::from_str(&content).map_err(|e| format!("Invalid ROA: {}", e))?;
+        let econtent = self.signed_payload(cms::OID_CT_ROA, "ROA")?;
+
+        let mut families: BTreeMap<u16, Vec<Entry>> = BTreeMap::new();
+        for p in content.prefixes.iter(){
+            let afi = if p.prefix.contains(':') { 2 } else { 1 };
+            let encoded = parse_ip_from_string(p.prefix.trim()).map_err(|_| format!("Invalid prefix {}", p.prefix))?;
+            let (addr, len) = resources::parse_prefix(&encoded, resources::address_bits(afi).unwrap()).ok_or(format!("Invalid prefix {}", p.prefix))?;
+            families.entry(afi).or_default().push(Entry{ addr, len, encoded, max_length: p.max_length });
+        }
+
+        let mut blocks = vec![];
+        for (afi, entries) in families.iter_mut(){
+            entries.sort();
+            entries.dedup();
+
+            let mut addresses = vec![];
+            for entry in entries.iter(){
+                let mut a = der::tlv(0x03, &entry.encoded);
+                if let Some(m) = entry.max_length{
+                    a.extend(der::tlv(0x02, &der::encode_uint(m as u128)));
+                }
+                addresses.extend(der::tlv(0x30, &a));
+            }
+
+            let mut family = der::tlv(0x04, &afi.to_be_bytes());
+            family.extend(der::tlv(0x30, &addresses));
+            blocks.extend(der::tlv(0x30, &family));
+        }
+
+        // An explicit version is kept as it is
+        let mut roa = vec![];
+        if let Some(version) = self.payload_version(econtent){
+            roa.extend(version);
+        }
+        roa.extend(der::tlv(0x02, &der::encode_uint(content.asn as u128)));
+        roa.extend(der::tlv(0x30, &blocks));
+        tree_util::set_string_payload(&mut self.tree, econtent, &der::tlv(0x30, &roa))?;
+
+        if update_ee{
+            let resources = families.iter().map(|(afi, entries)| {
+                let bits = resources::address_bits(*afi).unwrap();
+                IpFamily{
+                    afi: *afi,
+                    inherit: false,
+                    ranges: entries.iter().map(|e| resources::prefix_range(e.addr, e.len, bits)).collect(),
+                }
+            }).collect::<Vec<IpFamily>>();
+            self.set_cert_extension(resources::OID_IP_RESOURCES, true, Some(&resources::encode_ip_resources(&resources)))?;
+        }
+
+        self.overrides.retain_existing(&self.tree);
+        Ok(())
+    }
+}
+
+impl State{
+    /// The eContent OCTET STRING, if the object is a signed object of the given type.
+    pub(crate) fn signed_payload(&self, content_type: &str, name: &str) -> Result<usize, String>{
+        let obj = cms::signed_object(&self.tree)?;
+        if !tree_util::is_oid(&self.tree, obj.content_type, content_type){
+            return Err(format!("Object is not a {}", name));
+        }
+        Ok(obj.econtent)
+    }
+
+    /// The ROA example with another eContentType, for types the object generator does not know.
+    /// The payload is still the ROA until it is replaced.
+    pub(crate) fn example_signed_object(content_type: &str) -> Result<State, String>{
+        let mut state = State::load_example("roa")?;
+        let obj = cms::signed_object(&state.tree)?;
+        let oid = encode_oid_from_string(content_type);
+        tree_util::set_content(&mut state.tree, obj.content_type, oid.clone());
+
+        let signer = cms::signer_info(&state.tree, obj.signer_info)?;
+        if let Some(ct) = signer.signed_attrs.and_then(|a| cms::signed_attribute(&state.tree, a, cms::OID_CONTENT_TYPE)){
+            tree_util::set_content(&mut state.tree, ct, oid);
+        }
+        Ok(state)
+    }
+
+    /// Encoding of an explicit `[0]` version at the start of a payload.
+    pub(crate) fn payload_version(&self, econtent: usize) -> Option<Vec<u8>>{
+        let p = tree_util::payload(&self.tree, econtent, &self.overrides)?;
+        let first = *children(&p.tree, p.root).first()?;
+        (tag(&p.tree, first) == 0xA0).then(|| p.encode(first, &self.overrides))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn content(state: &State) -> RoaContent{
+        serde_json::from_str(&state.roa_get().unwrap()).unwrap()
+    }
+
+    fn prefix(prefix: &str, max_length: Option<u32>) -> RoaPrefix{
+        RoaPrefix{ prefix: prefix.to_string(), max_length }
+    }
+
+    #[test]
+    fn set_get(){
+        let mut state = State::load_example("roa").unwrap();
+        let roa = r#"{"asn": 64496, "prefixes": [
+            {"prefix": "2001:db8::/32", "maxLength": 48},
+            {"prefix": "192.0.2.0/24"},
+            {"prefix": "10.0.0.0/8", "maxLength": 24},
+            {"prefix": "192.0.2.0/24"}
+        ]}"#;
+        state.roa_set(roa.to_string(), false).unwrap();
+
+        // Families and prefixes are ordered, duplicates are dropped
+        let roa = content(&state);
+        assert_eq!(roa.asn, 64496);
+        assert_eq!(roa.prefixes, [prefix("10.0.0.0/8", Some(24)), prefix("192.0.2.0/24", None), prefix("2001:db8::/32", Some(48))]);
+
+        // Setting what was read gives the same encoding
+        let encoded = state.encode();
+        state.roa_set(state.roa_get().unwrap(), false).unwrap();
+        assert_eq!(state.encode(), encoded);
+    }
+
+    #[test]
+    fn invalid_content(){
+        let mut state = State::load_example("roa").unwrap();
+        let encoded = state.encode();
+        assert!(state.roa_set(r#"{"asn": 64496, "prefixes": [{"prefix": "192.0.2.0/33"}]}"#.to_string(), false).is_err());
+        assert!(state.roa_set(r#"{"asn": -1, "prefixes": []}"#.to_string(), false).is_err());
+        assert_eq!(state.encode(), encoded);
+
+        assert!(State::load_example("mft").unwrap().roa_get().is_err());
+    }
+
+    #[test]
+    fn max_length_out_of_range(){
+        let mut state = State::load_example("roa").unwrap();
+        let econtent = state.signed_payload(cms::OID_CT_ROA, "ROA").unwrap();
+
+        // maxLength of 2^32 does not fit the u32 of the JSON
+        let mut address = der::tlv(0x03, &[0x00, 0x0A]);
+        address.extend(der::tlv(0x02, &der::encode_uint(1 << 32)));
+        let mut family = der::tlv(0x04, &[0x00, 0x01]);
+        family.extend(der::tlv(0x30, &der::tlv(0x30, &address)));
+        let mut roa = der::tlv(0x02, &[0x01]);
+        roa.extend(der::tlv(0x30, &der::tlv(0x30, &family)));
+        tree_util::set_string_payload(&mut state.tree, econtent, &der::tlv(0x30, &roa)).unwrap();
+
+        assert_eq!(state.roa_get().unwrap_err(), "Invalid maxLength of 10.0.0.0/8");
+    }
+}
@@ -0,0 +1,222 @@
+use cure_asn1::tree_parser::Tree;
+use cure_pp::{cure_repo::CureRepository, repository_util::ObjectKey};
+use wasm_bindgen::prelude::*;
+
+use crate::{cms, der, encode_oid_from_string, keys, repo, resources, tree_util::{self, children}, validate, State};
+
+// BGPsec router certificates (RFC 8209) are EE certificates with a P-256 key, the
+// bgpsec-router EKU and only AS resources. The subject names AS and router.
+
+pub const OID_BGPSEC_ROUTER: &str = "1.3.6.1.5.5.7.3.30";
+const OID_COMMON_NAME: &str = "2.5.4.3";
+const OID_SERIAL_NUMBER: &str = "2.5.4.5";
+
+// Documentation AS and address (RFC 5398, RFC 5737)
+const EXAMPLE_AS: u32 = 64496;
+const EXAMPLE_ROUTER_ID: &str = "192.0.2.1";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouterInfo{
+    pub asns: String, // In the format of `set_cert_resources`
+    pub common_name: Option<String>,
+    pub router_id: Option<String>, // serialNumber of the subject, hex
+    pub public_key: String, // Hex
+    pub curve: Option<String>, // OID of the named curve
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouterKey{
+    pub private_key: Option<String>, // Hex, only for generated keys
+    pub public_key: String,
+}
+
+/// Whether the tree is a certificate with the BGPsec router EKU.
+pub fn is_router_certificate(tree: &Tree) -> bool{
+    let cert = match cms::certificate(tree, tree.root_id){
+        Ok(c) => c,
+        Err(_) => return false,
+    };
+    let eku = cert.extensions.and_then(|e| cms::extension_value(tree, e, validate::OID_EXT_KEY_USAGE));
+    eku.and_then(|e| tree_util::payload(tree, e, &Default::default()))
+        .map(|p| children(&p.tree, p.root).iter().any(|o| tree_util::is_oid(&p.tree, *o, OID_BGPSEC_ROUTER)))
+        .unwrap_or(false)
+}
+
+/// BGP Identifier from dotted decimal or eight hex digits.
+fn parse_router_id(id: &str) -> Result<String, String>{
+    let id = id.trim();
+    if let Ok(addr) = id.parse::<std::net::Ipv4Addr>(){
+        return Ok(format!("{:08X}", u32::from(addr)));
+    }
+    match u32::from_str_radix(id.trim_start_matches("0x"), 16){
+        Ok(v) => Ok(format!("{:08X}", v)),
+        Err(_) => Err(format!("Invalid router ID {}, use an IPv4 address or eight hex digits", id)),
+    }
+}
+
+fn encode_name(attributes: &[(&str, &str)]) -> Vec<u8>{
+    let mut name = vec![];
+    for (oid, value) in attributes{
+        let mut attr = der::tlv(0x06, &encode_oid_from_string(oid));
+        attr.extend(der::tlv(0x13, value.as_bytes()));
+        name.extend(der::tlv(0x31, &der::tlv(0x30, &attr)));
+    }
+    der::tlv(0x30, &name)
+}
+
+#[wasm_bindgen]
+impl State{
+    /// Returns AS resources, subject and public key of a router certificate as JSON.
+    #[wasm_bindgen]
+    pub fn router_get(&self) -> Result<String, String>{
+        let cert = cms::certificate(&self.tree, cms::object_certificate(&self.tree)?)?;
+        let (common_name, router_id) = self.router_subject()?;
+
+        let spki = children(&self.tree, cert.spki);
+        let curve = spki.first().and_then(|a| children(&self.tree, *a).get(1)).filter(|c| tree_util::tag(&self.tree, **c) == 0x06);
+        let public_key = spki.get(1).map(|b| tree_util::string_payload(&self.tree, *b, &self.overrides)).unwrap_or_default();
+
+        let asns = match cert.extensions.and_then(|e| cms::extension_value(&self.tree, e, resources::OID_AS_RESOURCES)){
+            Some(value) => {
+                let p = tree_util::payload(&self.tree, value, &self.overrides).ok_or("AS resources are not valid ASN.1")?;
+                let asn = resources::as_resources(&p.tree, p.root)?;
+                match asn.inherit{
+                    true => "as inherit".to_string(),
+                    false => asn.ranges.iter().map(|(min, max)| resources::format_as_range(*min, *max)).collect::<Vec<String>>().join(", "),
+                }
+            }
+            None => String::new(),
+        };
+
+        let info = RouterInfo{
+            asns,
+            common_name,
+            router_id,
+            public_key: hex::encode(public_key),
+            curve: curve.map(|c| der::decode_oid(&self.tree.tokens[c].data)),
+        };
+        Ok(serde_json::to_string(&info).unwrap())
+    }
+
+    /// Sets the AS resources from a list like `AS64496, AS64500-AS64510`. With `update_subject`
+    /// the common name is set to ROUTER- and the first AS in hex.
+    #[wasm_bindgen]
+    pub fn router_set_asns(&mut self, asns: String, update_subject: bool) -> Result<(), String>{
+        let (families, asn) = resources::parse_resource_list(&asns)?;
+        if !families.is_empty(){
+            return Err("Router certificates must not contain IP resources".to_string());
+        }
+        let asn = asn.ok_or("No AS given")?;
+        self.set_cert_extension(resources::OID_AS_RESOURCES, true, Some(&resources::encode_as_resources(&asn)))?;
+
+        if update_subject{
+            let first = asn.ranges.iter().map(|(min, _)| *min).min().ok_or("No AS given")?;
+            let (_, router_id) = self.router_subject()?;
+            self.set_router_subject(&format!("ROUTER-{:08X}", first), router_id.as_deref())?;
+        }
+        self.overrides.retain_existing(&self.tree);
+        Ok(())
+    }
+
+    /// Sets the router ID (BGP Identifier) in the serialNumber of the subject, an empty value removes it.
+    #[wasm_bindgen]
+    pub fn router_set_id(&mut self, router_id: String) -> Result<(), String>{
+        let router_id = match router_id.trim().is_empty(){
+            true => None,
+            false => Some(parse_router_id(&router_id)?),
+        };
+        let (common_name, _) = self.router_subject()?;
+        self.set_router_subject(&common_name.unwrap_or_default(), router_id.as_deref())?;
+        self.overrides.retain_existing(&self.tree);
+        Ok(())
+    }
+
+    /// Sets the router key to an uncompressed P-256 point in hex, or to a new key pair if empty.
+    /// SKI and the subjectPublicKeyInfo are updated, the certificate is not signed again.
+    /// Returns the key as JSON, the private key is only known for generated keys.
+    #[wasm_bindgen]
+    pub fn router_set_key(&mut self, public_key: String) -> Result<String, String>{
+        let (private_key, point) = match public_key.trim().is_empty(){
+            true => {
+                let (private_key, point) = keys::generate_p256();
+                (Some(hex::encode(private_key)), point)
+            }
+            false => (None, hex::decode(public_key.trim()).map_err(|_| "Public key must be hex encoded".to_string())?),
+        };
+        keys::check_p256_point(&point)?;
+
+        let cert = cms::certificate(&self.tree, cms::object_certificate(&self.tree)?)?;
+        tree_util::replace_node(&mut self.tree, cert.spki, &keys::ec_subject_public_key_info(&point))?;
+        let ski = der::tlv(0x04, &keys::key_identifier(&point));
+        self.set_cert_extension(cms::OID_SKI, false, Some(&ski))?;
+        self.overrides.retain_existing(&self.tree);
+
+        let key = RouterKey{
+            private_key,
+            public_key: hex::encode(point),
+        };
+        Ok(serde_json::to_string(&key).unwrap())
+    }
+}
+
+impl State{
+    /// Common name and serialNumber of the subject.
+    fn router_subject(&self) -> Result<(Option<String>, Option<String>), String>{
+        let cert = cms::certificate(&self.tree, cms::object_certificate(&self.tree)?)?;
+        let mut ret = (None, None);
+        for rdn in children(&self.tree, cert.subject){
+            for attr in children(&self.tree, *rdn){
+                let c = children(&self.tree, *attr);
+                if c.len() != 2{
+                    continue;
+                }
+                let value = Some(String::from_utf8_lossy(&self.tree.tokens[&c[1]].data).to_string());
+                if tree_util::is_oid(&self.tree, c[0], OID_COMMON_NAME){
+                    ret.0 = value;
+                }
+                else if tree_util::is_oid(&self.tree, c[0], OID_SERIAL_NUMBER){
+                    ret.1 = value;
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Replaces the subject with the common name and an optional serialNumber (RFC 8209 3.1).
+    fn set_router_subject(&mut self, common_name: &str, router_id: Option<&str>) -> Result<(), String>{
+        let cert = cms::certificate(&self.tree, cms::object_certificate(&self.tree)?)?;
+        let mut attributes = vec![(OID_COMMON_NAME, common_name)];
+        attributes.extend(router_id.map(|r| (OID_SERIAL_NUMBER, r)));
+        tree_util::replace_node(&mut self.tree, cert.subject, &encode_name(&attributes))?;
+        Ok(())
+    }
+
+    /// Example router certificate, derived from the certificate example since the object generator has none.
+    pub(crate) fn example_router() -> Result<State, String>{
+        let mut state = State::load_example("cer")?;
+        state.router_set_key(String::new())?;
+
+        state.set_cert_extension(validate::OID_BASIC_CONSTRAINTS, true, None)?;
+        state.set_cert_extension(validate::OID_SIA, false, None)?;
+        state.set_cert_extension(resources::OID_IP_RESOURCES, true, None)?;
+        state.set_cert_extension(validate::OID_KEY_USAGE, true, Some(&der::tlv(0x03, &[0x07, 0x80])))?;
+        let eku = der::tlv(0x30, &der::tlv(0x06, &encode_oid_from_string(OID_BGPSEC_ROUTER)));
+        state.set_cert_extension(validate::OID_EXT_KEY_USAGE, false, Some(&eku))?;
+
+        state.router_set_asns(format!("AS{}", EXAMPLE_AS), true)?;
+        state.router_set_id(EXAMPLE_ROUTER_ID.to_string())?;
+        state.sign_certificate_with(&keys::load_key(None)?)?;
+        Ok(state)
+    }
+
+    /// Issues the router certificate from the CA of `repo` and publishes it there as `name`.
+    /// Call after the objects of the repository are fixed.
+    pub(crate) fn publish_router_certificate(&self, repo: &mut CureRepository, name: &str, key: ObjectKey) -> Result<(), String>{
+        let ca = State::from_tree(repo.certificate.tree.clone());
+        let mut router = self.clone();
+        router.issue_certificate_from(&ca, &repo.certificate.child_key)?;
+        repo::publish(repo, name, &router, key)
+    }
+}
@@ -0,0 +1,328 @@
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+use crate::{RepoFiles, RrdpEntry};
+
+// RRDP files (RFC 8182) as generated by cure are edited as text here, since cure
+// has no options for their session and serial and generates no deltas.
+
+const RRDP_NAMESPACE: &str = "http://www.ripe.net/rpki/rrdp";
+
+/// Attributes with their name and value.
+fn attribute_regex() -> &'static Regex{
+    static ATTRIBUTE: OnceLock<Regex> = OnceLock::new();
+    ATTRIBUTE.get_or_init(|| Regex::new(r#"\s([A-Za-z_][\w.-]*)="([^"]*)""#).unwrap())
+}
+
+/// Value of the first attribute `name` in `xml`.
+pub(crate) fn attribute(xml: &str, name: &str) -> Option<String>{
+    attribute_regex().captures_iter(xml).find(|c| &c[1] == name).map(|c| c[2].to_string())
+}
+
+/// Replaces the first attribute `name`, which is the one of the root element.
+fn set_root_attribute(xml: &str, name: &str, value: &str) -> String{
+    match attribute_regex().captures_iter(xml).find(|c| &c[1] == name).and_then(|c| c.get(2)){
+        Some(v) => format!("{}{}{}", &xml[..v.start()], value, &xml[v.end()..]),
+        None => xml.to_string(),
+    }
+}
+
+fn notification_index(files: &RepoFiles) -> Result<usize, String>{
+    files.iter().position(|(name, _)| name.ends_with("notification.xml")).ok_or("Repository has no notification.xml".to_string())
+}
+
+/// Whether the file is a notification, snapshot or delta.
+pub(crate) fn is_rrdp_file(name: &str) -> bool{
+    ["notification.xml", "snapshot.xml", "delta.xml"].iter().any(|f| name.ends_with(f))
+}
+
+fn text(data: &[u8]) -> String{
+    String::from_utf8_lossy(data).to_string()
+}
+
+/// The file that is published at `uri`.
+fn file_at<'a>(files: &'a RepoFiles, uri: &str) -> Option<&'a (String, Vec<u8>)>{
+    files.iter().find(|(name, _)| uri.ends_with(name.as_str()))
+}
+
+/// Elements `name` of the notification, e.g. snapshot or delta.
+fn elements(xml: &str, name: &str) -> Vec<String>{
+    static ELEMENT: OnceLock<Regex> = OnceLock::new();
+    let re = ELEMENT.get_or_init(|| Regex::new(r#"<([A-Za-z_][\w.-]*)\s[^>]*>"#).unwrap());
+    re.captures_iter(xml).filter(|c| &c[1] == name).map(|c| c[0].to_string()).collect()
+}
+
+/// Index and URI of the snapshot of the notification.
+fn snapshot(files: &RepoFiles) -> Result<(usize, String), String>{
+    let xml = text(&files[notification_index(files)?].1);
+    let uri = elements(&xml, "snapshot").first().and_then(|el| attribute(el, "uri")).ok_or("Notification has no snapshot")?;
+    let index = files.iter().position(|(name, _)| uri.ends_with(name.as_str())).ok_or("Snapshot of the notification is missing")?;
+    Ok((index, uri))
+}
+
+/// URI and base64 content of the objects published in a snapshot.
+fn published(xml: &str) -> Vec<(String, String)>{
+    static PUBLISH: OnceLock<Regex> = OnceLock::new();
+    let re = PUBLISH.get_or_init(|| Regex::new(r#"(?s)<publish\s[^>]*?uri="([^"]*)"[^>]*>(.*?)</publish>"#).unwrap());
+    re.captures_iter(xml).map(|c| (c[1].to_string(), c[2].split_whitespace().collect())).collect()
+}
+
+fn object_hash(content: &str) -> Result<String, String>{
+    let data = base64::decode(content).map_err(|_| "Snapshot contains an object that is not base64".to_string())?;
+    Ok(hex::encode(Sha256::digest(data)))
+}
+
+/// Delta from the objects of one snapshot to the next: new objects are published, changed ones
+/// replace the old object by its hash and removed ones are withdrawn.
+fn delta_xml(session_id: &str, serial: u64, old: &[(String, String)], new: &[(String, String)]) -> Result<String, String>{
+    let mut xml = format!("<delta xmlns=\"{}\" version=\"1\" session_id=\"{}\" serial=\"{}\">\n", RRDP_NAMESPACE, session_id, serial);
+    for (uri, content) in new{
+        match old.iter().find(|(u, _)| u == uri){
+            Some((_, c)) if c == content => (),
+            Some((_, c)) => xml.push_str(&format!("  <publish uri=\"{}\" hash=\"{}\">{}</publish>\n", uri, object_hash(c)?, content)),
+            None => xml.push_str(&format!("  <publish uri=\"{}\">{}</publish>\n", uri, content)),
+        }
+    }
+    for (uri, content) in old.iter().filter(|(u, _)| !new.iter().any(|(n, _)| n == u)){
+        xml.push_str(&format!("  <withdraw uri=\"{}\" hash=\"{}\"/>\n", uri, object_hash(content)?));
+    }
+    xml.push_str("</delta>\n");
+    Ok(xml)
+}
+
+/// Sets session_id and serial of the snapshot and the notification. Paths and URIs that contain
+/// the session and serial are renamed, the hashes in the notification are updated.
+pub(crate) fn set_session(files: &mut RepoFiles, session_id: Option<&str>, serial: Option<u64>) -> Result<(), String>{
+    let xml = text(&files[notification_index(files)?].1);
+    let old_session = attribute(&xml, "session_id").ok_or("Notification has no session_id")?;
+    let old_serial = attribute(&xml, "serial").ok_or("Notification has no serial")?;
+    let session = session_id.map(|s| s.to_string()).unwrap_or(old_session.clone());
+    let serial = serial.map(|s| s.to_string()).unwrap_or(old_serial.clone());
+
+    let old_dir = format!("/{}/{}/", old_session, old_serial);
+    let new_dir = format!("/{}/{}/", session, serial);
+    for (name, data) in files.iter_mut().filter(|(name, _)| name.ends_with(".xml")){
+        *name = name.replace(&old_dir, &new_dir);
+        if name.ends_with("snapshot.xml") || name.ends_with("notification.xml"){
+            let xml = String::from_utf8_lossy(data).replace(&old_dir, &new_dir);
+            let xml = set_root_attribute(&xml, "session_id", &session);
+            *data = set_root_attribute(&xml, "serial", &serial).into_bytes();
+        }
+    }
+    fix_notification_hashes(files)
+}
+
+/// Recomputes the hashes of the snapshot and delta references in the notification. Empty
+/// hashes are written in the case of the snapshot hash.
+pub(crate) fn fix_notification_hashes(files: &mut RepoFiles) -> Result<(), String>{
+    let index = notification_index(files)?;
+    let xml = text(&files[index].1);
+    let uppercase = |hash: &str| hash.chars().any(|c| c.is_ascii_uppercase());
+    let snapshot_upper = elements(&xml, "snapshot").first().and_then(|el| attribute(el, "hash")).map(|h| uppercase(&h)).unwrap_or(false);
+    static REFERENCE: OnceLock<Regex> = OnceLock::new();
+    let element = REFERENCE.get_or_init(|| Regex::new(r#"<(snapshot|delta)\s[^>]*>"#).unwrap());
+
+    let fixed = element.replace_all(&xml, |c: &regex::Captures| {
+        let el = &c[0];
+        let data = attribute(el, "uri").and_then(|uri| file_at(files, &uri));
+        match (data, attribute(el, "hash")){
+            (Some((_, data)), Some(old)) => {
+                let hash = hex::encode(Sha256::digest(data));
+                let upper = if old.is_empty() { snapshot_upper } else { uppercase(&old) };
+                let hash = if upper { hash.to_uppercase() } else { hash };
+                set_root_attribute(el, "hash", &hash)
+            }
+            _ => el.to_string(),
+        }
+    }).to_string();
+
+    files[index].1 = fixed.into_bytes();
+    Ok(())
+}
+
+/// Makes `current` the next state of `previous`: it continues the session of `previous` with the
+/// next serial, and its notification lists a delta from the previous snapshot and the earlier deltas.
+pub(crate) fn next_state(previous: &RepoFiles, current: &mut RepoFiles) -> Result<(), String>{
+    let xml = text(&previous[notification_index(previous)?].1);
+    let session = attribute(&xml, "session_id").ok_or("Previous notification has no session_id")?;
+    let serial = attribute(&xml, "serial").and_then(|s| s.parse::<u64>().ok()).ok_or("Previous notification has no valid serial")? + 1;
+    let old = published(&text(&previous[snapshot(previous)?.0].1));
+
+    set_session(current, Some(&session), Some(serial))?;
+    let (index, uri) = snapshot(current)?;
+    let path = current[index].0.clone();
+    let new = published(&text(&current[index].1));
+
+    let delta_path = format!("{}delta.xml", path.trim_end_matches("snapshot.xml"));
+    let delta_uri = format!("{}delta.xml", uri.trim_end_matches("snapshot.xml"));
+    current.push((delta_path, delta_xml(&session, serial, &old, &new)?.into_bytes()));
+
+    // The hashes are filled in by fix_notification_hashes
+    let mut deltas = format!("  <delta serial=\"{}\" uri=\"{}\" hash=\"\"/>\n", serial, delta_uri);
+    for el in elements(&xml, "delta"){
+        let (serial, uri) = match (attribute(&el, "serial"), attribute(&el, "uri")){
+            (Some(s), Some(u)) => (s, u),
+            _ => continue,
+        };
+        if let Some(file) = file_at(previous, &uri){
+            deltas.push_str(&format!("  <delta serial=\"{}\" uri=\"{}\" hash=\"\"/>\n", serial, uri));
+            current.push(file.clone());
+        }
+    }
+
+    let index = notification_index(current)?;
+    let notification = text(&current[index].1);
+    let end = notification.rfind("</notification>").ok_or("Notification has no end tag")?;
+    current[index].1 = format!("{}{}{}", &notification[..end], deltas, &notification[end..]).into_bytes();
+    fix_notification_hashes(current)
+}
+
+/// The RRDP files of a repository with their URIs, which are based on the URI of the snapshot.
+pub(crate) fn entries(files: &RepoFiles) -> Result<Vec<RrdpEntry>, String>{
+    let (index, uri) = snapshot(files)?;
+    let base = uri.strip_suffix(files[index].0.as_str()).unwrap_or_default();
+    Ok(files.iter()
+        .filter(|(name, _)| is_rrdp_file(name))
+        .map(|(name, data)| RrdpEntry::new(format!("{}{}", base, name), text(data)))
+        .collect())
+}
+
+/// The objects of the snapshot as files, with the path of their rsync URI below the host.
+pub(crate) fn rsync_tree(files: &RepoFiles) -> Result<RepoFiles, String>{
+    let xml = text(&files[snapshot(files)?.0].1);
+    let mut ret = vec![];
+    for (uri, data) in parse_snapshot(&xml)?.objects{
+        let path = match uri.strip_prefix("rsync://").and_then(|r| r.split_once('/')){
+            Some((_, path)) if !path.is_empty() => path.to_string(),
+            _ => return Err(format!("{} is not an rsync URI", uri)),
+        };
+        ret.push((path, data));
+    }
+    Ok(ret)
+}
+
+/// Content of a snapshot.
+pub(crate) struct Snapshot{
+    pub session_id: String,
+    pub serial: u64,
+    pub objects: RepoFiles, // URI and content
+}
+
+pub(crate) fn parse_snapshot(xml: &str) -> Result<Snapshot, String>{
+    let mut objects = vec![];
+    for (uri, content) in published(xml){
+        let data = base64::decode(&content).map_err(|_| format!("Content of {} is not base64", uri))?;
+        objects.push((uri, data));
+    }
+    Ok(Snapshot{
+        session_id: attribute(xml, "session_id").ok_or("Snapshot has no session_id")?,
+        serial: attribute(xml, "serial").and_then(|s| s.parse().ok()).ok_or("Snapshot has no valid serial")?,
+        objects,
+    })
+}
+
+/// The snapshot of the notification in `files`, with the URI of the notification.
+pub(crate) fn read(files: &RepoFiles) -> Result<(Snapshot, String), String>{
+    let (index, uri) = snapshot(files)?;
+    let base = uri.strip_suffix(files[index].0.as_str()).unwrap_or_default();
+    let notification = format!("{}{}", base, files[notification_index(files)?].0);
+    Ok((parse_snapshot(&text(&files[index].1))?, notification))
+}
+
+/// Snapshot and notification with `objects`, the RRDP files are below `rrdp_uri`.
+pub(crate) fn write(rrdp_uri: &str, session_id: &str, serial: u64, objects: &RepoFiles) -> Result<RepoFiles, String>{
+    let path = |uri: &str| match uri.strip_prefix("https://").and_then(|r| r.split_once('/')){
+        Some((_, path)) => Ok(path.to_string()),
+        None => Err(format!("{} is not an https URI", uri)),
+    };
+    let rrdp_uri = format!("{}/", rrdp_uri.trim_end_matches('/'));
+    let snapshot_uri = format!("{}{}/{}/snapshot.xml", rrdp_uri, session_id, serial);
+
+    let mut snapshot = format!("<snapshot xmlns=\"{}\" version=\"1\" session_id=\"{}\" serial=\"{}\">\n", RRDP_NAMESPACE, session_id, serial);
+    for (uri, data) in objects{
+        snapshot.push_str(&format!("  <publish uri=\"{}\">{}</publish>\n", uri, base64::encode(data)));
+    }
+    snapshot.push_str("</snapshot>\n");
+
+    let notification = format!(
+        "<notification xmlns=\"{}\" version=\"1\" session_id=\"{}\" serial=\"{}\">\n  <snapshot uri=\"{}\" hash=\"{}\"/>\n</notification>\n",
+        RRDP_NAMESPACE, session_id, serial, snapshot_uri, hex::encode(Sha256::digest(snapshot.as_bytes())),
+    );
+    Ok(vec![
+        (path(&snapshot_uri)?, snapshot.into_bytes()),
+        (path(&format!("{}notification.xml", rrdp_uri))?, notification.into_bytes()),
+    ])
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const RRDP_URI: &str = "https://example.net/rrdp/";
+
+    fn uri(name: &str) -> String{
+        format!("rsync://example.net/repo/{}", name)
+    }
+
+    fn objects(list: &[(&str, u8)]) -> RepoFiles{
+        list.iter().map(|(name, b)| (uri(name), vec![*b])).collect()
+    }
+
+    fn file<'a>(files: &'a RepoFiles, path: &str) -> &'a [u8]{
+        &files.iter().find(|(name, _)| name == path).unwrap_or_else(|| panic!("{} is missing", path)).1
+    }
+
+    fn hash(data: &[u8]) -> String{
+        hex::encode(Sha256::digest(data))
+    }
+
+    #[test]
+    fn write_read(){
+        let written = objects(&[("a.roa", 1), ("b.mft", 2)]);
+        let files = write(RRDP_URI, "S", 7, &written).unwrap();
+        let (snapshot, notification) = read(&files).unwrap();
+        assert_eq!((snapshot.session_id.as_str(), snapshot.serial), ("S", 7));
+        assert_eq!(snapshot.objects, written);
+        assert_eq!(notification, "https://example.net/rrdp/notification.xml");
+    }
+
+    #[test]
+    fn delta_of_next_state(){
+        let previous = write(RRDP_URI, "S", 3, &objects(&[("a.roa", 1), ("b.roa", 2), ("c.roa", 3)])).unwrap();
+        let mut current = write(RRDP_URI, "T", 1, &objects(&[("a.roa", 1), ("b.roa", 4), ("d.roa", 5)])).unwrap();
+        next_state(&previous, &mut current).unwrap();
+
+        let notification = text(file(&current, "rrdp/notification.xml"));
+        assert_eq!(attribute(&notification, "session_id").as_deref(), Some("S"));
+        assert_eq!(attribute(&notification, "serial").as_deref(), Some("4"));
+
+        // Unchanged objects are left out, replaced and withdrawn ones carry the hash of the old object
+        let delta = text(file(&current, "rrdp/S/4/delta.xml"));
+        assert!(!delta.contains(&uri("a.roa")));
+        assert!(delta.contains(&format!("<publish uri=\"{}\" hash=\"{}\">{}</publish>", uri("b.roa"), hash(&[2]), base64::encode([4]))));
+        assert!(delta.contains(&format!("<publish uri=\"{}\">{}</publish>", uri("d.roa"), base64::encode([5]))));
+        assert!(delta.contains(&format!("<withdraw uri=\"{}\" hash=\"{}\"/>", uri("c.roa"), hash(&[3]))));
+
+        let references = elements(&notification, "snapshot").into_iter().chain(elements(&notification, "delta")).collect::<Vec<String>>();
+        assert_eq!(references.len(), 2);
+        for el in references{
+            let data = file_at(&current, &attribute(&el, "uri").unwrap()).unwrap();
+            assert_eq!(attribute(&el, "hash").unwrap().to_lowercase(), hash(&data.1));
+        }
+    }
+
+    #[test]
+    fn earlier_deltas_are_kept(){
+        let first = write(RRDP_URI, "S", 1, &objects(&[("a.roa", 1)])).unwrap();
+        let mut second = write(RRDP_URI, "T", 1, &objects(&[("a.roa", 2)])).unwrap();
+        next_state(&first, &mut second).unwrap();
+        let mut third = write(RRDP_URI, "U", 1, &objects(&[("a.roa", 3)])).unwrap();
+        next_state(&second, &mut third).unwrap();
+
+        let notification = text(file(&third, "rrdp/notification.xml"));
+        let serials = elements(&notification, "delta").iter().filter_map(|el| attribute(el, "serial")).collect::<Vec<String>>();
+        assert_eq!(serials, ["3", "2"]);
+        assert_eq!(file(&third, "rrdp/S/2/delta.xml"), file(&second, "rrdp/S/2/delta.xml"));
+    }
+}
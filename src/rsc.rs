@@ -0,0 +1,305 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{cert_resources::CertResources, cms, der, digest::DigestAlgorithm, encode_oid_from_string, keys, resign::ResignOptions, resources::{self, AsResources, IpFamily}, tree_util::{self, child_by_tag, children, tag}, State};
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ChecklistEntry{
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub hash: String, // Hex
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RscContent{
+    pub resources: String, // In the format of `set_cert_resources`, without inherit
+    pub digest_algorithm: String, // Name like SHA-256 or OID
+    pub checklist: Vec<ChecklistEntry>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ChecklistFile{
+    pub name: String,
+    pub content: String, // Base64
+}
+
+fn resources_text(t: &cure_asn1::tree_parser::Tree, block: usize) -> Result<String, String>{
+    let mut text = vec![];
+    if let Some(ip) = child_by_tag(t, block, 0xA1).and_then(|a| children(t, a).first().cloned()){
+        for family in resources::ip_resources(t, ip)?{
+            text.extend(family.ranges.iter().map(|(min, max)| resources::format_ip_range(family.afi, *min, *max)));
+        }
+    }
+    if let Some(asn) = child_by_tag(t, block, 0xA0).and_then(|a| children(t, a).first().cloned()){
+        text.extend(resources::as_resources(t, asn)?.ranges.iter().map(|(min, max)| resources::format_as_range(*min, *max)));
+    }
+    Ok(text.join(", "))
+}
+
+/// ResourceBlock of an RSC, the same structures as in certificates but without inherit (RFC 9323 4.2).
+fn encode_resource_block(families: &[IpFamily], asn: Option<&AsResources>) -> Result<Vec<u8>, String>{
+    if families.iter().any(|f| f.inherit) || asn.map(|a| a.inherit).unwrap_or(false){
+        return Err("Resources of a signed checklist must not be inherited".to_string());
+    }
+    if families.is_empty() && asn.is_none(){
+        return Err("No resources given".to_string());
+    }
+
+    let mut block = vec![];
+    if let Some(asn) = asn{
+        block.extend(der::tlv(0xA0, &resources::encode_as_resources(asn)));
+    }
+    if !families.is_empty(){
+        block.extend(der::tlv(0xA1, &resources::encode_ip_resources(families)));
+    }
+    Ok(der::tlv(0x30, &block))
+}
+
+fn parse_algorithm(name: &str) -> Result<String, String>{
+    if name.trim().chars().all(|c| c.is_ascii_digit() || c == '.'){
+        return Ok(name.trim().to_string());
+    }
+    DigestAlgorithm::from_name(name).map(|a| a.oid().to_string())
+}
+
+#[wasm_bindgen]
+impl State{
+    /// Returns resources, digest algorithm and checklist of an RPKI Signed Checklist as JSON.
+    #[wasm_bindgen]
+    pub fn rsc_get(&self) -> Result<String, String>{
+        let econtent = self.signed_payload(cms::OID_CT_RSC, "signed checklist")?;
+        let p = tree_util::payload(&self.tree, econtent, &self.overrides).ok_or("eContent is not valid ASN.1")?;
+        let t = &p.tree;
+
+        let c = children(t, p.root);
+        let c = if c.first().map(|v| tag(t, *v) == 0xA0).unwrap_or(false) { &c[1..] } else { c };
+        if c.len() != 3{
+            return Err("RpkiSignedChecklist must contain resources, digestAlgorithm and checkList".to_string());
+        }
+
+        let oid = children(t, c[1]).first().map(|o| der::decode_oid(&t.tokens[o].data)).ok_or("Invalid digestAlgorithm")?;
+        let mut checklist = vec![];
+        for entry in children(t, c[2]){
+            let e = children(t, *entry);
+            checklist.push(ChecklistEntry{
+                file: e.iter().find(|n| tag(t, **n) == 0x16).map(|n| String::from_utf8_lossy(&t.tokens[n].data).to_string()),
+                hash: e.iter().find(|n| tag(t, **n) == 0x04).map(|n| hex::encode(&t.tokens[n].data)).unwrap_or_default(),
+            });
+        }
+
+        let content = RscContent{
+            resources: resources_text(t, c[0])?,
+            digest_algorithm: DigestAlgorithm::from_oid(&oid).map(|a| a.name().to_string()).unwrap_or(oid),
+            checklist,
+        };
+        Ok(serde_json::to_string(&content).unwrap())
+    }
+
+    /// Rebuilds the RSC payload from JSON in the format of `rsc_get`. Entries are written in the given
+    /// order with the given hashes. With `update_ee` the EE certificate gets the same resources.
+    /// Digest and signatures are not updated, see `resign`.
+    #[wasm_bindgen]
+    pub fn rsc_set(&mut self, content: String, update_ee: bool) -> Result<(), String>{
+        let content: RscContent = serde_json::from_str(&content).map_err(|e| format!("Invalid signed checklist: {}", e))?;
+        let econtent = self.signed_payload(cms::OID_CT_RSC, "signed checklist")?;
+
+        let (families, asn) = resources::parse_resource_list(&content.resources)?;
+        let block = encode_resource_block(&families, asn.as_ref())?;
+
+        let mut list = vec![];
+        for entry in content.checklist.iter(){
+            let hash = hex::decode(entry.hash.trim().trim_start_matches("0x")).map_err(|_| "Hash must be hex encoded".to_string())?;
+            let mut e = entry.file.as_ref().map(|f| der::tlv(0x16, f.as_bytes())).unwrap_or_default();
+            e.extend(der::tlv(0x04, &hash));
+            list.extend(der::tlv(0x30, &e));
+        }
+
+        // An explicit version is kept as it is
+        let mut rsc = vec![];
+        if let Some(version) = self.payload_version(econtent){
+            rsc.extend(version);
+        }
+        rsc.extend(block);
+        rsc.extend(der::tlv(0x30, &der::tlv(0x06, &encode_oid_from_string(&parse_algorithm(&content.digest_algorithm)?))));
+        rsc.extend(der::tlv(0x30, &list));
+        tree_util::set_string_payload(&mut self.tree, econtent, &der::tlv(0x30, &rsc))?;
+
+        if update_ee{
+            self.set_cert_resources(content.resources)?;
+        }
+
+        self.overrides.retain_existing(&self.tree);
+        self.label_rsc();
+        Ok(())
+    }
+
+    /// Replaces the checklist with the hashes of `files`, a JSON list of `{name, content}` with base64
+    /// content, using the digest algorithm of the RSC. File names are only included with `with_names`.
+    #[wasm_bindgen]
+    pub fn rsc_checklist_from_files(&mut self, files: String, with_names: bool) -> Result<(), String>{
+        let files: Vec<ChecklistFile> = serde_json::from_str(&files).map_err(|e| format!("Invalid file list: {}", e))?;
+        let mut content: RscContent = serde_json::from_str(&self.rsc_get()?).unwrap();
+        let algorithm = DigestAlgorithm::from_name(&content.digest_algorithm)?;
+
+        content.checklist = vec![];
+        for file in files{
+            let data = base64::decode(file.content.trim()).map_err(|_| format!("Content of {} must be base64 encoded", file.name))?;
+            content.checklist.push(ChecklistEntry{
+                file: with_names.then_some(file.name),
+                hash: hex::encode(algorithm.hash(&data)),
+            });
+        }
+        self.rsc_set(serde_json::to_string(&content).unwrap(), false)
+    }
+}
+
+impl State{
+    /// Example RSC over the resources of the ROA example, the object generator has no signed checklists.
+    pub(crate) fn example_rsc() -> Result<State, String>{
+        let mut state = State::example_signed_object(cms::OID_CT_RSC)?;
+
+        let resources: CertResources = serde_json::from_str(&state.cert_resources()?).unwrap();
+        let content = RscContent{
+            resources: resources.text,
+            digest_algorithm: DigestAlgorithm::Sha256.name().to_string(),
+            checklist: vec![ChecklistEntry{
+                file: Some("example.txt".to_string()),
+                hash: hex::encode(DigestAlgorithm::Sha256.hash(b"example")),
+            }],
+        };
+        state.rsc_set(serde_json::to_string(&content).unwrap(), false)?;
+
+        let ee_key = keys::load_key(None)?;
+        state.resign_with(&ResignOptions::default(), &ee_key, Some(&keys::load_key(None)?))?;
+        Ok(state)
+    }
+
+    /// Root of the payload of a signed object of the given type in the tree. The eContent is
+    /// expanded first if needed, its encoding stays the same.
+    pub(crate) fn expanded_payload(&mut self, content_type: &str, name: &str) -> Option<usize>{
+        let econtent = self.signed_payload(content_type, name).ok()?;
+        if children(&self.tree, econtent).is_empty(){
+            let payload = tree_util::string_payload(&self.tree, econtent, &self.overrides);
+            tree_util::set_encapsulated(&mut self.tree, econtent, &payload).ok()?;
+            self.tree.tokens.get_mut(&econtent).unwrap().manipulated = false;
+        }
+        children(&self.tree, econtent).first().cloned()
+    }
+
+    /// Sets the labels of the nodes, the parser does not know these fields. Labels of repeated
+    /// fields (e.g. each checklist entry) name no single node, they are only set on the nodes and
+    /// dropped from the label map.
+    pub(crate) fn set_labels(&mut self, labels: Vec<(usize, &str)>){
+        for (id, label) in &labels{
+            self.tree.tokens.get_mut(id).unwrap().info = label.to_string();
+            if labels.iter().filter(|(_, l)| l == label).count() == 1{
+                self.tree.labels.insert(label.to_string(), *id);
+            }
+            else{
+                self.tree.labels.remove(*label);
+            }
+        }
+    }
+
+    /// Labels the fields of an RSC payload, the parser does not know this type. The eContent is
+    /// expanded first if needed, its encoding stays the same.
+    pub(crate) fn label_rsc(&mut self){
+        let root = match self.expanded_payload(cms::OID_CT_RSC, "signed checklist"){
+            Some(r) => r,
+            None => return,
+        };
+        let mut labels = vec![(root, "rpkiSignedChecklist")];
+        let c = children(&self.tree, root).to_vec();
+        let fields = if c.first().map(|v| tag(&self.tree, *v) == 0xA0).unwrap_or(false){
+            labels.push((c[0], "version"));
+            &c[1..]
+        }
+        else{
+            &c[..]
+        };
+
+        for (id, name) in fields.iter().zip(["resources", "digestAlgorithm", "checkList"]){
+            labels.push((*id, name));
+        }
+        if let Some(block) = fields.first(){
+            labels.extend(child_by_tag(&self.tree, *block, 0xA0).map(|a| (a, "asID")));
+            labels.extend(child_by_tag(&self.tree, *block, 0xA1).map(|a| (a, "ipAddrBlocks")));
+        }
+        for entry in fields.get(2).map(|l| children(&self.tree, *l).to_vec()).unwrap_or_default(){
+            labels.push((entry, "fileNameAndHash"));
+            for n in children(&self.tree, entry).to_vec(){
+                labels.push((n, if tag(&self.tree, n) == 0x16 { "fileName" } else { "hash" }));
+            }
+        }
+        self.set_labels(labels);
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn content(state: &State) -> RscContent{
+        serde_json::from_str(&state.rsc_get().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn set_get(){
+        let mut state = State::load_example("rsc").unwrap();
+        let mut rsc = content(&state);
+        rsc.checklist.push(ChecklistEntry{ file: None, hash: "00ff".to_string() });
+        state.rsc_set(serde_json::to_string(&rsc).unwrap(), false).unwrap();
+
+        let read = content(&state);
+        assert_eq!(read.digest_algorithm, "SHA-256");
+        assert_eq!(read.checklist.len(), 2);
+        assert_eq!((read.checklist[1].file.as_deref(), read.checklist[1].hash.as_str()), (None, "00ff"));
+
+        let encoded = state.encode();
+        state.rsc_set(state.rsc_get().unwrap(), false).unwrap();
+        assert_eq!(state.encode(), encoded);
+    }
+
+    #[test]
+    fn checklist_from_files(){
+        let mut state = State::load_example("rsc").unwrap();
+        let files = serde_json::to_string(&[
+            ChecklistFile{ name: "a.txt".to_string(), content: base64::encode(b"a") },
+            ChecklistFile{ name: "b.txt".to_string(), content: base64::encode(b"b") },
+        ]).unwrap();
+
+        state.rsc_checklist_from_files(files.clone(), true).unwrap();
+        let checklist = content(&state).checklist;
+        assert_eq!(checklist[0].file.as_deref(), Some("a.txt"));
+        assert_eq!(checklist[1].hash, hex::encode(DigestAlgorithm::Sha256.hash(b"b")));
+
+        state.rsc_checklist_from_files(files, false).unwrap();
+        assert!(content(&state).checklist.iter().all(|e| e.file.is_none()));
+
+        // Each entry is labelled, only the single fields are in the label map
+        let labelled = state.tree.tokens.values().filter(|t| t.info == "hash").count();
+        assert_eq!(labelled, 2);
+        assert!(state.tree.labels.contains_key("digestAlgorithm"));
+        assert!(!state.tree.labels.contains_key("hash"));
+    }
+
+    #[test]
+    fn invalid_content(){
+        let mut state = State::load_example("rsc").unwrap();
+        let mut rsc = content(&state);
+
+        rsc.resources = "inherit".to_string();
+        assert!(state.rsc_set(serde_json::to_string(&rsc).unwrap(), false).is_err());
+
+        rsc = content(&state);
+        rsc.digest_algorithm = "MD5".to_string();
+        assert!(state.rsc_set(serde_json::to_string(&rsc).unwrap(), false).is_err());
+
+        rsc = content(&state);
+        rsc.checklist[0].hash = "xyz".to_string();
+        assert!(state.rsc_set(serde_json::to_string(&rsc).unwrap(), false).is_err());
+
+        assert!(state.rsc_checklist_from_files(r#"[{"name": "a", "content": "!"}]"#.to_string(), true).is_err());
+        assert!(State::load_example("roa").unwrap().rsc_get().is_err());
+    }
+}
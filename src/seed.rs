@@ -0,0 +1,251 @@
+use chrono::{DateTime, Duration, Utc};
+use cure_pp::{cure_repo::CureRepository, repository_util::ObjectKey};
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use rsa::{pkcs1::EncodeRsaPrivateKey, RsaPrivateKey};
+use sha2::{Digest, Sha256};
+use std::{cell::RefCell, collections::HashMap};
+
+use crate::{cms, der, keys, repo::{CaPath, RepoOptions}, tree_util::{self, children, tag}, State};
+
+// Seeded repositories: keys, names, serials and times are derived from the seed instead
+// of cure's random choices, so the same input gives a byte-identical archive. cure fills
+// in times and serials on its own, they are overwritten and everything is signed again.
+
+// Time of seeded repositories without an explicit time
+const DEFAULT_TIME: &str = "2025-01-01T00:00:00Z";
+
+thread_local!{
+    // Generated keys by their seed material, generating RSA keys is slow
+    static KEYS: RefCell<HashMap<[u8; 32], String>> = RefCell::new(HashMap::new());
+}
+
+pub(crate) struct Seed{
+    seed: u64,
+    time: DateTime<Utc>,
+    counter: u64,
+}
+
+/// Object of the user in the repository, its own serial and times are kept.
+pub(crate) enum Placed{
+    Payload(usize),
+    Manifest,
+    Crl,
+    Certificate, // Certificate of the CA at the given path
+}
+
+/// Objects of the user with the path of their CA, and which generated objects are kept.
+struct Placement<'a>{
+    objects: &'a [(CaPath, Placed, State)],
+    options: &'a RepoOptions,
+}
+
+impl Placement<'_>{
+    fn find(&self, path: &[usize], f: impl Fn(&Placed) -> bool) -> Option<&State>{
+        self.objects.iter().find(|(p, placed, _)| p == path && f(placed)).map(|(_, _, s)| s)
+    }
+}
+
+/// Serial, validity and signing time of an object.
+struct Fields{
+    serial: Vec<u8>, // INTEGER content
+    validity: (DateTime<Utc>, DateTime<Utc>),
+    signing_time: DateTime<Utc>,
+}
+
+fn material(seed: u64, label: &str, index: u64) -> [u8; 32]{
+    let mut hasher = Sha256::new();
+    hasher.update(seed.to_be_bytes());
+    hasher.update(label.as_bytes());
+    hasher.update(index.to_be_bytes());
+    hasher.finalize().into()
+}
+
+/// RRDP session id of a seed, in the form of a version 4 UUID.
+pub(crate) fn session_id(seed: u64) -> String{
+    let mut m = material(seed, "session", 0);
+    m[6] = (m[6] & 0x0F) | 0x40;
+    m[8] = (m[8] & 0x3F) | 0x80;
+    let h = hex::encode(&m[..16]);
+    format!("{}-{}-{}-{}-{}", &h[..8], &h[8..12], &h[12..16], &h[16..20], &h[20..])
+}
+
+fn signing_time_node(state: &State) -> Option<usize>{
+    let obj = cms::signed_object(&state.tree).ok()?;
+    let signer = cms::signer_info(&state.tree, obj.signer_info).ok()?;
+    cms::signed_attribute(&state.tree, signer.signed_attrs?, cms::OID_SIGNING_TIME)
+}
+
+pub(crate) fn hash(state: &State) -> String{
+    hex::encode(Sha256::digest(state.encode()))
+}
+
+/// Fields of the object as they are, for the object of the user.
+fn fields_of(state: &State) -> Option<Fields>{
+    let t = &state.tree;
+    let cert = cms::certificate(t, cms::object_certificate(t).ok()?).ok()?;
+    let time = |id: &usize| der::parse_time(tag(t, *id), &t.tokens[id].data);
+    let times = children(t, cert.validity);
+    let validity = (time(times.first()?)?, time(times.get(1)?)?);
+
+    Some(Fields{
+        serial: t.tokens[&cert.serial].data.clone(),
+        validity,
+        signing_time: signing_time_node(state).and_then(|n| time(&n)).unwrap_or(validity.0),
+    })
+}
+
+impl Fields{
+    fn apply(&self, state: &mut State) -> Result<(), String>{
+        let cert = cms::certificate(&state.tree, cms::object_certificate(&state.tree)?)?;
+        tree_util::set_content(&mut state.tree, cert.serial, self.serial.clone());
+        state.set_cert_window(Some(self.validity.0), Some(self.validity.1))?;
+        if let Some(node) = signing_time_node(state){
+            let data = der::encode_time(tag(&state.tree, node), &self.signing_time);
+            tree_util::set_content(&mut state.tree, node, data);
+        }
+        Ok(())
+    }
+}
+
+impl Seed{
+    pub(crate) fn new(seed: u64, time: Option<&str>) -> Result<Seed, String>{
+        let time = DateTime::parse_from_rfc3339(time.unwrap_or(DEFAULT_TIME).trim())
+            .map(|t| t.with_timezone(&Utc))
+            .map_err(|_| "Invalid ISO 8601 time. Example: 2025-02-12T14:30:00Z".to_string())?;
+        Ok(Seed{ seed, time, counter: 0 })
+    }
+
+    fn next(&mut self, label: &str) -> [u8; 32]{
+        self.counter += 1;
+        material(self.seed, label, self.counter)
+    }
+
+    /// Next RSA key, the same seed gives the same keys in the same order.
+    pub(crate) fn key(&mut self) -> Result<ObjectKey, String>{
+        let m = self.next("key");
+        let cached = KEYS.with(|k| k.borrow().get(&m).cloned());
+        let encoded = match cached{
+            Some(k) => k,
+            None => {
+                let key = RsaPrivateKey::new(&mut ChaCha20Rng::from_seed(m), 2048).map_err(|e| format!("Key generation failed: {}", e))?;
+                let der = key.to_pkcs1_der().map_err(|e| format!("Key encoding failed: {}", e))?;
+                let encoded = base64::encode(der.as_bytes());
+                KEYS.with(|k| k.borrow_mut().insert(m, encoded.clone()));
+                encoded
+            }
+        };
+        keys::load_key(Some(&encoded))
+    }
+
+    /// Next file name without extension.
+    pub(crate) fn name(&mut self) -> String{
+        hex::encode(&self.next("name")[..16])
+    }
+
+    fn fields(&mut self, days: i64) -> Fields{
+        let serial = u64::from_be_bytes(self.next("serial")[..8].try_into().unwrap());
+        Fields{
+            serial: der::encode_uint(serial as u128),
+            validity: (self.time, self.time + Duration::days(days)),
+            signing_time: self.time,
+        }
+    }
+
+    /// Seeded keys for all CAs of the repository and their manifests, before cure fixes the objects.
+    pub(crate) fn set_keys(&mut self, repo: &mut CureRepository) -> Result<(), String>{
+        crate::repo::set_ca_key(repo, &self.key()?);
+        repo.manifest.child_key = self.key()?;
+        for p in repo.payloads.iter_mut(){
+            p.child_key = self.key()?;
+        }
+        for child in repo.child_repos.iter_mut(){
+            self.set_keys(child)?;
+        }
+        Ok(())
+    }
+
+    /// Seeded serials and times for all objects of the repository, which are then signed again
+    /// bottom-up. The objects of the user keep their own fields.
+    pub(crate) fn normalize(&mut self, repo: &mut CureRepository, objects: &[(CaPath, Placed, State)], options: &RepoOptions) -> Result<(), String>{
+        let placement = Placement{ objects, options };
+        self.normalize_ca(repo, None, &[], &placement).map(|_| ())
+    }
+
+    /// Returns the old and new hash of the CA certificate for the manifest of the issuer.
+    fn normalize_ca(&mut self, repo: &mut CureRepository, issuer: Option<(&State, &ObjectKey)>, path: &[usize], placement: &Placement) -> Result<(String, String), String>{
+        let options = placement.options;
+        let key = repo.certificate.child_key.clone();
+        let mut hashes = vec![];
+
+        let mut cert = State::from_tree(repo.certificate.tree.clone());
+        let old_cert = hash(&cert);
+        let fields = placement.find(path, |p| matches!(p, Placed::Certificate)).and_then(fields_of);
+        fields.unwrap_or_else(|| self.fields(365)).apply(&mut cert)?;
+        match issuer{
+            Some((ca, ca_key)) => cert.issue_certificate_from(ca, ca_key)?,
+            None => cert.sign_certificate_with(&key)?,
+        }
+        repo.certificate.tree = cert.encoded_tree()?;
+
+        for (i, child) in repo.child_repos.iter_mut().enumerate(){
+            hashes.push(self.normalize_ca(child, Some((&cert, &key)), &[path, &[i]].concat(), placement)?);
+        }
+
+        for (i, p) in repo.payloads.iter_mut().enumerate(){
+            let mut obj = State::from_tree(p.tree.clone());
+            let old = hash(&obj);
+            let fields = placement.find(path, |p| matches!(p, Placed::Payload(index) if *index == i)).and_then(fields_of);
+            fields.unwrap_or_else(|| self.fields(365)).apply(&mut obj)?;
+            match cms::signed_object(&obj.tree).is_ok(){
+                true => obj.issue_from(&cert, &key, &p.child_key)?,
+                false => obj.issue_certificate_from(&cert, &key)?,
+            }
+            hashes.push((old, hash(&obj)));
+            p.tree = obj.encoded_tree()?;
+        }
+
+        let (this_update, next_update) = (self.time.to_rfc3339(), (self.time + Duration::days(1)).to_rfc3339());
+        if options.fix_crl || placement.find(path, |p| matches!(p, Placed::Crl)).is_none(){
+            let mut crl = State::from_tree(repo.crl.tree.clone());
+            let old = hash(&crl);
+            crl.crl_set_update(this_update.clone(), next_update.clone())?;
+            crl.crl_set_number("1".to_string())?;
+            crl.sign_crl_with(&key)?;
+            hashes.push((old, hash(&crl)));
+            repo.crl.tree = crl.encoded_tree()?;
+        }
+
+        if options.fix_manifest || placement.find(path, |p| matches!(p, Placed::Manifest)).is_none(){
+            let mut mft = State::from_tree(repo.manifest.tree.clone());
+            mft.manifest_replace_hashes(&hashes)?;
+            mft.manifest_set_window(this_update, next_update)?;
+            mft.manifest_set_number("1".to_string())?;
+
+            self.fields(1).apply(&mut mft)?;
+            mft.issue_from(&cert, &key, &repo.manifest.child_key)?;
+            repo.manifest.tree = mft.encoded_tree()?;
+        }
+        Ok((old_cert, hash(&cert)))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::{repo::Repository, State};
+
+    const OPTIONS: &str = r#"{"seed": 7, "time": "2024-01-01T00:00:00Z"}"#;
+
+    #[test]
+    fn seeded_archives_are_identical(){
+        let roa = State::load_example("roa").unwrap();
+        let archive = roa.repositorify_with(OPTIONS.to_string()).unwrap();
+        assert_eq!(archive, roa.repositorify_with(OPTIONS.to_string()).unwrap());
+        assert_ne!(archive, roa.repositorify_with(OPTIONS.replace('7', "8")).unwrap());
+
+        let mut repo = Repository::new();
+        repo.add_ca("child".to_string(), "ta".to_string()).unwrap();
+        repo.place(&roa, "child".to_string(), "payload".to_string(), None).unwrap();
+        repo.place(&roa, "ta".to_string(), "payload".to_string(), Some("ta-roa".to_string())).unwrap();
+        assert_eq!(repo.repositorify(OPTIONS.to_string()).unwrap(), repo.repositorify(OPTIONS.to_string()).unwrap());
+    }
+}
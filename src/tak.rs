@@ -0,0 +1,287 @@
+use cure_pp::repository_util::{load_random_key, random_fname, ObjectKey};
+use wasm_bindgen::prelude::*;
+
+use crate::{cms, create_tar_gz_in_memory, der, encoder::{self, Overrides}, keys, repo, tree_util::{self, children, tag, Payload}, State};
+
+// Trust Anchor Keys (RFC 9691) announce the current key of a trust anchor together with
+// its predecessor or successor, so relying parties can follow a key rollover.
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaKey{
+    #[serde(default)]
+    pub comments: Vec<String>,
+    pub certificate_uris: Vec<String>,
+    pub public_key: String, // Base64 SubjectPublicKeyInfo, as in a TAL
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TakContent{
+    pub current: TaKey,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub predecessor: Option<TaKey>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub successor: Option<TaKey>,
+}
+
+fn parse_ta_key(p: &Payload, id: usize, overrides: &Overrides) -> Result<TaKey, String>{
+    let t = &p.tree;
+    let c = children(t, id);
+    if c.len() != 3{
+        return Err("TAKey must contain comments, certificateURIs and subjectPublicKeyInfo".to_string());
+    }
+    let strings = |list: usize| children(t, list).iter().map(|s| String::from_utf8_lossy(&t.tokens[s].data).to_string()).collect::<Vec<String>>();
+
+    Ok(TaKey{
+        comments: strings(c[0]),
+        certificate_uris: strings(c[1]),
+        public_key: base64::encode(p.encode(c[2], overrides)),
+    })
+}
+
+/// Parses the TAK payload, an explicit version is skipped.
+pub fn parse_tak(p: &Payload, overrides: &Overrides) -> Result<TakContent, String>{
+    let t = &p.tree;
+    let c = children(t, p.root);
+    let c = if c.first().map(|v| tag(t, *v) == 0x02).unwrap_or(false) { &c[1..] } else { c };
+    let current = c.first().ok_or("TAK must contain the current key")?;
+
+    let tagged = |tag_u: u8| c[1..].iter().find(|n| tag(t, **n) == tag_u).and_then(|n| children(t, *n).first().cloned());
+    Ok(TakContent{
+        current: parse_ta_key(p, *current, overrides)?,
+        predecessor: tagged(0xA0).map(|k| parse_ta_key(p, k, overrides)).transpose()?,
+        successor: tagged(0xA1).map(|k| parse_ta_key(p, k, overrides)).transpose()?,
+    })
+}
+
+fn encode_ta_key(key: &TaKey) -> Result<Vec<u8>, String>{
+    let comments = key.comments.iter().flat_map(|c| der::tlv(0x0C, c.as_bytes())).collect::<Vec<u8>>();
+    let uris = key.certificate_uris.iter().flat_map(|u| der::tlv(0x16, u.as_bytes())).collect::<Vec<u8>>();
+    let spki = base64::decode(key.public_key.split_whitespace().collect::<String>()).map_err(|_| "Public key must be base64 encoded".to_string())?;
+
+    let mut ret = der::tlv(0x30, &comments);
+    ret.extend(der::tlv(0x30, &uris));
+    ret.extend(spki);
+    Ok(der::tlv(0x30, &ret))
+}
+
+/// TAL of a key (RFC 8630), comments become # lines.
+pub(crate) fn tal_text(key: &TaKey) -> String{
+    let mut lines = key.comments.iter().map(|c| format!("# {}", c)).collect::<Vec<String>>();
+    lines.extend(key.certificate_uris.iter().cloned());
+    lines.push(String::new());
+
+    let spki = key.public_key.split_whitespace().collect::<String>();
+    lines.extend(spki.as_bytes().chunks(64).map(|c| String::from_utf8_lossy(c).to_string()));
+    lines.join("\n") + "\n"
+}
+
+/// URIs of a TAL, comments are skipped.
+pub(crate) fn tal_uris(tal: &str) -> Vec<String>{
+    tal.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.starts_with('#'))
+        .take_while(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect()
+}
+
+#[wasm_bindgen]
+impl State{
+    /// Returns the current, predecessor and successor key of a TAK as JSON.
+    #[wasm_bindgen]
+    pub fn tak_get(&self) -> Result<String, String>{
+        let econtent = self.signed_payload(cms::OID_CT_TAK, "TAK")?;
+        let p = tree_util::payload(&self.tree, econtent, &self.overrides).ok_or("eContent is not valid ASN.1")?;
+        Ok(serde_json::to_string(&parse_tak(&p, &self.overrides)?).unwrap())
+    }
+
+    /// Rebuilds the TAK payload from JSON in the format of `tak_get`.
+    /// Digest and signatures are not updated, see `resign`.
+    #[wasm_bindgen]
+    pub fn tak_set(&mut self, content: String) -> Result<(), String>{
+        let content: TakContent = serde_json::from_str(&content).map_err(|e| format!("Invalid TAK: {}", e))?;
+        let econtent = self.signed_payload(cms::OID_CT_TAK, "TAK")?;
+
+        // The version is a plain INTEGER here, an explicit one is kept as it is
+        let mut tak = vec![];
+        if let Some(p) = tree_util::payload(&self.tree, econtent, &self.overrides){
+            if let Some(version) = children(&p.tree, p.root).first().filter(|v| tag(&p.tree, **v) == 0x02){
+                tak.extend(p.encode(*version, &self.overrides));
+            }
+        }
+        tak.extend(encode_ta_key(&content.current)?);
+        if let Some(key) = content.predecessor.as_ref(){
+            tak.extend(der::tlv(0xA0, &encode_ta_key(key)?));
+        }
+        if let Some(key) = content.successor.as_ref(){
+            tak.extend(der::tlv(0xA1, &encode_ta_key(key)?));
+        }
+
+        tree_util::set_string_payload(&mut self.tree, econtent, &der::tlv(0x30, &tak))?;
+        self.overrides.retain_existing(&self.tree);
+        Ok(())
+    }
+
+    /// TAL of the current, predecessor or successor key of the TAK.
+    #[wasm_bindgen]
+    pub fn tak_tal(&self, key: String) -> Result<String, String>{
+        let content: TakContent = serde_json::from_str(&self.tak_get()?).unwrap();
+        let key = match key.as_str(){
+            "current" => Some(content.current),
+            "predecessor" => content.predecessor,
+            "successor" => content.successor,
+            _ => return Err("Key must be current, predecessor or successor".to_string()),
+        };
+        key.map(|k| tal_text(&k)).ok_or("TAK has no such key".to_string())
+    }
+
+    /// Like `repositorify`, with a TAK under the trust anchor that announces a successor key.
+    /// The successor TA certificate and its TAL are part of the archive, so a relying party
+    /// can be taken through a key rollover (RFC 9691).
+    #[wasm_bindgen]
+    pub fn repositorify_tak(&self) -> Result<Vec<u8>, String>{
+        let options = repo::RepoOptions::default();
+        let (mut repo, extra_files) = self.build_rpki_repo(&options)?;
+        let ta = State::from_tree(repo.certificate.tree.clone());
+        let uris = tal_uris(&repo.get_tal());
+
+        let successor_key = keys::load_key(None)?;
+        let successor = ta.successor_certificate(&successor_key)?;
+        let successor_uris = uris.iter().map(|u| format!("{}/ta-successor.cer", u.rsplit_once('/').map(|(base, _)| base).unwrap_or(u))).collect();
+
+        let ta_cert = cms::certificate(&ta.tree, ta.tree.root_id)?;
+        let content = TakContent{
+            current: TaKey{
+                comments: vec!["Current key".to_string()],
+                certificate_uris: uris,
+                public_key: base64::encode(encoder::encode_element(&ta.tree, ta_cert.spki, &ta.overrides)),
+            },
+            predecessor: None,
+            successor: Some(TaKey{
+                comments: vec!["Successor key".to_string()],
+                certificate_uris: successor_uris,
+                public_key: base64::encode(keys::subject_public_key_info(&successor_key)),
+            }),
+        };
+
+        let mut tak = State::example_tak()?;
+        tak.tak_set(serde_json::to_string(&content).unwrap())?;
+        let ee_key = load_random_key(&repo.conf).1;
+        tak.issue_from(&ta, &repo.certificate.child_key, &ee_key)?;
+        repo::publish(&mut repo, &format!("{}.tak", random_fname()), &tak, ee_key)?;
+
+        let (mut files, tal, ta_cert) = State::export_rpki_repo(repo, extra_files, &options)?;
+        let ta_path = repo::ta_certificate_path(&String::from_utf8_lossy(&tal));
+        files.push((ta_path, ta_cert));
+        files.push(("ta.tal".to_string(), tal));
+        // The successor certificate is published where its TAL points to
+        let successor_tal = tal_text(content.successor.as_ref().unwrap());
+        files.push((repo::ta_certificate_path(&successor_tal), successor.encode()));
+        files.push(("ta-successor.tal".to_string(), successor_tal.into_bytes()));
+        Ok(create_tar_gz_in_memory(files).unwrap_or_default())
+    }
+}
+
+impl State{
+    /// Example TAK with a random current key, derived from the ROA example since the object generator has no TAKs.
+    pub(crate) fn example_tak() -> Result<State, String>{
+        let mut state = State::example_signed_object(cms::OID_CT_TAK)?;
+        // The asID of the ROA would be kept as the version of the TAK
+        let econtent = state.signed_payload(cms::OID_CT_TAK, "TAK")?;
+        tree_util::set_string_payload(&mut state.tree, econtent, &der::tlv(0x30, &[]))?;
+
+        let content = TakContent{
+            current: TaKey{
+                comments: vec!["Example trust anchor".to_string()],
+                certificate_uris: vec!["rsync://example.net/repo/ta.cer".to_string(), "https://example.net/repo/ta.cer".to_string()],
+                public_key: base64::encode(keys::subject_public_key_info(&keys::load_key(None)?)),
+            },
+            predecessor: None,
+            successor: None,
+        };
+        state.tak_set(serde_json::to_string(&content).unwrap())?;
+        state.set_cert_resources("inherit".to_string())?;
+
+        let ee_key = keys::load_key(None)?;
+        state.resign_with(&Default::default(), &ee_key, Some(&keys::load_key(None)?))?;
+        Ok(state)
+    }
+
+    /// Copy of this TA certificate with `key`, self-signed.
+    fn successor_certificate(&self, key: &ObjectKey) -> Result<State, String>{
+        let mut cert = self.clone();
+        let spki = cms::certificate(&cert.tree, cert.tree.root_id)?.spki;
+        tree_util::replace_node(&mut cert.tree, spki, &keys::subject_public_key_info(key))?;
+
+        let ids = cert.key_identifiers()?;
+        let ski = hex::decode(&ids.expected_ski).unwrap();
+        cert.set_cert_extension(cms::OID_SKI, false, Some(&der::tlv(0x04, &ski)))?;
+        if ids.aki_node.is_some(){
+            cert.set_cert_extension(cms::OID_AKI, false, Some(&der::tlv(0x30, &der::tlv(0x80, &ski))))?;
+        }
+        cert.sign_certificate_with(key)?;
+        Ok(cert)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn content(state: &State) -> TakContent{
+        serde_json::from_str(&state.tak_get().unwrap()).unwrap()
+    }
+
+    fn successor() -> TaKey{
+        TaKey{
+            comments: vec!["Successor key".to_string()],
+            certificate_uris: vec!["rsync://example.net/repo/ta-successor.cer".to_string()],
+            public_key: base64::encode(keys::subject_public_key_info(&keys::load_key(None).unwrap())),
+        }
+    }
+
+    #[test]
+    fn set_get(){
+        let mut state = State::load_example("tak").unwrap();
+        let mut tak = content(&state);
+        assert!(tak.predecessor.is_none() && tak.successor.is_none());
+
+        tak.successor = Some(successor());
+        state.tak_set(serde_json::to_string(&tak).unwrap()).unwrap();
+        let read = content(&state);
+        assert_eq!(read.current.certificate_uris, tak.current.certificate_uris);
+        assert_eq!(read.successor.unwrap().public_key, tak.successor.unwrap().public_key);
+
+        let encoded = state.encode();
+        state.tak_set(state.tak_get().unwrap()).unwrap();
+        assert_eq!(state.encode(), encoded);
+    }
+
+    #[test]
+    fn tal(){
+        let mut state = State::load_example("tak").unwrap();
+        let mut tak = content(&state);
+        tak.successor = Some(successor());
+        state.tak_set(serde_json::to_string(&tak).unwrap()).unwrap();
+
+        let tal = state.tak_tal("successor".to_string()).unwrap();
+        assert!(tal.starts_with("# Successor key\n"));
+        assert_eq!(tal_uris(&tal), ["rsync://example.net/repo/ta-successor.cer"]);
+        assert_eq!(repo::ta_certificate_path(&tal), "repo/ta-successor.cer");
+        assert_eq!(tal.lines().skip_while(|l| !l.is_empty()).collect::<String>(), tak.successor.unwrap().public_key);
+
+        assert!(state.tak_tal("predecessor".to_string()).is_err());
+        assert!(state.tak_tal("next".to_string()).is_err());
+    }
+
+    #[test]
+    fn invalid_content(){
+        let mut state = State::load_example("tak").unwrap();
+        let mut tak = content(&state);
+        tak.current.public_key = "!".to_string();
+        assert!(state.tak_set(serde_json::to_string(&tak).unwrap()).is_err());
+        assert!(state.tak_set("{}".to_string()).is_err());
+        assert!(State::load_example("roa").unwrap().tak_get().is_err());
+    }
+}
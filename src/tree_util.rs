@@ -0,0 +1,212 @@
+use std::borrow::Cow;
+
+use cure_asn1::tree_parser::Tree;
+
+use crate::{encode_oid_from_string, encoder::{self, Overrides}};
+
+/// Copies all nodes of `sub` into `tree` and attaches them below `parent`.
+/// Returns the new id of the root of `sub`.
+pub fn graft(tree: &mut Tree, sub: &Tree, parent: usize, position: Option<usize>) -> usize{
+    let mut next_id = tree.tokens.keys().max().map(|m| m + 1).unwrap_or(0).max(tree.cur_index);
+
+    let mut id_map = std::collections::HashMap::new();
+    for old_id in subtree_ids(sub, sub.root_id){
+        id_map.insert(old_id, next_id);
+        next_id += 1;
+    }
+
+    for (old_id, new_id) in id_map.iter(){
+        let mut token = sub.tokens[old_id].clone();
+        token.id = *new_id;
+        token.parent = if *old_id == sub.root_id{
+            parent
+        }
+        else{
+            id_map[&token.parent]
+        };
+        token.children = token.children.iter().map(|c| id_map[c]).collect();
+        token.tainted = true;
+        tree.tokens.insert(*new_id, token);
+    }
+    tree.cur_index = next_id;
+
+    let new_root = id_map[&sub.root_id];
+    let siblings = &mut tree.tokens.get_mut(&parent).unwrap().children;
+    match position{
+        Some(p) if p < siblings.len() => siblings.insert(p, new_root),
+        _ => siblings.push(new_root),
+    }
+
+    new_root
+}
+
+/// Ids of a node and all its descendants, in encoding order.
+pub fn subtree_ids(tree: &Tree, id: usize) -> Vec<usize>{
+    let mut ret = vec![id];
+    for child in tree.tokens[&id].children.iter(){
+        ret.extend(subtree_ids(tree, *child));
+    }
+    ret
+}
+
+/// Parses `payload` as ASN.1 and checks that it re-encodes to exactly the same bytes.
+pub fn parse_encapsulated(payload: &[u8]) -> Result<Tree, String>{
+    if payload.is_empty(){
+        return Err("Node has no content to expand".to_string());
+    }
+
+    let sub = cure_asn1::interface::parse_tree(payload, "").ok_or("Content is not valid ASN.1".to_string())?;
+    if encoder::encode_tree(&sub, &Overrides::default()) != payload{
+        return Err("Content is not a single DER encoded element".to_string());
+    }
+    Ok(sub)
+}
+
+/// Replaces the children of a string node with the parsed `payload`.
+pub fn set_encapsulated(tree: &mut Tree, id: usize, payload: &[u8]) -> Result<(), String>{
+    let sub = parse_encapsulated(payload)?;
+
+    for child in tree.tokens[&id].children.clone(){
+        tree.deep_delete(child);
+    }
+    tree.tokens.get_mut(&id).unwrap().children.clear();
+
+    let token = tree.tokens.get_mut(&id).unwrap();
+    token.manipulated = true;
+    token.data = if encoder::is_bit_string(token.tag_u){
+        vec![0]
+    }
+    else{
+        vec![]
+    };
+
+    graft(tree, &sub, id, None);
+    tree.taint_parents(id);
+    tree.fix_sizes(true);
+    Ok(())
+}
+
+/// Raw bytes carried by an OCTET STRING or BIT STRING, whether it is expanded or not.
+/// For BIT STRINGs the unused-bits octet is not part of the payload.
+pub fn string_payload(tree: &Tree, id: usize, overrides: &Overrides) -> Vec<u8>{
+    let token = &tree.tokens[&id];
+    let content = encoder::encode_content(tree, id, overrides);
+    if encoder::is_bit_string(token.tag_u) && !content.is_empty(){
+        return content[1..].to_vec();
+    }
+    content
+}
+
+/// Writes the payload of an OCTET STRING or BIT STRING. Expanded strings stay expanded.
+pub fn set_string_payload(tree: &mut Tree, id: usize, payload: &[u8]) -> Result<(), String>{
+    let token = &tree.tokens[&id];
+    if !token.children.is_empty(){
+        return set_encapsulated(tree, id, payload);
+    }
+
+    let data = if encoder::is_bit_string(token.tag_u){
+        let mut d = vec![0];
+        d.extend_from_slice(payload);
+        d
+    }
+    else{
+        payload.to_vec()
+    };
+    set_content(tree, id, data);
+    Ok(())
+}
+
+/// Sets the content of a primitive node and updates the lengths above it.
+pub fn set_content(tree: &mut Tree, id: usize, data: Vec<u8>){
+    let token = tree.tokens.get_mut(&id).unwrap();
+    token.data = data;
+    token.manipulated = true;
+    token.tainted = true;
+    tree.taint_parents(id);
+    tree.fix_sizes(true);
+}
+
+/// Replaces a node and its subtree with the parsed `bytes`, keeping its position. Returns the new id.
+pub fn replace_node(tree: &mut Tree, id: usize, bytes: &[u8]) -> Result<usize, String>{
+    let sub = parse_encapsulated(bytes)?;
+
+    if id == tree.root_id{
+        let obj_type = tree.obj_type.clone();
+        *tree = sub;
+        tree.obj_type = obj_type;
+        return Ok(tree.root_id);
+    }
+
+    let parent = tree.tokens[&id].parent;
+    let position = tree.tokens[&parent].children.iter().position(|c| *c == id);
+    tree.deep_delete(id);
+    tree.tokens.get_mut(&parent).unwrap().children.retain(|c| *c != id);
+
+    let new_id = graft(tree, &sub, parent, position);
+    tree.taint_parents(new_id);
+    tree.fix_sizes(true);
+    Ok(new_id)
+}
+
+pub fn tag(tree: &Tree, id: usize) -> u8{
+    tree.tokens[&id].tag_u
+}
+
+pub fn children(tree: &Tree, id: usize) -> &[usize]{
+    &tree.tokens[&id].children
+}
+
+/// The `index`-th child of a node, or an error naming `what` was expected there.
+pub fn child(tree: &Tree, id: usize, index: usize, what: &str) -> Result<usize, String>{
+    tree.tokens[&id].children.get(index).cloned().ok_or(format!("Missing {}", what))
+}
+
+/// First child with the given tag.
+pub fn child_by_tag(tree: &Tree, id: usize, tag_u: u8) -> Option<usize>{
+    tree.tokens[&id].children.iter().find(|c| tree.tokens[*c].tag_u == tag_u).cloned()
+}
+
+pub fn is_oid(tree: &Tree, id: usize, oid: &str) -> bool{
+    let token = &tree.tokens[&id];
+    token.tag_u == 0x06 && token.data == encode_oid_from_string(oid)
+}
+
+/// Encapsulated content of an OCTET STRING or BIT STRING as a tree. Expanded strings are used
+/// in place, otherwise the payload is parsed and all its nodes map to the string node itself.
+pub struct Payload<'a>{
+    pub tree: Cow<'a, Tree>,
+    pub root: usize,
+    outer: Option<usize>,
+}
+
+impl Payload<'_>{
+    /// Id of the node in the original tree to report for `id`.
+    pub fn node(&self, id: usize) -> usize{
+        self.outer.unwrap_or(id)
+    }
+
+    /// Encoding of a node, overrides only apply if the payload is part of the original tree.
+    pub fn encode(&self, id: usize, overrides: &Overrides) -> Vec<u8>{
+        match self.outer{
+            Some(_) => encoder::encode_element(&self.tree, id, &Overrides::default()),
+            None => encoder::encode_element(&self.tree, id, overrides),
+        }
+    }
+}
+
+pub fn payload<'a>(tree: &'a Tree, id: usize, overrides: &Overrides) -> Option<Payload<'a>>{
+    if let Some(first) = tree.tokens[&id].children.first(){
+        return Some(Payload{
+            tree: Cow::Borrowed(tree),
+            root: *first,
+            outer: None,
+        });
+    }
+
+    let sub = parse_encapsulated(&string_payload(tree, id, overrides)).ok()?;
+    Some(Payload{
+        root: sub.root_id,
+        tree: Cow::Owned(sub),
+        outer: Some(id),
+    })
+}
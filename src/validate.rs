@@ -0,0 +1,1112 @@
+use chrono::{DateTime, Utc};
+use cure_asn1::tree_parser::Tree;
+use sha2::{Digest, Sha256};
+use wasm_bindgen::prelude::*;
+
+use crate::{cms, der, encoder::Overrides, gbr, keys, router, tak, resources::{self, AsResources, IpFamily}, tree_util::{self, child_by_tag, children, is_oid, tag}, State};
+
+// Checks objects against the RPKI profiles. Every violation points to the node it was
+// found at and the section of the profile that is violated.
+
+pub(crate) const OID_BASIC_CONSTRAINTS: &str = "2.5.29.19";
+pub(crate) const OID_KEY_USAGE: &str = "2.5.29.15";
+pub(crate) const OID_EXT_KEY_USAGE: &str = "2.5.29.37";
+const OID_CRL_DP: &str = "2.5.29.31";
+const OID_CERT_POLICIES: &str = "2.5.29.32";
+const OID_AIA: &str = "1.3.6.1.5.5.7.1.1";
+pub(crate) const OID_SIA: &str = "1.3.6.1.5.5.7.1.11";
+const OID_IP_RESOURCES_V2: &str = "1.3.6.1.5.5.7.1.28";
+const OID_AS_RESOURCES_V2: &str = "1.3.6.1.5.5.7.1.29";
+
+const OID_POLICY_RPKI: &str = "1.3.6.1.5.5.7.14.2";
+const OID_POLICY_RPKI_V2: &str = "1.3.6.1.5.5.7.14.3";
+
+const OID_AD_CA_ISSUERS: &str = "1.3.6.1.5.5.7.48.2";
+pub(crate) const OID_AD_CA_REPOSITORY: &str = "1.3.6.1.5.5.7.48.5";
+pub(crate) const OID_AD_MANIFEST: &str = "1.3.6.1.5.5.7.48.10";
+const OID_AD_SIGNED_OBJECT: &str = "1.3.6.1.5.5.7.48.11";
+const OID_AD_NOTIFY: &str = "1.3.6.1.5.5.7.48.13";
+
+// Extensions allowed in resource certificates with their required criticality
+const CERT_EXTENSIONS: &[(&str, bool, &str)] = &[
+    (OID_BASIC_CONSTRAINTS, true, "RFC 6487 4.8.1"),
+    (cms::OID_SKI, false, "RFC 6487 4.8.2"),
+    (cms::OID_AKI, false, "RFC 6487 4.8.3"),
+    (OID_KEY_USAGE, true, "RFC 6487 4.8.4"),
+    (OID_EXT_KEY_USAGE, false, "RFC 6487 4.8.5"),
+    (OID_CRL_DP, false, "RFC 6487 4.8.6"),
+    (OID_AIA, false, "RFC 6487 4.8.7"),
+    (OID_SIA, false, "RFC 6487 4.8.8"),
+    (OID_CERT_POLICIES, true, "RFC 6487 4.8.9"),
+    (resources::OID_IP_RESOURCES, true, "RFC 6487 4.8.10"),
+    (resources::OID_AS_RESOURCES, true, "RFC 6487 4.8.11"),
+    (OID_IP_RESOURCES_V2, true, "RFC 8360 4.2"),
+    (OID_AS_RESOURCES_V2, true, "RFC 8360 4.3"),
+];
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Violation{
+    pub node: usize,
+    pub section: String, // e.g. "RFC 6487 4.8.4"
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct ValidationReport{
+    pub object_type: String,
+    pub violations: Vec<Violation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Profile{
+    CaCertificate,
+    EeCertificate,
+    RouterCertificate,
+    Crl,
+    Roa,
+    Manifest,
+    Aspa,
+    Gbr,
+    Rsc,
+    Tak,
+    SignedObject, // Unknown eContentType, only the CMS profile is checked
+}
+
+impl Profile{
+    fn name(&self) -> &'static str{
+        match self{
+            Profile::CaCertificate => "CA certificate",
+            Profile::EeCertificate => "EE certificate",
+            Profile::RouterCertificate => "BGPsec router certificate",
+            Profile::Crl => "CRL",
+            Profile::Roa => "ROA",
+            Profile::Manifest => "Manifest",
+            Profile::Aspa => "ASPA",
+            Profile::Gbr => "Ghostbusters record",
+            Profile::Rsc => "RSC",
+            Profile::Tak => "TAK",
+            Profile::SignedObject => "Signed object",
+        }
+    }
+}
+
+/// What the checked certificate says, for comparison with the payload of a signed object.
+#[derive(Debug, Clone, Default)]
+struct CertInfo{
+    ski: Option<Vec<u8>>,
+    not_before: Option<DateTime<Utc>>,
+    not_after: Option<DateTime<Utc>>,
+    ip: Option<(usize, Vec<IpFamily>)>,
+    asn: Option<(usize, AsResources)>,
+}
+
+struct Extension{
+    id: usize,
+    oid: String,
+    critical: bool,
+    value: usize, // extnValue OCTET STRING
+}
+
+#[wasm_bindgen]
+impl State{
+    /// Checks the object against its RPKI profile (RFC 6487, 6488, 8209, 9582, 9286, 9323, 9691, 6493, ASPA).
+    /// Returns a JSON report with the detected object type and all violations found. Validity
+    /// times are checked against `time` (RFC 3339) if given, otherwise against the current time.
+    #[wasm_bindgen]
+    pub fn validate_rpki(&self, time: Option<String>) -> Result<String, String>{
+        let now = match time.as_deref().map(str::trim).filter(|t| !t.is_empty()){
+            Some(t) => DateTime::parse_from_rfc3339(t).map_err(|_| format!("Invalid time {}, use RFC 3339", t))?.with_timezone(&Utc),
+            None => Utc::now(),
+        };
+        let mut v = Validator{
+            tree: &self.tree,
+            overrides: &self.overrides,
+            now,
+            violations: vec![],
+        };
+
+        let profile = detect_profile(&self.tree)?;
+        match profile{
+            Profile::CaCertificate | Profile::EeCertificate | Profile::RouterCertificate => {
+                v.certificate(self.tree.root_id, profile, None);
+            }
+            Profile::Crl => v.crl(self.tree.root_id),
+            _ => v.signed_object(profile)?,
+        }
+
+        let report = ValidationReport{
+            object_type: profile.name().to_string(),
+            violations: v.violations,
+        };
+        Ok(serde_json::to_string(&report).unwrap())
+    }
+}
+
+fn detect_profile(tree: &Tree) -> Result<Profile, String>{
+    let root = tree.root_id;
+    let first = tree_util::child(tree, root, 0, "content")?;
+
+    if is_oid(tree, first, cms::OID_SIGNED_DATA){
+        let obj = cms::signed_object(tree)?;
+        let ct = obj.content_type;
+        let profile = [
+            (cms::OID_CT_ROA, Profile::Roa),
+            (cms::OID_CT_MANIFEST, Profile::Manifest),
+            (cms::OID_CT_ASPA, Profile::Aspa),
+            (cms::OID_CT_GBR, Profile::Gbr),
+            (cms::OID_CT_RSC, Profile::Rsc),
+            (cms::OID_CT_TAK, Profile::Tak),
+        ].iter().find(|(oid, _)| is_oid(tree, ct, oid)).map(|(_, p)| *p);
+        return Ok(profile.unwrap_or(Profile::SignedObject));
+    }
+
+    if tag(tree, first) != 0x30 || children(tree, root).len() != 3{
+        return Err("Object is neither a certificate, a CRL nor a signed object".to_string());
+    }
+
+    // thisUpdate follows the issuer in a TBSCertList, a TBSCertificate has the Validity SEQUENCE there
+    let tbs = children(tree, first);
+    if tbs.iter().skip(2).take(2).any(|c| matches!(tag(tree, *c), 0x17 | 0x18)){
+        return Ok(Profile::Crl);
+    }
+
+    // basicConstraints with cA TRUE
+    let cert = cms::certificate(tree, root)?;
+    let is_ca = cert.extensions
+        .and_then(|e| cms::extension_value(tree, e, OID_BASIC_CONSTRAINTS))
+        .and_then(|bc| tree_util::payload(tree, bc, &Overrides::default()))
+        .and_then(|p| children(&p.tree, p.root).first().map(|c| tag(&p.tree, *c) == 0x01 && p.tree.tokens[c].data != [0]))
+        .unwrap_or(false);
+    Ok(match is_ca{
+        true => Profile::CaCertificate,
+        false if router::is_router_certificate(tree) => Profile::RouterCertificate,
+        false => Profile::EeCertificate,
+    })
+}
+
+fn oid(tree: &Tree, id: usize) -> Option<String>{
+    (tag(tree, id) == 0x06).then(|| der::decode_oid(&tree.tokens[&id].data))
+}
+
+fn uint(tree: &Tree, id: usize) -> Option<u128>{
+    if tag(tree, id) != 0x02{
+        return None;
+    }
+    der::parse_uint(&tree.tokens[&id].data)
+}
+
+fn time(tree: &Tree, id: usize) -> Option<DateTime<Utc>>{
+    der::parse_time(tag(tree, id), &tree.tokens[&id].data)
+}
+
+/// All URIs (GeneralName uniformResourceIdentifier) below a node.
+fn uris(tree: &Tree, id: usize) -> Vec<String>{
+    tree_util::subtree_ids(tree, id).into_iter()
+        .filter(|c| tag(tree, *c) == 0x86)
+        .map(|c| String::from_utf8_lossy(&tree.tokens[&c].data).to_string())
+        .collect()
+}
+
+/// rsync URI of the access method `method` in the SIA of the certificate (or EE certificate).
+pub(crate) fn sia_uri(state: &State, method: &str) -> Option<String>{
+    let cert = cms::certificate(&state.tree, cms::object_certificate(&state.tree).ok()?).ok()?;
+    let value = cms::extension_value(&state.tree, cert.extensions?, OID_SIA)?;
+    let p = tree_util::payload(&state.tree, value, &state.overrides)?;
+    let ad = children(&p.tree, p.root).iter().find(|ad| children(&p.tree, **ad).first().and_then(|o| oid(&p.tree, *o)).as_deref() == Some(method))?;
+    uris(&p.tree, *ad).into_iter().find(|u| is_rsync_uri(u))
+}
+
+fn common_name(tree: &Tree, name: usize) -> Option<String>{
+    tree_util::subtree_ids(tree, name).into_iter()
+        .filter(|a| tag(tree, *a) == 0x30 && children(tree, *a).len() == 2)
+        .find(|a| is_oid(tree, children(tree, *a)[0], "2.5.4.3"))
+        .map(|a| String::from_utf8_lossy(&tree.tokens[&children(tree, a)[1]].data).to_string())
+}
+
+fn is_rsync_uri(uri: &str) -> bool{
+    match uri.strip_prefix("rsync://"){
+        Some(rest) => {
+            let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+            !host.is_empty() && !path.is_empty() && !uri.contains(char::is_whitespace)
+        }
+        None => false,
+    }
+}
+
+struct Validator<'a>{
+    tree: &'a Tree,
+    overrides: &'a Overrides,
+    now: DateTime<Utc>,
+    violations: Vec<Violation>,
+}
+
+impl Validator<'_>{
+    fn fail(&mut self, node: usize, section: &str, message: impl Into<String>){
+        self.violations.push(Violation{
+            node,
+            section: section.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn check(&mut self, ok: bool, node: usize, section: &str, message: impl Into<String>){
+        if !ok{
+            self.fail(node, section, message);
+        }
+    }
+
+    /// The `index`-th child, records a violation if it is missing.
+    fn child(&mut self, id: usize, index: usize, what: &str, section: &str) -> Option<usize>{
+        let ret = children(self.tree, id).get(index).cloned();
+        if ret.is_none(){
+            self.fail(id, section, format!("Missing {}", what));
+        }
+        ret
+    }
+
+    fn algorithm(&mut self, id: usize, allowed: &[&str], section: &str, what: &str){
+        let ok = children(self.tree, id).first().map(|o| allowed.iter().any(|a| is_oid(self.tree, *o, a))).unwrap_or(false);
+        self.check(ok, id, section, format!("{} must be {}", what, allowed.join(" or ")));
+    }
+
+    fn validity(&mut self, not_before: usize, not_after: usize, section: &str) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>){
+        let from = time(self.tree, not_before);
+        let until = time(self.tree, not_after);
+        self.check(from.is_some(), not_before, section, "Invalid time");
+        self.check(until.is_some(), not_after, section, "Invalid time");
+
+        if let (Some(f), Some(u)) = (from, until){
+            self.check(f < u, not_after, section, "End of the validity window is not after its start");
+            self.check(f <= self.now, not_before, section, "Not yet valid");
+            self.check(u >= self.now, not_after, section, "Expired");
+        }
+        (from, until)
+    }
+
+    fn extensions(&mut self, id: usize) -> Vec<Extension>{
+        let mut ret = vec![];
+        for ext in children(self.tree, id).to_vec(){
+            let c = children(self.tree, ext);
+            let critical_node = c.iter().find(|n| tag(self.tree, **n) == 0x01).cloned();
+            let (oid, value) = match (c.first().and_then(|o| oid(self.tree, *o)), c.last()){
+                (Some(o), Some(v)) if tag(self.tree, *v) == 0x04 => (o, *v),
+                _ => {
+                    self.fail(ext, "RFC 5280 4.1", "Malformed extension");
+                    continue;
+                }
+            };
+
+            let critical = critical_node.map(|n| self.tree.tokens[&n].data != [0]).unwrap_or(false);
+            if let Some(n) = critical_node{
+                self.check(critical, n, "RFC 5280 4.1", "critical FALSE is the default and must be omitted in DER");
+            }
+            if ret.iter().any(|e: &Extension| e.oid == oid){
+                self.fail(ext, "RFC 5280 4.2", format!("Extension {} appears more than once", oid));
+            }
+            ret.push(Extension{ id: ext, oid, critical, value });
+        }
+        ret
+    }
+
+    /// Checks a resource certificate. `object` is the signed object an EE certificate belongs to.
+    fn certificate(&mut self, id: usize, profile: Profile, object: Option<Profile>) -> CertInfo{
+        let mut info = CertInfo::default();
+        let cert = match cms::certificate(self.tree, id){
+            Ok(c) => c,
+            Err(e) => {
+                self.fail(id, "RFC 6487 4", e);
+                return info;
+            }
+        };
+        let is_ca = profile == Profile::CaCertificate;
+        let is_router = profile == Profile::RouterCertificate;
+
+        let version = children(self.tree, cert.tbs)[0];
+        let offset = if tag(self.tree, version) == 0xA0 { 1 } else { 0 };
+        let version_ok = offset == 1 && children(self.tree, version).first().and_then(|v| uint(self.tree, *v)) == Some(2);
+        self.check(version_ok, version, "RFC 6487 4.1", "Version must be v3");
+
+        if let Some(serial) = self.child(cert.tbs, offset, "serial number", "RFC 6487 4.2"){
+            let data = &self.tree.tokens[&serial].data;
+            let ok = tag(self.tree, serial) == 0x02 && der::parse_uint(data).map(|s| s > 0).unwrap_or(false) && data.len() <= 20;
+            self.check(ok, serial, "RFC 6487 4.2", "Serial number must be a positive integer of at most 20 octets");
+        }
+
+        if let Some(alg) = self.child(cert.tbs, offset + 1, "signature algorithm", "RFC 6487 4.3"){
+            self.algorithm(alg, &[cms::OID_SHA256_WITH_RSA], "RFC 6487 4.3", "Signature algorithm");
+        }
+        if let Some(alg) = self.child(id, 1, "signatureAlgorithm", "RFC 6487 4.3"){
+            self.algorithm(alg, &[cms::OID_SHA256_WITH_RSA], "RFC 6487 4.3", "Signature algorithm");
+        }
+
+        let issuer = self.child(cert.tbs, offset + 2, "issuer", "RFC 6487 4.4");
+        let subject = self.child(cert.tbs, offset + 4, "subject", "RFC 6487 4.5");
+        let self_signed = match (issuer, subject){
+            (Some(i), Some(s)) => self.encode(i) == self.encode(s),
+            _ => false,
+        };
+
+        if let Some(validity) = self.child(cert.tbs, offset + 3, "validity", "RFC 6487 4.6"){
+            let c = children(self.tree, validity).to_vec();
+            if c.len() == 2{
+                let (from, until) = self.validity(c[0], c[1], "RFC 6487 4.6");
+                info.not_before = from;
+                info.not_after = until;
+            }
+            else{
+                self.fail(validity, "RFC 6487 4.6", "Validity must contain notBefore and notAfter");
+            }
+        }
+
+        if let Some(alg) = children(self.tree, cert.spki).first().cloned(){
+            if is_router{
+                self.algorithm(alg, &[keys::OID_EC_PUBLIC_KEY], "RFC 8208 3.1", "Public key algorithm");
+                let curve = children(self.tree, alg).get(1).map(|c| is_oid(self.tree, *c, keys::OID_SECP256R1)).unwrap_or(false);
+                self.check(curve, alg, "RFC 8208 3.1", "Router keys must use the curve P-256");
+            }
+            else{
+                self.algorithm(alg, &[keys::OID_RSA_ENCRYPTION], "RFC 6487 4.7", "Public key algorithm");
+            }
+        }
+        if is_router{
+            let ok = subject.and_then(|s| common_name(self.tree, s)).map(|n| n.starts_with("ROUTER-")).unwrap_or(false);
+            self.check(ok, subject.unwrap_or(cert.tbs), "RFC 8209 3.1", "The common name of the subject must start with ROUTER-");
+        }
+
+        let exts = match cert.extensions{
+            Some(e) => self.extensions(e),
+            None => {
+                self.fail(cert.tbs, "RFC 6487 4.8", "Resource certificates must have extensions");
+                return info;
+            }
+        };
+
+        for ext in exts.iter(){
+            match CERT_EXTENSIONS.iter().find(|(oid, _, _)| *oid == ext.oid){
+                Some((_, critical, section)) => {
+                    let msg = if *critical { "Extension must be critical" } else { "Extension must not be critical" };
+                    self.check(ext.critical == *critical, ext.id, section, msg);
+                }
+                None => self.fail(ext.id, "RFC 6487 4.8", format!("Extension {} is not allowed in resource certificates", ext.oid)),
+            }
+        }
+
+        let find = |oid: &str| exts.iter().find(|e| e.oid == oid);
+        let cert_node = cert.tbs;
+
+        // Presence of the extensions depending on the certificate kind
+        let bc = find(OID_BASIC_CONSTRAINTS);
+        if is_ca{
+            self.check(bc.is_some(), cert_node, "RFC 6487 4.8.1", "CA certificates must have basicConstraints");
+        }
+        else if let Some(bc) = bc{
+            self.fail(bc.id, "RFC 6487 4.8.1", "EE certificates must not have basicConstraints");
+        }
+
+        match find(cms::OID_SKI){
+            Some(ski) => {
+                let payload = tree_util::string_payload(self.tree, ski.value, self.overrides);
+                info.ski = tree_util::parse_encapsulated(&payload).ok().map(|t| t.tokens[&t.root_id].data.clone());
+
+                let bits = children(self.tree, cert.spki).get(1).map(|b| tree_util::string_payload(self.tree, *b, self.overrides));
+                let expected = bits.map(|b| keys::key_identifier(&b));
+                self.check(info.ski.is_some() && info.ski == expected, ski.value, "RFC 6487 4.8.2", "SubjectKeyIdentifier does not match the public key");
+            }
+            None => self.fail(cert_node, "RFC 6487 4.8.2", "Missing SubjectKeyIdentifier"),
+        }
+
+        if !self_signed{
+            self.check(find(cms::OID_AKI).is_some(), cert_node, "RFC 6487 4.8.3", "Missing AuthorityKeyIdentifier");
+        }
+
+        match find(OID_KEY_USAGE){
+            Some(ku) => {
+                let payload = tree_util::string_payload(self.tree, ku.value, self.overrides);
+                let bits = tree_util::parse_encapsulated(&payload).ok().map(|t| t.tokens[&t.root_id].data.clone()).unwrap_or_default();
+                let set = |i: usize| bits.get(1 + i / 8).map(|b| b & (0x80 >> (i % 8)) != 0).unwrap_or(false);
+                let used = (0..9).filter(|i| set(*i)).collect::<Vec<usize>>();
+
+                let (expected, msg) = if is_ca{
+                    (vec![5, 6], "CA certificates must have exactly keyCertSign and cRLSign set")
+                }
+                else{
+                    (vec![0], "EE certificates must have exactly digitalSignature set")
+                };
+                self.check(used == expected, ku.value, "RFC 6487 4.8.4", msg);
+            }
+            None => self.fail(cert_node, "RFC 6487 4.8.4", "Missing keyUsage"),
+        }
+
+        if is_ca{
+            if let Some(eku) = find(OID_EXT_KEY_USAGE){
+                self.fail(eku.id, "RFC 6487 4.8.5", "CA certificates must not have extendedKeyUsage");
+            }
+        }
+        if is_router && !router::is_router_certificate(self.tree){
+            self.fail(cert_node, "RFC 8209 3.1.2.1", "extendedKeyUsage must contain id-kp-bgpsec-router");
+        }
+
+        for (oid, section, name) in [(OID_CRL_DP, "RFC 6487 4.8.6", "CRLDistributionPoints"), (OID_AIA, "RFC 6487 4.8.7", "AuthorityInfoAccess")]{
+            match (find(oid), self_signed){
+                (Some(e), true) => self.fail(e.id, section, format!("Self-signed certificates must not have {}", name)),
+                (Some(e), false) => self.rsync_uris(e.value, section, name),
+                (None, false) => self.fail(cert_node, section, format!("Missing {}", name)),
+                (None, true) => (),
+            }
+        }
+        if let Some(aia) = find(OID_AIA){
+            self.access_methods(aia.value, &[OID_AD_CA_ISSUERS], &[OID_AD_CA_ISSUERS], "RFC 6487 4.8.7");
+        }
+
+        match (find(OID_SIA), object){
+            (Some(sia), Some(Profile::Rsc)) => self.fail(sia.id, "RFC 9323 2.1", "The EE certificate of an RSC must not have SubjectInfoAccess"),
+            (Some(sia), _) if is_router => self.fail(sia.id, "RFC 8209 3.1.2.2", "Router certificates must not have SubjectInfoAccess"),
+            (Some(sia), _) if is_ca => {
+                self.access_methods(sia.value, &[OID_AD_CA_REPOSITORY, OID_AD_MANIFEST], &[OID_AD_CA_REPOSITORY, OID_AD_MANIFEST, OID_AD_NOTIFY], "RFC 6487 4.8.8.1");
+            }
+            (Some(sia), _) => {
+                self.access_methods(sia.value, &[OID_AD_SIGNED_OBJECT], &[OID_AD_SIGNED_OBJECT], "RFC 6487 4.8.8.2");
+            }
+            (None, Some(Profile::Rsc)) => (),
+            (None, _) if is_router => (),
+            (None, _) => self.fail(cert_node, "RFC 6487 4.8.8", "Missing SubjectInfoAccess"),
+        }
+
+        match find(OID_CERT_POLICIES){
+            Some(cp) => {
+                let policies = tree_util::payload(self.tree, cp.value, self.overrides).map(|p| {
+                    let c = children(&p.tree, p.root).to_vec();
+                    c.iter().filter_map(|pi| children(&p.tree, *pi).first().and_then(|o| oid(&p.tree, *o))).collect::<Vec<String>>()
+                }).unwrap_or_default();
+                let ok = policies.len() == 1 && (policies[0] == OID_POLICY_RPKI || policies[0] == OID_POLICY_RPKI_V2);
+                self.check(ok, cp.value, "RFC 6487 4.8.9", "certificatePolicies must contain exactly the RPKI policy");
+            }
+            None => self.fail(cert_node, "RFC 6487 4.8.9", "Missing certificatePolicies"),
+        }
+
+        let ip = find(resources::OID_IP_RESOURCES).or(find(OID_IP_RESOURCES_V2));
+        let asn = find(resources::OID_AS_RESOURCES).or(find(OID_AS_RESOURCES_V2));
+        self.check(ip.is_some() || asn.is_some(), cert_node, "RFC 6487 4.8.10", "At least one of the IP and AS resource extensions must be present");
+        if is_router{
+            if let Some(ip) = ip{
+                self.fail(ip.id, "RFC 8209 3.1.2.3", "Router certificates must not have IP resources");
+            }
+            self.check(asn.is_some(), cert_node, "RFC 8209 3.1.2.4", "Router certificates must have AS resources");
+        }
+
+        if let Some(ip) = ip{
+            let parsed = tree_util::payload(self.tree, ip.value, self.overrides).map(|p| resources::ip_resources(&p.tree, p.root));
+            match parsed{
+                Some(Ok(r)) => info.ip = Some((ip.value, r)),
+                Some(Err(e)) => self.fail(ip.value, "RFC 3779 2.2.3", e),
+                None => self.fail(ip.value, "RFC 3779 2.2.3", "IP resources are not valid ASN.1"),
+            }
+        }
+        if let Some(asn) = asn{
+            let parsed = tree_util::payload(self.tree, asn.value, self.overrides).map(|p| resources::as_resources(&p.tree, p.root));
+            match parsed{
+                Some(Ok(r)) if is_router && r.inherit => self.fail(asn.value, "RFC 8209 3.1.2.4", "AS resources of router certificates must not be inherited"),
+                Some(Ok(r)) => info.asn = Some((asn.value, r)),
+                Some(Err(e)) => self.fail(asn.value, "RFC 3779 3.2.3", e),
+                None => self.fail(asn.value, "RFC 3779 3.2.3", "AS resources are not valid ASN.1"),
+            }
+        }
+
+        info
+    }
+
+    fn encode(&self, id: usize) -> Vec<u8>{
+        crate::encoder::encode_element(self.tree, id, self.overrides)
+    }
+
+    fn rsync_uris(&mut self, value: usize, section: &str, name: &str){
+        let uris = tree_util::payload(self.tree, value, self.overrides).map(|p| uris(&p.tree, p.root)).unwrap_or_default();
+        self.check(uris.iter().any(|u| is_rsync_uri(u)), value, section, format!("{} must contain an rsync URI", name));
+    }
+
+    /// Checks the AccessDescriptions of an AIA or SIA: all `required` methods have an rsync URI
+    /// and no other methods than `allowed` are used.
+    fn access_methods(&mut self, value: usize, required: &[&str], allowed: &[&str], section: &str){
+        let p = match tree_util::payload(self.tree, value, self.overrides){
+            Some(p) => p,
+            None => return self.fail(value, section, "Access descriptions are not valid ASN.1"),
+        };
+
+        let mut found: Vec<(String, String, usize)> = vec![];
+        for ad in children(&p.tree, p.root){
+            let c = children(&p.tree, *ad);
+            let method = c.first().and_then(|o| oid(&p.tree, *o)).unwrap_or_default();
+            let uri = uris(&p.tree, *ad).first().cloned().unwrap_or_default();
+            found.push((method, uri, p.node(*ad)));
+        }
+
+        for (method, uri, node) in found.iter(){
+            if !allowed.contains(&method.as_str()){
+                self.fail(*node, section, format!("Access method {} is not allowed here", method));
+            }
+            else if method == OID_AD_NOTIFY{
+                self.check(uri.starts_with("https://"), *node, section, "RRDP notification URI must use https");
+            }
+            else if !uri.starts_with("rsync://") && !uri.starts_with("https://"){
+                self.fail(*node, section, format!("Invalid URI {}", uri));
+            }
+        }
+
+        for r in required{
+            let ok = found.iter().any(|(m, u, _)| m == r && is_rsync_uri(u));
+            self.check(ok, value, section, format!("Missing access method {} with an rsync URI", r));
+        }
+    }
+
+    fn crl(&mut self, id: usize){
+        let tbs = children(self.tree, id)[0];
+        let c = children(self.tree, tbs).to_vec();
+        if c.len() < 4{
+            return self.fail(tbs, "RFC 6487 5", "TBSCertList is incomplete");
+        }
+
+        let offset = if tag(self.tree, c[0]) == 0x02 { 1 } else { 0 };
+        self.check(offset == 1 && uint(self.tree, c[0]) == Some(1), c[0], "RFC 6487 5", "Version must be v2");
+
+        if let Some(alg) = self.child(tbs, offset, "signature algorithm", "RFC 6487 5"){
+            self.algorithm(alg, &[cms::OID_SHA256_WITH_RSA], "RFC 6487 5", "Signature algorithm");
+        }
+        if let Some(alg) = self.child(id, 1, "signatureAlgorithm", "RFC 6487 5"){
+            self.algorithm(alg, &[cms::OID_SHA256_WITH_RSA], "RFC 6487 5", "Signature algorithm");
+        }
+
+        let this_update = c.get(offset + 2).cloned();
+        let next_update = c.get(offset + 3).cloned().filter(|n| matches!(tag(self.tree, *n), 0x17 | 0x18));
+        match (this_update, next_update){
+            (Some(t), Some(n)) => { self.validity(t, n, "RFC 6487 5"); }
+            _ => self.fail(tbs, "RFC 6487 5", "thisUpdate and nextUpdate must be present"),
+        }
+
+        let revoked = c.iter().skip(offset + 3).find(|n| tag(self.tree, **n) == 0x30).cloned();
+        if let Some(revoked) = revoked{
+            for entry in children(self.tree, revoked).to_vec(){
+                let e = children(self.tree, entry);
+                self.check(e.len() == 2, entry, "RFC 6487 5", "Revoked certificates must not have CRL entry extensions");
+            }
+        }
+
+        let exts = match child_by_tag(self.tree, tbs, 0xA0).and_then(|e| children(self.tree, e).first().cloned()){
+            Some(e) => self.extensions(e),
+            None => return self.fail(tbs, "RFC 6487 5", "Missing crlExtensions"),
+        };
+        for ext in exts.iter(){
+            if ext.oid != cms::OID_AKI && ext.oid != cms::OID_CRL_NUMBER{
+                self.fail(ext.id, "RFC 6487 5", format!("Extension {} is not allowed in CRLs", ext.oid));
+            }
+            self.check(!ext.critical, ext.id, "RFC 6487 5", "CRL extensions must not be critical");
+        }
+        self.check(exts.iter().any(|e| e.oid == cms::OID_AKI), tbs, "RFC 6487 5", "Missing AuthorityKeyIdentifier");
+
+        match exts.iter().find(|e| e.oid == cms::OID_CRL_NUMBER){
+            Some(number) => {
+                let ok = tree_util::payload(self.tree, number.value, self.overrides)
+                    .map(|p| tag(&p.tree, p.root) == 0x02 && der::parse_uint(&p.tree.tokens[&p.root].data).is_some() && p.tree.tokens[&p.root].data.len() <= 20)
+                    .unwrap_or(false);
+                self.check(ok, number.value, "RFC 5280 5.2.3", "CRLNumber must be a non-negative integer of at most 20 octets");
+            }
+            None => self.fail(tbs, "RFC 6487 5", "Missing CRLNumber"),
+        }
+    }
+
+    fn signed_object(&mut self, profile: Profile) -> Result<(), String>{
+        let obj = cms::signed_object(self.tree)?;
+        let sd = obj.signed_data;
+
+        if let Some(version) = self.child(sd, 0, "version", "RFC 6488 2.1.1"){
+            self.check(uint(self.tree, version) == Some(3), version, "RFC 6488 2.1.1", "SignedData version must be 3");
+        }
+        if let Some(algs) = self.child(sd, 1, "digestAlgorithms", "RFC 6488 2.1.2"){
+            let c = children(self.tree, algs).to_vec();
+            self.check(c.len() == 1, algs, "RFC 6488 2.1.2", "digestAlgorithms must contain exactly one algorithm");
+            for alg in c{
+                self.algorithm(alg, &[cms::OID_SHA256], "RFC 6488 2.1.2", "Digest algorithm");
+            }
+        }
+
+        let ee = match child_by_tag(self.tree, sd, 0xA0){
+            Some(certs) => {
+                self.check(children(self.tree, certs).len() == 1, certs, "RFC 6488 2.1.4", "certificates must contain exactly one EE certificate");
+                obj.certificate.map(|c| (c, self.certificate(c, Profile::EeCertificate, Some(profile))))
+            }
+            None => {
+                self.fail(sd, "RFC 6488 2.1.4", "Missing certificates");
+                None
+            }
+        };
+        if let Some(crls) = child_by_tag(self.tree, sd, 0xA1){
+            self.fail(crls, "RFC 6488 2.1.5", "crls must be omitted");
+        }
+
+        let infos = self.tree.tokens[&obj.signer_info].parent;
+        self.check(children(self.tree, infos).len() == 1, infos, "RFC 6488 2.1.6", "signerInfos must contain exactly one SignerInfo");
+        self.signer_info(&obj, ee.as_ref().and_then(|(_, info)| info.ski.clone()))?;
+
+        if let Some((ee_id, info)) = ee{
+            match profile{
+                Profile::Roa => self.roa(obj.econtent, &info),
+                Profile::Manifest => self.manifest(obj.econtent, &info),
+                Profile::Aspa => self.aspa(obj.econtent, &info),
+                Profile::Gbr => self.gbr(obj.econtent),
+                Profile::Rsc => self.rsc(obj.econtent, &info, ee_id),
+                Profile::Tak => self.tak(obj.econtent),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn signer_info(&mut self, obj: &cms::SignedObject, ee_ski: Option<Vec<u8>>) -> Result<(), String>{
+        let si = obj.signer_info;
+        let signer = cms::signer_info(self.tree, si)?;
+
+        if let Some(version) = self.child(si, 0, "version", "RFC 6488 2.1.6.1"){
+            self.check(uint(self.tree, version) == Some(3), version, "RFC 6488 2.1.6.1", "SignerInfo version must be 3");
+        }
+
+        let sid_ok = tag(self.tree, signer.sid) == 0x80;
+        self.check(sid_ok, signer.sid, "RFC 6488 2.1.6.2", "sid must be a subjectKeyIdentifier");
+        if sid_ok && ee_ski.is_some(){
+            self.check(Some(&self.tree.tokens[&signer.sid].data) == ee_ski.as_ref(), signer.sid, "RFC 6488 2.1.6.2", "sid does not match the SKI of the EE certificate");
+        }
+
+        self.check(is_oid(self.tree, signer.digest_algorithm, cms::OID_SHA256), signer.digest_algorithm, "RFC 6488 2.1.6.3", "Digest algorithm must be SHA-256");
+
+        // signatureAlgorithm directly precedes the signature
+        let c = children(self.tree, si).to_vec();
+        if let Some(pos) = c.iter().position(|n| *n == signer.signature){
+            if pos > 0{
+                self.algorithm(c[pos - 1], &[keys::OID_RSA_ENCRYPTION, cms::OID_SHA256_WITH_RSA], "RFC 6488 2.1.6.5", "Signature algorithm");
+            }
+        }
+        if let Some(unsigned) = child_by_tag(self.tree, si, 0xA1){
+            self.fail(unsigned, "RFC 6488 2.1.6.7", "unsignedAttrs must be omitted");
+        }
+
+        let attrs = match signer.signed_attrs{
+            Some(a) => a,
+            None => {
+                self.fail(si, "RFC 6488 2.1.6.4", "Missing signedAttrs");
+                return Ok(());
+            }
+        };
+
+        let allowed = [cms::OID_CONTENT_TYPE, cms::OID_MESSAGE_DIGEST, cms::OID_SIGNING_TIME, cms::OID_BINARY_SIGNING_TIME];
+        let mut seen: Vec<String> = vec![];
+        for attr in children(self.tree, attrs).to_vec(){
+            let a = children(self.tree, attr).to_vec();
+            let attr_oid = a.first().and_then(|o| oid(self.tree, *o)).unwrap_or_default();
+            if !allowed.contains(&attr_oid.as_str()){
+                self.fail(attr, "RFC 6488 2.1.6.4", format!("Signed attribute {} is not allowed", attr_oid));
+            }
+            if seen.contains(&attr_oid){
+                self.fail(attr, "RFC 6488 2.1.6.4", format!("Signed attribute {} appears more than once", attr_oid));
+            }
+            let single = a.get(1).map(|v| children(self.tree, *v).len() == 1).unwrap_or(false);
+            self.check(single, attr, "RFC 6488 2.1.6.4", "Signed attributes must have exactly one value");
+            seen.push(attr_oid);
+        }
+
+        match cms::signed_attribute(self.tree, attrs, cms::OID_CONTENT_TYPE){
+            Some(ct) => {
+                let ok = self.tree.tokens[&ct].data == self.tree.tokens[&obj.content_type].data;
+                self.check(ok, ct, "RFC 6488 2.1.6.4.1", "content-type attribute does not match the eContentType");
+            }
+            None => self.fail(attrs, "RFC 6488 2.1.6.4.1", "Missing content-type attribute"),
+        }
+
+        match cms::signed_attribute(self.tree, attrs, cms::OID_MESSAGE_DIGEST){
+            Some(md) => {
+                let digest = Sha256::digest(tree_util::string_payload(self.tree, obj.econtent, self.overrides)).to_vec();
+                let ok = tree_util::string_payload(self.tree, md, self.overrides) == digest;
+                self.check(ok, md, "RFC 6488 2.1.6.4.2", "message-digest does not match the eContent");
+            }
+            None => self.fail(attrs, "RFC 6488 2.1.6.4.2", "Missing message-digest attribute"),
+        }
+        Ok(())
+    }
+
+    /// Checks that the version of a payload is omitted as required for DEFAULT 0.
+    /// Returns the index of the first field after it.
+    fn default_version(&mut self, p: &tree_util::Payload, section: &str) -> usize{
+        match children(&p.tree, p.root).first(){
+            Some(v) if tag(&p.tree, *v) == 0xA0 => {
+                self.fail(p.node(*v), section, "Version 0 is the default and must be omitted in DER");
+                1
+            }
+            _ => 0,
+        }
+    }
+
+    fn roa(&mut self, econtent: usize, ee: &CertInfo){
+        let p = match tree_util::payload(self.tree, econtent, self.overrides){
+            Some(p) => p,
+            None => return self.fail(econtent, "RFC 9582 4", "eContent is not a valid RouteOriginAttestation"),
+        };
+        let offset = self.default_version(&p, "RFC 9582 4.1");
+        let c = children(&p.tree, p.root).to_vec();
+
+        match c.get(offset){
+            Some(asid) if uint(&p.tree, *asid).map(|a| a <= u32::MAX as u128).unwrap_or(false) => (),
+            Some(asid) => self.fail(p.node(*asid), "RFC 9582 4.2", "asID must be an integer between 0 and 4294967295"),
+            None => self.fail(econtent, "RFC 9582 4.2", "Missing asID"),
+        }
+
+        let blocks = match c.get(offset + 1){
+            Some(b) => *b,
+            None => return self.fail(econtent, "RFC 9582 4.3", "Missing ipAddrBlocks"),
+        };
+        let families = children(&p.tree, blocks).to_vec();
+        self.check((1..=2).contains(&families.len()), p.node(blocks), "RFC 9582 4.3", "ipAddrBlocks must contain one or two address families");
+
+        let ee_ip = ee.ip.as_ref();
+        if ee_ip.map(|(_, r)| r.iter().any(|f| f.inherit)).unwrap_or(false){
+            self.fail(ee_ip.unwrap().0, "RFC 9582 5", "The EE certificate must not use inherit for IP resources");
+        }
+        if ee_ip.is_none(){
+            self.fail(econtent, "RFC 9582 5", "The EE certificate has no IP resources");
+        }
+        if let Some((node, _)) = ee.asn.as_ref(){
+            self.fail(*node, "RFC 9582 5", "The EE certificate must not contain AS resources");
+        }
+
+        let mut afis = vec![];
+        for family in families{
+            let f = children(&p.tree, family).to_vec();
+            let afi = f.first().and_then(|a| resources::parse_afi(&p.tree.tokens[a].data));
+            let bits = match afi.and_then(resources::address_bits){
+                Some(b) if f.len() == 2 => b,
+                _ => {
+                    self.fail(p.node(family), "RFC 9582 4.3.1", "Invalid ROAIPAddressFamily");
+                    continue;
+                }
+            };
+            let afi = afi.unwrap();
+            if afis.contains(&afi){
+                self.fail(p.node(family), "RFC 9582 4.3.1", "Address family appears more than once");
+            }
+            afis.push(afi);
+
+            let addresses = children(&p.tree, f[1]).to_vec();
+            self.check(!addresses.is_empty(), p.node(f[1]), "RFC 9582 4.3.2", "addresses must not be empty");
+            for address in addresses{
+                let a = children(&p.tree, address).to_vec();
+                let prefix = a.first().and_then(|b| resources::parse_prefix(&p.tree.tokens[b].data, bits));
+                let (addr, len) = match prefix{
+                    Some(pr) => pr,
+                    None => {
+                        self.fail(p.node(address), "RFC 9582 4.3.2.1", "Invalid prefix");
+                        continue;
+                    }
+                };
+
+                if let Some(max) = a.get(1){
+                    let ok = uint(&p.tree, *max).map(|m| m >= len as u128 && m <= bits as u128).unwrap_or(false);
+                    self.check(ok, p.node(*max), "RFC 9582 4.3.2.2", format!("maxLength must be between the prefix length {} and {}", len, bits));
+                }
+
+                if let Some((node, r)) = ee_ip{
+                    let ranges = r.iter().filter(|f| f.afi == afi).flat_map(|f| f.ranges.clone()).collect::<Vec<_>>();
+                    let ok = resources::covered(&ranges, resources::prefix_range(addr, len, bits));
+                    self.check(ok, p.node(address), "RFC 9582 5", format!("Prefix is not covered by the IP resources of the EE certificate (node {})", node));
+                }
+            }
+        }
+    }
+
+    fn manifest(&mut self, econtent: usize, ee: &CertInfo){
+        let p = match tree_util::payload(self.tree, econtent, self.overrides){
+            Some(p) => p,
+            None => return self.fail(econtent, "RFC 9286 4.2", "eContent is not a valid Manifest"),
+        };
+        let offset = self.default_version(&p, "RFC 9286 4.2.1");
+        let c = children(&p.tree, p.root).to_vec();
+        if c.len() != offset + 5{
+            return self.fail(econtent, "RFC 9286 4.2", "Manifest must contain manifestNumber, thisUpdate, nextUpdate, fileHashAlg and fileList");
+        }
+
+        let number = c[offset];
+        let ok = uint(&p.tree, number).is_some() && p.tree.tokens[&number].data.len() <= 20;
+        self.check(ok, p.node(number), "RFC 9286 4.2.1", "manifestNumber must be a non-negative integer of at most 20 octets");
+
+        let (this_update, next_update) = (c[offset + 1], c[offset + 2]);
+        for t in [this_update, next_update]{
+            self.check(tag(&p.tree, t) == 0x18, p.node(t), "RFC 9286 4.2.1", "thisUpdate and nextUpdate must be GeneralizedTime");
+        }
+        let from = time(&p.tree, this_update);
+        let until = time(&p.tree, next_update);
+        match (from, until){
+            (Some(f), Some(u)) => {
+                self.check(f < u, p.node(next_update), "RFC 9286 4.2.1", "nextUpdate must be after thisUpdate");
+                self.check(u >= self.now, p.node(next_update), "RFC 9286 6.3", "Manifest is stale");
+                if let (Some(nb), Some(na)) = (ee.not_before, ee.not_after){
+                    self.check(nb <= f && u <= na, p.node(this_update), "RFC 9286 5.1", "thisUpdate and nextUpdate must lie within the validity of the EE certificate");
+                }
+            }
+            _ => self.fail(p.node(this_update), "RFC 9286 4.2.1", "Invalid thisUpdate or nextUpdate"),
+        }
+
+        let hash_alg = c[offset + 3];
+        self.check(is_oid(&p.tree, hash_alg, cms::OID_SHA256), p.node(hash_alg), "RFC 9286 4.2.1", "fileHashAlg must be SHA-256");
+
+        let mut names: Vec<String> = vec![];
+        for entry in children(&p.tree, c[offset + 4]).to_vec(){
+            let e = children(&p.tree, entry).to_vec();
+            if e.len() != 2{
+                self.fail(p.node(entry), "RFC 9286 4.2.2", "FileAndHash must contain file and hash");
+                continue;
+            }
+            let name = String::from_utf8_lossy(&p.tree.tokens[&e[0]].data).to_string();
+            self.check(is_manifest_file_name(&name), p.node(e[0]), "RFC 9286 4.2.2", format!("Invalid file name {}", name));
+            if names.contains(&name){
+                self.fail(p.node(e[0]), "RFC 9286 4.2.2", format!("File {} is listed more than once", name));
+            }
+            names.push(name);
+
+            let hash = &p.tree.tokens[&e[1]].data;
+            let ok = tag(&p.tree, e[1]) == 0x03 && hash.len() == 33 && hash[0] == 0;
+            self.check(ok, p.node(e[1]), "RFC 9286 4.2.2", "hash must be a SHA-256 digest as BIT STRING");
+        }
+
+        self.inherited_resources(ee, "RFC 9286 5.1");
+    }
+
+    /// EE certificates of some objects must use inherit for all resources.
+    fn inherited_resources(&mut self, ee: &CertInfo, section: &str){
+        if let Some((node, r)) = ee.ip.as_ref(){
+            self.check(r.iter().all(|f| f.inherit), *node, section, "The EE certificate must use inherit for IP resources");
+        }
+        if let Some((node, r)) = ee.asn.as_ref(){
+            self.check(r.inherit, *node, section, "The EE certificate must use inherit for AS resources");
+        }
+    }
+
+    fn aspa(&mut self, econtent: usize, ee: &CertInfo){
+        let section = "draft-ietf-sidrops-aspa-profile 3";
+        let p = match tree_util::payload(self.tree, econtent, self.overrides){
+            Some(p) => p,
+            None => return self.fail(econtent, section, "eContent is not a valid ASProviderAttestation"),
+        };
+        let c = children(&p.tree, p.root).to_vec();
+
+        // The version is explicitly 1, it has no default
+        let version = c.first().filter(|v| tag(&p.tree, **v) == 0xA0).cloned();
+        let version_ok = version.and_then(|v| children(&p.tree, v).first().cloned()).and_then(|v| uint(&p.tree, v)) == Some(1);
+        self.check(version_ok, p.node(version.unwrap_or(p.root)), "draft-ietf-sidrops-aspa-profile 3.1", "Version must be present and 1");
+        let offset = if version.is_some() { 1 } else { 0 };
+
+        let customer = match c.get(offset).and_then(|a| uint(&p.tree, *a).map(|v| (*a, v))){
+            Some(a) => a,
+            None => return self.fail(econtent, "draft-ietf-sidrops-aspa-profile 3.2", "Missing or invalid customerASID"),
+        };
+
+        let providers = match c.get(offset + 1){
+            Some(pr) => *pr,
+            None => return self.fail(econtent, "draft-ietf-sidrops-aspa-profile 3.3", "Missing providers"),
+        };
+        let list = children(&p.tree, providers).to_vec();
+        self.check(!list.is_empty(), p.node(providers), "draft-ietf-sidrops-aspa-profile 3.3", "providers must not be empty");
+
+        let mut last: Option<u128> = None;
+        for pr in list{
+            let asn = match uint(&p.tree, pr){
+                Some(a) => a,
+                None => {
+                    self.fail(p.node(pr), "draft-ietf-sidrops-aspa-profile 3.3", "Invalid provider ASID");
+                    continue;
+                }
+            };
+            self.check(asn != customer.1, p.node(pr), "draft-ietf-sidrops-aspa-profile 3.3", "The customer must not be listed as provider");
+            if let Some(l) = last{
+                self.check(asn > l, p.node(pr), "draft-ietf-sidrops-aspa-profile 3.3", "Providers must be sorted ascending without duplicates");
+            }
+            last = Some(asn);
+        }
+
+        if let Some((node, _)) = ee.ip.as_ref(){
+            self.fail(*node, "draft-ietf-sidrops-aspa-profile 4", "The EE certificate must not contain IP resources");
+        }
+        match ee.asn.as_ref(){
+            Some((node, r)) => {
+                let ok = !r.inherit && r.ranges == [(customer.1, customer.1)];
+                self.check(ok, *node, "draft-ietf-sidrops-aspa-profile 4", "The AS resources of the EE certificate must be exactly the customerASID");
+            }
+            None => self.fail(p.node(customer.0), "draft-ietf-sidrops-aspa-profile 4", "The EE certificate has no AS resources"),
+        }
+    }
+
+    fn gbr(&mut self, econtent: usize){
+        let vcard = String::from_utf8_lossy(&tree_util::string_payload(self.tree, econtent, self.overrides)).to_string();
+        for (section, message) in gbr::vcard_issues(&vcard){
+            self.fail(econtent, section, message);
+        }
+    }
+
+    fn rsc(&mut self, econtent: usize, ee: &CertInfo, ee_id: usize){
+        let p = match tree_util::payload(self.tree, econtent, self.overrides){
+            Some(p) => p,
+            None => return self.fail(econtent, "RFC 9323 4", "eContent is not a valid RpkiSignedChecklist"),
+        };
+        let offset = self.default_version(&p, "RFC 9323 4.1");
+        let c = children(&p.tree, p.root).to_vec();
+        if c.len() != offset + 3{
+            return self.fail(econtent, "RFC 9323 4", "RpkiSignedChecklist must contain resources, digestAlgorithm and checkList");
+        }
+
+        let block = c[offset];
+        let asn = child_by_tag(&p.tree, block, 0xA0).and_then(|a| children(&p.tree, a).first().cloned());
+        let ip = child_by_tag(&p.tree, block, 0xA1).and_then(|a| children(&p.tree, a).first().cloned());
+        self.check(asn.is_some() || ip.is_some(), p.node(block), "RFC 9323 4.2", "resources must contain AS or IP resources");
+
+        if let Some(asn) = asn{
+            match resources::as_resources(&p.tree, asn){
+                Ok(r) => {
+                    let held = ee.asn.as_ref().filter(|(_, e)| !e.inherit).map(|(_, e)| e.ranges.clone()).unwrap_or_default();
+                    let ok = r.ranges.iter().all(|range| resources::covered(&held, *range));
+                    self.check(ok, p.node(asn), "RFC 9323 4.2", "AS resources are not covered by the EE certificate");
+                }
+                Err(e) => self.fail(p.node(asn), "RFC 9323 4.2", e),
+            }
+        }
+        if let Some(ip) = ip{
+            match resources::ip_resources(&p.tree, ip){
+                Ok(r) => {
+                    let held = ee.ip.as_ref().map(|(_, e)| e.clone()).unwrap_or_default();
+                    for family in r{
+                        let ranges = held.iter().filter(|f| f.afi == family.afi && !f.inherit).flat_map(|f| f.ranges.clone()).collect::<Vec<_>>();
+                        let ok = family.ranges.iter().all(|range| resources::covered(&ranges, *range));
+                        self.check(ok, p.node(ip), "RFC 9323 4.2", "IP resources are not covered by the EE certificate");
+                    }
+                }
+                Err(e) => self.fail(p.node(ip), "RFC 9323 4.2", e),
+            }
+        }
+
+        self.algorithm_in(&p, c[offset + 1], cms::OID_SHA256, "RFC 9323 4.3", "digestAlgorithm must be SHA-256");
+
+        let list = children(&p.tree, c[offset + 2]).to_vec();
+        self.check(!list.is_empty(), p.node(c[offset + 2]), "RFC 9323 4.4", "checkList must not be empty");
+        for entry in list{
+            let e = children(&p.tree, entry).to_vec();
+            let hash = e.iter().find(|n| tag(&p.tree, **n) == 0x04);
+            let ok = hash.map(|h| p.tree.tokens[h].data.len() == 32).unwrap_or(false);
+            self.check(ok, p.node(entry), "RFC 9323 4.4", "hash must be a SHA-256 digest");
+
+            if let Some(name) = e.iter().find(|n| tag(&p.tree, **n) == 0x16){
+                let name = String::from_utf8_lossy(&p.tree.tokens[name].data).to_string();
+                self.check(is_manifest_file_name(&name), p.node(entry), "RFC 9323 4.4", format!("Invalid file name {}", name));
+            }
+        }
+
+        self.check(ee.ip.is_some() || ee.asn.is_some(), ee_id, "RFC 9323 2.1", "The EE certificate must contain resources");
+    }
+
+    fn tak(&mut self, econtent: usize){
+        let p = match tree_util::payload(self.tree, econtent, self.overrides){
+            Some(p) => p,
+            None => return self.fail(econtent, "RFC 9691 3", "eContent is not a valid TAK"),
+        };
+        // The version is an untagged INTEGER DEFAULT 0, so any encoded version violates DER
+        if let Some(version) = children(&p.tree, p.root).first().filter(|v| tag(&p.tree, **v) == 0x02){
+            self.check(uint(&p.tree, *version) == Some(0), p.node(*version), "RFC 9691 3", "version must be 0");
+            self.fail(p.node(*version), "RFC 9691 3", "Version 0 is the default and must be omitted in DER (X.690 11.5)");
+        }
+
+        let content = match tak::parse_tak(&p, self.overrides){
+            Ok(c) => c,
+            Err(e) => return self.fail(econtent, "RFC 9691 3", e),
+        };
+        let keys = [Some(("current", content.current)), content.predecessor.map(|k| ("predecessor", k)), content.successor.map(|k| ("successor", k))];
+        for (name, key) in keys.into_iter().flatten(){
+            self.check(!key.certificate_uris.is_empty(), econtent, "RFC 9691 3", format!("certificateURIs of the {} key must not be empty", name));
+            for uri in key.certificate_uris.iter().filter(|u| !is_rsync_uri(u) && !u.starts_with("https://")){
+                self.fail(econtent, "RFC 9691 3", format!("{} is neither an rsync nor an HTTPS URI", uri));
+            }
+            let spki = base64::decode(&key.public_key).ok().and_then(|d| tree_util::parse_encapsulated(&d).ok());
+            let ok = spki.map(|t| children(&t, t.root_id).len() == 2).unwrap_or(false);
+            self.check(ok, econtent, "RFC 9691 3", format!("subjectPublicKeyInfo of the {} key is invalid", name));
+        }
+    }
+
+    fn algorithm_in(&mut self, p: &tree_util::Payload, id: usize, oid: &str, section: &str, message: &str){
+        let ok = children(&p.tree, id).first().map(|o| is_oid(&p.tree, *o, oid)).unwrap_or(false);
+        self.check(ok, p.node(id), section, message);
+    }
+}
+
+/// File names on manifests are restricted to letters, digits, '-' and '_' with a three letter extension.
+fn is_manifest_file_name(name: &str) -> bool{
+    match name.rsplit_once('.'){
+        Some((base, ext)) => {
+            !base.is_empty()
+                && base.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                && ext.len() == 3
+                && ext.chars().all(|c| c.is_ascii_lowercase())
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn violations(state: &State, time: Option<&str>) -> Vec<Violation>{
+        let report: ValidationReport = serde_json::from_str(&state.validate_rpki(time.map(str::to_string)).unwrap()).unwrap();
+        report.violations
+    }
+
+    fn has(violations: &[Violation], text: &str) -> bool{
+        violations.iter().any(|v| v.section.contains(text) || v.message.contains(text))
+    }
+
+    #[test]
+    fn object_types(){
+        for (example, name) in [("roa", "ROA"), ("mft", "Manifest"), ("crl", "CRL"), ("aspa", "ASPA"), ("rsc", "RSC"), ("tak", "TAK")]{
+            let report: ValidationReport = serde_json::from_str(&State::load_example(example).unwrap().validate_rpki(None).unwrap()).unwrap();
+            assert_eq!(report.object_type, name);
+        }
+    }
+
+    #[test]
+    fn message_digest(){
+        let mut state = State::load_example("roa").unwrap();
+        state.roa_set(r#"{"asn": 64496, "prefixes": [{"prefix": "192.0.2.0/24"}]}"#.to_string(), false).unwrap();
+        assert!(has(&violations(&state, None), "RFC 6488 2.1.6.4.2"));
+
+        state.resign("{}".to_string()).unwrap();
+        assert!(!has(&violations(&state, None), "RFC 6488 2.1.6.4.2"));
+    }
+
+    #[test]
+    fn reference_time(){
+        let state = State::load_example("roa").unwrap();
+        assert!(has(&violations(&state, Some("1990-01-01T00:00:00Z")), "Not yet valid"));
+        assert!(!has(&violations(&state, Some("1990-01-01T00:00:00Z")), "Expired"));
+        assert!(state.validate_rpki(Some("yesterday".to_string())).is_err());
+    }
+
+    #[test]
+    fn explicit_tak_version(){
+        let mut state = State::load_example("tak").unwrap();
+        assert!(!has(&violations(&state, None), "X.690 11.5"));
+
+        let econtent = state.signed_payload(cms::OID_CT_TAK, "TAK").unwrap();
+        let payload = tree_util::string_payload(&state.tree, econtent, &state.overrides);
+        let header = der::parse_header(&payload, 0).unwrap();
+        let mut tak = vec![0x02, 0x01, 0x00];
+        tak.extend(&payload[header.header_len..]);
+        tree_util::set_string_payload(&mut state.tree, econtent, &der::tlv(0x30, &tak)).unwrap();
+        assert!(has(&violations(&state, None), "X.690 11.5"));
+    }
+
+    #[test]
+    fn not_an_object(){
+        let state = State::from_tree(cure_asn1::interface::parse_tree(&[0x30, 0x03, 0x02, 0x01, 0x05], "").unwrap());
+        assert!(state.validate_rpki(None).is_err());
+    }
+}